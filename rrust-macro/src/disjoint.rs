@@ -0,0 +1,217 @@
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::visit::Visit;
+
+use crate::utils::{
+    address, flat_tokens, is_place, local_ident, parse_block, push_error, tuple_assign_ops,
+};
+
+/// The block of statements grouped by a `disjoint!` macro.
+pub fn disjoint_block(mac: &syn::Macro) -> syn::Result<syn::Block> {
+    let tokens = &mac.tokens;
+    parse_block(quote! {{ #tokens }})
+}
+
+/// The checks done once at the top of a `disjoint!` group, they panic
+/// if any two of the places used by the assignment operations in the
+/// group are aliases of each other. As the checks are done before the
+/// statements, the places can not depend on anything changed in the
+/// group.
+pub fn disjoint_guards(block: &syn::Block) -> syn::Result<Vec<syn::Stmt>> {
+    let mut visitor = PlaceVisitor::default();
+    visitor.visit_block(block);
+
+    for place in &visitor.places {
+        let mut indices = IndexVisitor::default();
+        indices.visit_expr(place);
+        for changed in &visitor.changed {
+            let needle = flat_tokens(changed.to_token_stream());
+            if indices.indices.iter().any(|index| {
+                flat_tokens(index.to_token_stream())
+                    .windows(needle.len())
+                    .any(|w| w == needle)
+            }) {
+                push_error(
+                    &mut visitor.errors,
+                    syn::Error::new_spanned(
+                        place,
+                        format!(
+                            "The places in disjoint! can not depend on `{}` as it is changed in the group",
+                            changed.to_token_stream()
+                        ),
+                    ),
+                );
+            }
+        }
+    }
+
+    if let Some(errors) = visitor.errors {
+        return Err(errors);
+    }
+
+    if cfg!(feature = "unchecked") {
+        return Ok(Vec::new());
+    }
+
+    // Locals of the group are not yet defined at the top, and they can
+    // not be aliases of the places from outside of it anyway.
+    let is_local = |place: &syn::Expr| match place {
+        syn::Expr::Path(p) => p
+            .path
+            .get_ident()
+            .is_some_and(|i| visitor.locals.contains(i)),
+        _ => false,
+    };
+
+    let mut places: Vec<&syn::Expr> = Vec::new();
+    for place in visitor.places.iter().filter(|p| !is_local(p)) {
+        let tokens = place.to_token_stream().to_string();
+        if !places
+            .iter()
+            .any(|p| p.to_token_stream().to_string() == tokens)
+        {
+            places.push(place);
+        }
+    }
+
+    let mut guards = Vec::new();
+    for (i, a) in places.iter().enumerate() {
+        for b in &places[i + 1..] {
+            let (a, b) = (address(a), address(b));
+            guards.push(syn::parse_quote! {
+                if core::ptr::eq(#a as *const _ as *const (), #b as *const _ as *const ()) {
+                    panic!("{}:{}: Places in disjoint! are aliases of each other", file!(), line!());
+                }
+            });
+        }
+    }
+    Ok(guards)
+}
+
+/// The checks done when a reversible function is entered, they panic
+/// if any two of its mutable slice arguments overlap. The borrow
+/// checker already rejects this in safe code, but slices created by
+/// unsafe code, like with `slice::from_raw_parts_mut`, are not seen by
+/// it. Only arguments of the type `&mut [T]` are checked.
+pub fn slice_guards_impl(input: TokenStream) -> syn::Result<TokenStream> {
+    let params = syn::parse::Parser::parse2(
+        syn::punctuated::Punctuated::<syn::FnArg, syn::Token![,]>::parse_terminated,
+        input,
+    )?;
+    if cfg!(feature = "unchecked") {
+        return Ok(TokenStream::new());
+    }
+
+    let slices: Vec<&syn::Pat> = params
+        .iter()
+        .filter_map(|param| match param {
+            syn::FnArg::Typed(pt) if is_mut_slice(&pt.ty) => Some(&*pt.pat),
+            _ => None,
+        })
+        .collect();
+
+    let mut guards = Vec::new();
+    for (i, a) in slices.iter().enumerate() {
+        for b in &slices[i + 1..] {
+            guards.push(quote! {
+                if ::rrust::slices_overlap(
+                    (#a.as_ptr() as usize, core::mem::size_of_val(&*#a)),
+                    (#b.as_ptr() as usize, core::mem::size_of_val(&*#b)),
+                ) {
+                    panic!("{}:{}: Slice arguments overlap each other", file!(), line!());
+                }
+            });
+        }
+    }
+    Ok(quote! { #(#guards)* })
+}
+
+/// Whether a type is a mutable slice reference, `&mut [T]`.
+fn is_mut_slice(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Group(g) => is_mut_slice(&g.elem),
+        syn::Type::Paren(p) => is_mut_slice(&p.elem),
+        syn::Type::Reference(r) => {
+            r.mutability.is_some() && matches!(&*r.elem, syn::Type::Slice(_))
+        }
+        _ => false,
+    }
+}
+
+/// Collects the places used by the assignment operations and what is
+/// changed by the statements, macros are not entered as they do their
+/// own checks.
+#[derive(Default)]
+struct PlaceVisitor {
+    places: Vec<syn::Expr>,
+    locals: Vec<syn::Ident>,
+    changed: Vec<syn::Expr>,
+    errors: Option<syn::Error>,
+}
+
+impl<'ast> Visit<'ast> for PlaceVisitor {
+    fn visit_local(&mut self, node: &'ast syn::Local) {
+        if let Ok(i) = local_ident(node) {
+            self.changed.push(syn::parse_quote! { #i });
+            self.locals.push(i);
+        }
+        syn::visit::visit_local(self, node);
+    }
+
+    fn visit_expr_assign_op(&mut self, node: &'ast syn::ExprAssignOp) {
+        let (left, right) = (&*node.left, &*node.right);
+        if let Some(ops) = tuple_assign_ops(left, &node.op, right) {
+            match ops {
+                Ok(ops) => ops.iter().for_each(|op| self.visit_expr(op)),
+                Err(e) => push_error(&mut self.errors, e),
+            }
+            return;
+        }
+        if let syn::Expr::Index(index) = left {
+            if matches!(&*index.index, syn::Expr::Range(_)) {
+                return;
+            }
+        }
+        if left.to_token_stream().to_string() == right.to_token_stream().to_string() {
+            push_error(
+                &mut self.errors,
+                syn::Error::new_spanned(node, "Lefthand and righthand are aliases of each other"),
+            );
+        }
+        self.places.push(left.clone());
+        if is_place(right) {
+            self.places.push(right.clone());
+        }
+        self.changed.push(left.clone());
+        syn::visit::visit_expr_assign_op(self, node);
+    }
+}
+
+/// Collects the index expressions of a place.
+#[derive(Default)]
+struct IndexVisitor<'ast> {
+    indices: Vec<&'ast syn::Expr>,
+}
+
+impl<'ast> Visit<'ast> for IndexVisitor<'ast> {
+    fn visit_expr_index(&mut self, node: &'ast syn::ExprIndex) {
+        self.indices.push(&node.index);
+        syn::visit::visit_expr_index(self, node);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::slice_guards_impl;
+    use quote::quote;
+
+    #[test]
+    fn slice_guards() {
+        let output = slice_guards_impl(quote! {
+            a: &mut [i32], n: &mut usize, b: &mut [u8], c: &[i32]
+        })
+        .unwrap();
+        let checks = if cfg!(feature = "unchecked") { 0 } else { 1 };
+        assert_eq!(output.to_string().matches("slices_overlap").count(), checks);
+    }
+}
@@ -1,94 +1,340 @@
 use proc_macro2::TokenStream;
-use quote::ToTokens;
+use quote::{quote, ToTokens};
 use syn::{fold::Fold, Token};
 
-use crate::utils::{delocal_ident, local_ident, macro_ident_expr};
+use crate::disjoint::{disjoint_block, disjoint_guards};
+use crate::utils::{
+    alias_guard, delocal_error, delocal_ident, delocal_self_reference, delocal_slot, delocal_use,
+    function_name, implicit_delocals, local_ident, local_pat, macro_args, macro_ident,
+    macro_ident_expr, mark_outer_locals, outer_locals, parse_block, push_error, range_assign_op,
+    remove_empty_stmts, rif_delocals, side_effect_macros, swap_guard, take_skip, tuple_assign_ops,
+    unconditional_recursion, use_slots, Place,
+};
 
-pub fn forward_impl(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let input = syn::parse_macro_input!(input);
+pub fn forward_impl(input: TokenStream) -> syn::Result<TokenStream> {
+    let (name, input) = function_name(input);
+    let input = parse_block(input)?;
 
     let mut visitor = FFolder::new();
+    if let Some(error) = name.and_then(|name| unconditional_recursion(&name, &input)) {
+        visitor.error(error);
+    }
     let block = visitor.fold_block(input);
 
     visitor.delocal_check();
 
+    if let Some(errors) = visitor.errors {
+        return Err(errors);
+    }
+
+    #[cfg(feature = "debug-expand")]
+    eprintln!("forward!\n{}", crate::utils::pretty_block(&block));
+
     let mut output = TokenStream::new();
 
     let brace = syn::token::Brace::default();
 
     brace.surround(&mut output, |output| block.to_tokens(output));
 
-    proc_macro::TokenStream::from(output)
+    Ok(output)
+}
+
+/// The `Stats` of the code, the operations are counted
+/// by the same walk that expands `forward!`. Errors in the code are
+/// left to be reported by `forward!` itself.
+pub fn stats_impl(input: TokenStream) -> syn::Result<TokenStream> {
+    let block = parse_block(input)?;
+
+    let mut visitor = FFolder::new();
+    visitor.fold_block(block);
+
+    let Counts {
+        adds,
+        subs,
+        xors,
+        calls,
+        loops,
+    } = visitor.counts;
+
+    Ok(quote! {
+        ::rrust::Stats {
+            adds: #adds,
+            subs: #subs,
+            xors: #xors,
+            calls: #calls,
+            loops: #loops,
+        }
+    })
+}
+
+#[derive(Default)]
+struct Counts {
+    adds: usize,
+    subs: usize,
+    xors: usize,
+    calls: usize,
+    loops: usize,
+}
+
+impl Counts {
+    fn add(&mut self, other: &Counts) {
+        self.adds += other.adds;
+        self.subs += other.subs;
+        self.xors += other.xors;
+        self.calls += other.calls;
+        self.loops += other.loops;
+    }
 }
 
 struct FFolder {
-    pub delocal_list: Vec<syn::Ident>,
+    pub delocal_list: Vec<Place>,
+    outer: Vec<syn::Ident>,
     level: u8,
+    disjoint: bool,
+    immutable: Vec<syn::Ident>,
+    counts: Counts,
+    errors: Option<syn::Error>,
 }
 
 impl FFolder {
     fn new() -> Self {
         FFolder {
             delocal_list: Vec::default(),
+            outer: Vec::default(),
             level: 0,
+            disjoint: false,
+            immutable: Vec::default(),
+            counts: Counts::default(),
+            errors: None,
         }
     }
 
-    fn fwd_stmt(&mut self, node: syn::Stmt) -> syn::Stmt {
+    fn error(&mut self, error: syn::Error) {
+        push_error(&mut self.errors, error);
+    }
+
+    fn fwd_stmt(&mut self, mut node: syn::Stmt) -> syn::Stmt {
+        if take_skip(&mut node) {
+            return node;
+        }
         match node {
             syn::Stmt::Local(l) => self.local(l),
-            syn::Stmt::Item(_) => todo!(),
+            syn::Stmt::Item(syn::Item::Macro(m))
+                if macro_ident(&m.mac.path).is_some_and(|i| i == "disjoint") =>
+            {
+                syn::Stmt::Expr(self.disjoint_group(&m.mac))
+            }
+            syn::Stmt::Item(i) => {
+                self.error(syn::Error::new_spanned(
+                    &i,
+                    "Items are not supported in reversible code",
+                ));
+                syn::Stmt::Item(i)
+            }
             syn::Stmt::Expr(e) => self.expr(e),
             syn::Stmt::Semi(e, s) => self.semi(e, s),
         }
     }
 
     fn local(&mut self, local: syn::Local) -> syn::Stmt {
-        let i = local_ident(&local);
-        self.delocal_list.push(i);
+        if let Some((_, init)) = &local.init {
+            self.count_only(init);
+        }
+        match local_ident(&local) {
+            Ok(i) => {
+                self.immutable.retain(|l| *l != i);
+                if matches!(local_pat(&local), syn::Pat::Ident(pi) if pi.mutability.is_none()) {
+                    self.immutable.push(i.clone());
+                }
+                self.delocal_list.push(Place::local(&i))
+            }
+            Err(e) => self.error(e),
+        }
         syn::Stmt::Local(local)
     }
 
+    /// Mutating a local that is not declared with `let mut` is an
+    /// error, this gives a better explanation than the compiler.
+    fn immutable_local(&mut self, expr: &syn::Expr) {
+        if let syn::Expr::AssignOp(syn::ExprAssignOp { left, .. }) = expr {
+            if let syn::Expr::Path(p) = &**left {
+                if let Some(i) = p.path.get_ident().filter(|i| self.immutable.contains(i)) {
+                    let error = syn::Error::new_spanned(
+                        left,
+                        format!(
+                            "`{}` is not mutable, a local changed by reversible code has to be declared with `let mut`",
+                            i
+                        ),
+                    );
+                    self.error(error);
+                }
+            }
+        }
+    }
+
     fn expr(&mut self, expr: syn::Expr) -> syn::Stmt {
-        self.delocal(&expr);
-        syn::Stmt::Expr(fwd_expr(self.fold_expr(expr)))
+        if let syn::Expr::Macro(m) = &expr {
+            if macro_ident(&m.mac.path).is_some_and(|i| i == "disjoint") {
+                return syn::Stmt::Expr(self.disjoint_group(&m.mac));
+            }
+        }
+        let expr = self.delocal(expr);
+        self.side_effects(&expr);
+        self.immutable_local(&expr);
+        syn::Stmt::Expr(fwd_expr(self.fold_expr(expr), self.disjoint))
     }
 
     fn semi(&mut self, expr: syn::Expr, semi: Token![;]) -> syn::Stmt {
-        self.delocal(&expr);
-        syn::Stmt::Semi(fwd_expr(self.fold_expr(expr)), semi)
+        if let syn::Expr::Macro(m) = &expr {
+            if macro_ident(&m.mac.path).is_some_and(|i| i == "disjoint") {
+                return syn::Stmt::Semi(self.disjoint_group(&m.mac), semi);
+            }
+        }
+        let expr = self.delocal(expr);
+        self.side_effects(&expr);
+        self.immutable_local(&expr);
+        syn::Stmt::Semi(fwd_expr(self.fold_expr(expr), self.disjoint), semi)
     }
 
-    fn delocal(&mut self, expr: &syn::Expr) {
-        if let Some(i) = macro_ident_expr(expr) {
-            let delocal: syn::Ident = syn::parse_quote! { delocal };
-            if i == delocal {
-                let di = delocal_ident(expr).unwrap();
+    /// A group of statements in `disjoint!`, the alias checks of its
+    /// assignment operations are done once at the top of the group.
+    fn disjoint_group(&mut self, mac: &syn::Macro) -> syn::Expr {
+        let block = match disjoint_block(mac) {
+            Ok(block) => block,
+            Err(e) => {
+                self.error(e);
+                return syn::Expr::Verbatim(mac.to_token_stream());
+            }
+        };
+        let guards = disjoint_guards(&block).unwrap_or_else(|e| {
+            self.error(e);
+            Vec::new()
+        });
+        let outer = std::mem::replace(&mut self.disjoint, true);
+        let block = self.fold_block(block);
+        self.disjoint = outer;
+        let path = &mac.path;
+        syn::parse_quote! {
+            #path! {
+                @group {
+                    #(#guards)*
+                    #block
+                }
+            }
+        }
+    }
+
+    fn count(&mut self, expr: &syn::Expr) {
+        match expr {
+            syn::Expr::AssignOp(a) => match a.op {
+                syn::BinOp::AddEq(_) => self.counts.adds += 1,
+                syn::BinOp::SubEq(_) => self.counts.subs += 1,
+                syn::BinOp::BitXorEq(_) => self.counts.xors += 1,
+                _ => {}
+            },
+            syn::Expr::Call(_) | syn::Expr::MethodCall(_) => self.counts.calls += 1,
+            syn::Expr::Macro(m) => {
+                if let Some(i) = macro_ident(&m.mac.path) {
+                    if i == "rloop" || i == "rfor" {
+                        self.counts.loops += 1;
+                    }
+                }
+                // The arguments of a macro are not parsed by syn, so
+                // look through them for the statements of nested blocks.
+                match macro_args(expr) {
+                    Ok(args) => args.iter().for_each(|arg| self.count_only(arg)),
+                    Err(_) => m
+                        .mac
+                        .tokens
+                        .clone()
+                        .into_iter()
+                        .filter_map(|token| match token {
+                            proc_macro2::TokenTree::Group(g)
+                                if g.delimiter() == proc_macro2::Delimiter::Brace =>
+                            {
+                                syn::parse2::<syn::Expr>(proc_macro2::TokenTree::Group(g).into())
+                                    .ok()
+                            }
+                            _ => None,
+                        })
+                        .for_each(|block| self.count_only(&block)),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Count the operations of an expression which is not expanded
+    /// here, like the arguments of a macro, the result of the walk is
+    /// thrown away.
+    fn count_only(&mut self, expr: &syn::Expr) {
+        let mut visitor = FFolder::new();
+        visitor.fold_expr(expr.clone());
+        self.counts.add(&visitor.counts);
+    }
+
+    fn side_effects(&mut self, expr: &syn::Expr) {
+        if let Some(error) = side_effect_macros(expr) {
+            self.error(error);
+        }
+    }
+
+    fn delocal(&mut self, expr: syn::Expr) -> syn::Expr {
+        if let Some(locals) = outer_locals(&expr) {
+            self.outer.extend(locals);
+        } else if let Some(i) = macro_ident_expr(&expr) {
+            if i == "delocal" {
+                if let Some(slot) = delocal_slot(&expr) {
+                    if let Some(error) = delocal_use(&mut self.delocal_list, &slot) {
+                        self.error(error);
+                    }
+                    return expr;
+                }
+                let di = match delocal_ident(&expr) {
+                    Ok(di) => di,
+                    Err(e) => {
+                        self.error(e);
+                        return expr;
+                    }
+                };
+                if let Some(error) = delocal_self_reference(&expr, &di) {
+                    self.error(error);
+                }
                 if let Some(index) = self.delocal_list.iter().position(|l| *l == di) {
                     self.delocal_list.remove(index);
+                } else if let Some(index) = self.outer.iter().position(|l| *l == di) {
+                    self.outer.remove(index);
                 } else {
-                    panic!("Attempt to delocal a non local variable: {}", di);
+                    self.error(syn::Error::new(
+                        di.span(),
+                        format!("Attempt to delocal a non local variable: {}", di),
+                    ));
+                }
+            } else if i == "rif" {
+                let (delocals, errors) = rif_delocals(&expr);
+                let marked: Vec<_> = delocals
+                    .into_iter()
+                    .filter(|l| self.delocal_list.iter().any(|p| p == l) || self.outer.contains(l))
+                    .collect();
+                self.delocal_list.retain(|p| !marked.iter().any(|l| p == l));
+                self.outer.retain(|l| !marked.contains(l));
+                if let Some(errors) = errors {
+                    self.error(errors);
                 }
+                return mark_outer_locals(expr, &marked);
             }
         }
+        expr
     }
 
-    fn delocal_check(&self) {
-        if !self.delocal_list.is_empty() {
-            let ident_list = self
-                .delocal_list
-                .iter()
-                .map(|i| i.to_string())
-                .collect::<Vec<_>>();
-            panic!(
-                "The following local(s) need to be consumed by delocal!: {:?}",
-                ident_list
-            );
+    fn delocal_check(&mut self) {
+        if let Some(error) = delocal_error(&self.delocal_list) {
+            self.error(error);
         }
     }
 }
 
-fn fwd_expr(expr: syn::Expr) -> syn::Expr {
+fn fwd_expr(expr: syn::Expr, disjoint: bool) -> syn::Expr {
     match expr {
         syn::Expr::AssignOp(syn::ExprAssignOp {
             attrs,
@@ -96,11 +342,21 @@ fn fwd_expr(expr: syn::Expr) -> syn::Expr {
             op,
             right,
         }) => {
-            let cmp: syn::Stmt = syn::parse_quote! {
-                if core::ptr::eq(&(#left), &(#right)) {
-                    panic!("{}:{}: Lefthand and righthand are aliases of each other", file!(), line!());
-                }
-            };
+            if let Some(lowered) = range_assign_op(&left, &op, &right) {
+                return lowered;
+            }
+
+            if let Some(ops) = tuple_assign_ops(&left, &op, &right) {
+                return match ops {
+                    Ok(ops) => {
+                        let ops = ops.into_iter().map(|op| fwd_expr(op, disjoint));
+                        syn::parse_quote! {{ #(#ops;)* }}
+                    }
+                    Err(e) => syn::Expr::Verbatim(e.to_compile_error()),
+                };
+            }
+
+            let cmp = alias_guard(&left, &right).filter(|_| !disjoint);
 
             let aop = syn::Expr::AssignOp(syn::ExprAssignOp {
                 attrs,
@@ -118,25 +374,374 @@ fn fwd_expr(expr: syn::Expr) -> syn::Expr {
             };
             syn::Expr::Block(block)
         }
+        syn::Expr::Call(call) => match swap_guard(&call) {
+            Some(cmp) => syn::Expr::Block(syn::parse_quote! {
+                {
+                    #cmp
+                    #call
+                }
+            }),
+            None => syn::Expr::Call(call),
+        },
         _ => expr,
     }
 }
 
 impl syn::fold::Fold for FFolder {
     fn fold_stmt(&mut self, node: syn::Stmt) -> syn::Stmt {
+        use_slots(&mut self.delocal_list, &node);
         self.fwd_stmt(node)
     }
 
+    fn fold_expr(&mut self, node: syn::Expr) -> syn::Expr {
+        self.count(&node);
+        syn::fold::fold_expr(self, node)
+    }
+
     fn fold_block(&mut self, mut block: syn::Block) -> syn::Block {
+        remove_empty_stmts(&mut block);
+        implicit_delocals(&mut block);
+
         let mut block_visitor = FFolder::new();
 
         block_visitor.level = self.level + 1;
+        block_visitor.disjoint = self.disjoint;
+        block_visitor.immutable = self.immutable.clone();
         block.stmts.iter_mut().for_each(|n| {
             *n = block_visitor.fold_stmt(n.clone());
         });
 
         block_visitor.delocal_check();
 
+        self.counts.add(&block_visitor.counts);
+        if let Some(errors) = block_visitor.errors {
+            self.error(errors);
+        }
+
         block
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{forward_impl, stats_impl};
+    use quote::quote;
+
+    #[test]
+    fn forward_assign_op() {
+        let output = forward_impl(quote! {{ *a += 1; *b -= *a; }}).unwrap();
+        let block: syn::Block = syn::parse2(output).unwrap();
+        assert_eq!(block.stmts.len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "unchecked")]
+    fn forward_unchecked() {
+        let output = forward_impl(quote! {{ *a += *c; std::mem::swap(a, c); }}).unwrap();
+        let block: syn::Block = syn::parse2(output).unwrap();
+        let expected: syn::Block = syn::parse_quote! {{
+            {
+                stringify!(*a, +=, *c);
+                *a += *c
+            };
+            std::mem::swap(a, c);
+        }};
+        match &block.stmts[..] {
+            [syn::Stmt::Expr(syn::Expr::Block(b))] => assert_eq!(b.block.stmts, expected.stmts),
+            _ => panic!("Expected a single block: {:?}", block),
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "unchecked"))]
+    fn forward_place_macro() {
+        let output = forward_impl(quote! {{ *a += place!(c.at_mut(0)); }}).unwrap();
+        let block: syn::Block = syn::parse2(output).unwrap();
+        let expected: syn::Stmt = syn::parse_quote! {
+            if core::ptr::eq(core::ptr::addr_of!(*a) as *const _ as *const (), &(place!(c.at_mut(0))) as *const _ as *const ()) {
+                panic!("{}:{}: Lefthand and righthand are aliases of each other", file!(), line!());
+            }
+        };
+        match &block.stmts[..] {
+            [syn::Stmt::Expr(syn::Expr::Block(b))] => match &b.block.stmts[..] {
+                [syn::Stmt::Semi(syn::Expr::Block(op), _)] => {
+                    assert_eq!(op.block.stmts[1], expected)
+                }
+                _ => panic!("Expected a single block: {:?}", b),
+            },
+            _ => panic!("Expected a single block: {:?}", block),
+        }
+    }
+
+    #[test]
+    fn forward_alias_guard_places() {
+        let checks = if cfg!(feature = "unchecked") { 0 } else { 1 };
+        // Any path can be a place, also one written like a constant
+        // such as an uppercase local or a `static mut`.
+        for input in [
+            quote! {{ *a += limit; }},
+            quote! {{ *a += LIMIT; }},
+            quote! {{ N ^= N; }},
+        ] {
+            let output = forward_impl(input).unwrap();
+            assert_eq!(output.to_string().matches("ptr :: eq").count(), checks);
+        }
+        for input in [quote! {{ *a += 1; }}, quote! {{ *a += LIMIT | 1; }}] {
+            let output = forward_impl(input).unwrap();
+            assert!(!output.to_string().contains("ptr :: eq"), "{}", output);
+        }
+    }
+
+    #[test]
+    fn forward_swap_guard() {
+        let checks = if cfg!(feature = "unchecked") { 0 } else { 1 };
+        for input in [
+            quote! {{ std::mem::swap(a, b); }},
+            quote! {{ core::mem::swap(a, b); }},
+            quote! {{ mem::swap(a, b); }},
+        ] {
+            let output = forward_impl(input).unwrap();
+            assert_eq!(output.to_string().matches("ptr :: eq").count(), checks);
+        }
+        let output =
+            forward_impl(quote! {{ a.swap(0, 1); other::swap(a, b); swap(i, j); }}).unwrap();
+        assert!(!output.to_string().contains("ptr :: eq"));
+    }
+
+    #[test]
+    fn forward_tuple_assign_op_order() {
+        let output = forward_impl(quote! {{ (*x, *y) -= (1, *x); }}).unwrap();
+        let output = output.to_string();
+        let (x, y) = (output.find("* x -= 1"), output.find("* y -= * x"));
+        assert!(x.is_some() && y < x, "{}", output);
+
+        // The error is left in the expanded code like other errors of
+        // tuple assignments.
+        let output = forward_impl(quote! {{ (*x, *y) += (*y, *x); }}).unwrap();
+        assert!(output.to_string().contains(
+            "The righthand sides of a tuple assignment read each other's lefthand sides, so it can not be reversed"
+        ));
+    }
+
+    #[test]
+    fn forward_range_assign_op() {
+        let output = forward_impl(quote! {{ arr[2..5] += *a; }}).unwrap();
+        let block: syn::Block = syn::parse2(output).unwrap();
+        match &block.stmts[..] {
+            [syn::Stmt::Expr(syn::Expr::Block(b))] => {
+                assert!(matches!(
+                    &b.block.stmts[..],
+                    [syn::Stmt::Semi(syn::Expr::ForLoop(_), _)]
+                ))
+            }
+            _ => panic!("Expected a single block: {:?}", block),
+        }
+    }
+
+    #[test]
+    fn forward_delocal() {
+        assert!(forward_impl(quote! {{ let mut i = 0; i += 1; delocal!(i, 1); }}).is_ok());
+    }
+
+    #[test]
+    fn forward_missing_delocal() {
+        let error = forward_impl(quote! {{ let mut i = 0; }}).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The following local(s) need to be consumed by delocal!: [\"i\"]"
+        );
+    }
+
+    #[test]
+    fn forward_implicit_delocal() {
+        let output = forward_impl(quote! {{ let i = -1; *a += i; }}).unwrap();
+        let block: syn::Block = syn::parse2(output).unwrap();
+        let expected: syn::Stmt = syn::parse_quote! {
+            ::rrust::delocal!(i, -1);
+        };
+        match &block.stmts[..] {
+            [syn::Stmt::Expr(syn::Expr::Block(b))] => {
+                assert_eq!(b.block.stmts.last(), Some(&expected))
+            }
+            _ => panic!("Expected a single block: {:?}", block),
+        }
+    }
+
+    #[test]
+    fn forward_delocal_non_local() {
+        let error = forward_impl(quote! {{ delocal!(i, 0); }}).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Attempt to delocal a non local variable: i"
+        );
+    }
+
+    #[test]
+    fn forward_delocal_slot() {
+        assert!(forward_impl(quote! {{
+            s.buf[k] += 1;
+            rfor!(l in 0..n, { m[i][j] += s.buf[k]; });
+            s.buf[k] -= 1;
+            delocal!(s.buf[k], 0);
+            delocal!(m[i][j], 0);
+        }})
+        .is_ok());
+
+        let error =
+            forward_impl(quote! {{ arr[k] += 1; delocal!(arr[k], 1); delocal!(arr[k], 1); }})
+                .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Attempt to delocal a slot which is not in use: arr[k]"
+        );
+
+        let error = forward_impl(quote! {{ arr[k] += 1; delocal!(arr[k + 1], 0); }}).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Attempt to delocal a slot which is not in use: arr[k+1]"
+        );
+    }
+
+    #[test]
+    fn forward_delocal_self_reference() {
+        let error =
+            forward_impl(quote! {{ let mut z = 0; z += 1; delocal!(z, z + 1); }}).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The value of delocal! can not refer to the local `z` itself"
+        );
+    }
+
+    #[test]
+    fn forward_rif_unbalanced_delocal() {
+        let error = forward_impl(quote! {{
+            let t = 0;
+            rif!(*x == 0, { delocal!(t, 0); }, { *x -= 1; }, *x == 1);
+        }})
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`t` is delocaled in the then branch of rif! but not in the else branch"
+        );
+    }
+
+    #[test]
+    fn forward_pure_macro() {
+        assert!(forward_impl(quote! {{ assert_eq!(*a, 1); *a += 1; }}).is_ok());
+    }
+
+    #[test]
+    fn forward_side_effect_macro() {
+        let error = forward_impl(quote! {{ println!("{}", a); }}).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`println!` has side effects which can not be reversed, mark the statement with #[rrust::skip] to allow it"
+        );
+        assert!(forward_impl(quote! {{ *a += vec![1][0]; }}).is_err());
+        assert!(forward_impl(quote! {{ std::println!("{}", a); }}).is_err());
+        assert!(forward_impl(quote! {{ #[rrust::skip] println!("{}", a); }}).is_ok());
+    }
+
+    #[test]
+    fn forward_comma_separated() {
+        let expected = forward_impl(quote! {{ *a += 1; *b -= *a; }}).unwrap();
+        for input in [
+            quote! {{ *a += 1, *b -= *a, }},
+            quote! {{ *a += 1; *b -= *a, }},
+            quote! {{ *a += 1;; *b -= *a; }},
+        ] {
+            let output = forward_impl(input).unwrap();
+            assert_eq!(output.to_string(), expected.to_string());
+        }
+    }
+
+    #[test]
+    fn forward_unconditional_recursion() {
+        let error =
+            forward_impl(quote! { Count, { *n -= 1; { Count::forward(n); } } }).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`Count::forward` is called unconditionally, so the recursion never terminates"
+        );
+        assert!(forward_impl(quote! { Count, {
+            rif!(*n == 0, {}, { *n -= 1; Count::forward(n); }, *n == 0);
+        }})
+        .is_ok());
+        assert!(forward_impl(quote! { Count, { Other::forward(n); } }).is_ok());
+        let error = forward_impl(quote! { Count, { *n -= 1; Self::forward(n); } }).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`Self::forward` is called unconditionally, so the recursion never terminates"
+        );
+    }
+
+    #[test]
+    fn forward_disjoint() {
+        let output = forward_impl(quote! {{
+            disjoint! { *a += *b; *b -= *c; *a ^= 1; }
+        }})
+        .unwrap();
+        let block: syn::Block = syn::parse2(output).unwrap();
+        let group = match &block.stmts[..] {
+            [syn::Stmt::Expr(syn::Expr::Block(b))] => match &b.block.stmts[..] {
+                [syn::Stmt::Item(syn::Item::Macro(m))] => m.mac.tokens.to_string(),
+                _ => panic!("Expected a disjoint! group: {:?}", b),
+            },
+            _ => panic!("Expected a single block: {:?}", block),
+        };
+        let checks = if cfg!(feature = "unchecked") { 0 } else { 3 };
+        assert_eq!(group.matches("ptr :: eq").count(), checks);
+    }
+
+    #[test]
+    fn forward_disjoint_dependent_index() {
+        let error = forward_impl(quote! {{
+            disjoint! { *i += 1; arr[*i] += *x; }
+        }})
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The places in disjoint! can not depend on `* i` as it is changed in the group"
+        );
+    }
+
+    #[test]
+    fn forward_immutable_local() {
+        let error = forward_impl(quote! {{ let i = 0; i -= 1; }}).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`i` is not mutable, a local changed by reversible code has to be declared with `let mut`"
+        );
+        assert!(
+            forward_impl(quote! {{ let i = 0; { let mut i = 1; i -= 1; delocal!(i, 0); } }})
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn forward_not_a_block() {
+        assert!(forward_impl(quote! { *a += 1 }).is_err());
+    }
+
+    #[test]
+    fn stats_count() {
+        let output = stats_impl(quote! {{
+            *a += 1;
+            let mut t = f(*a);
+            rloop!(*i == 0, { *i += 1; *a ^= *i; Step::forward(a); }, *i == 10);
+            rfor!(j in 0..3, { *a -= j; });
+            delocal!(t, f(*a));
+        }})
+        .unwrap();
+        let expected: syn::Expr = syn::parse_quote! {
+            ::rrust::Stats {
+                adds: 2usize,
+                subs: 1usize,
+                xors: 1usize,
+                calls: 3usize,
+                loops: 2usize,
+            }
+        };
+        assert_eq!(syn::parse2::<syn::Expr>(output).unwrap(), expected);
+    }
+}
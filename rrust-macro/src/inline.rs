@@ -0,0 +1,34 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// The function marked `#[inline(always)]` when the `inline` feature
+/// is enabled, otherwise the function as it is.
+pub fn maybe_inline_impl(input: TokenStream) -> syn::Result<TokenStream> {
+    let function: syn::ItemFn = syn::parse2(input)?;
+    if cfg!(feature = "inline") {
+        Ok(quote! {
+            #[inline(always)]
+            #function
+        })
+    } else {
+        Ok(quote! { #function })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::maybe_inline_impl;
+    use quote::quote;
+
+    #[test]
+    fn maybe_inline() {
+        let output = maybe_inline_impl(quote! { fn forward(a: &mut i32) { *a += 1; } }).unwrap();
+        let function: syn::ItemFn = syn::parse2(output).unwrap();
+        let inline: syn::Attribute = syn::parse_quote! { #[inline(always)] };
+        if cfg!(feature = "inline") {
+            assert_eq!(function.attrs, vec![inline]);
+        } else {
+            assert!(function.attrs.is_empty());
+        }
+    }
+}
@@ -1,13 +1,40 @@
+mod disjoint;
 mod forward;
+mod inline;
 mod reverse;
 mod utils;
 
 #[proc_macro]
 pub fn forward(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    forward::forward_impl(input)
+    forward::forward_impl(input.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
 }
 
 #[proc_macro]
 pub fn reverse(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    reverse::reverse_impl(input)
+    reverse::reverse_impl(input.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro]
+pub fn stats(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    forward::stats_impl(input.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro]
+pub fn maybe_inline(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    inline::maybe_inline_impl(input.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro]
+pub fn slice_guards(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    disjoint::slice_guards_impl(input.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
 }
@@ -1,29 +1,49 @@
 use proc_macro2::TokenStream;
 use quote::ToTokens;
-use syn::{fold::Fold, parse::Parser};
+use syn::fold::Fold;
 
-use crate::utils::{delocal_ident, local_ident, macro_ident_expr};
+use crate::disjoint::{disjoint_block, disjoint_guards};
+use crate::utils::{
+    alias_guard, delocal_error, delocal_ident, delocal_self_reference, delocal_slot, delocal_use,
+    implicit_delocals, local_ident, local_type, macro_args, macro_ident, macro_ident_expr,
+    mark_outer_locals, mutates, outer_locals, parse_block, push_error, range_assign_op,
+    remove_empty_stmts, rif_delocals, side_effect_macros, swap_guard, take_skip, tuple_assign_ops,
+    use_slots, Place,
+};
 
-pub fn reverse_impl(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let input = syn::parse_macro_input!(input);
+pub fn reverse_impl(input: TokenStream) -> syn::Result<TokenStream> {
+    let input = parse_block(input)?;
 
     let mut visitor = RFolder::new();
     let block = visitor.fold_block(input);
 
     visitor.delocal_check();
 
+    if let Some(errors) = visitor.errors {
+        return Err(errors);
+    }
+
+    #[cfg(feature = "debug-expand")]
+    eprintln!("reverse!\n{}", crate::utils::pretty_block(&block));
+
     let mut output = TokenStream::new();
 
     let brace = syn::token::Brace::default();
 
     brace.surround(&mut output, |output| block.to_tokens(output));
 
-    proc_macro::TokenStream::from(output)
+    Ok(output)
 }
 
 #[derive(Default)]
 struct RFolder {
-    pub delocal_list: Vec<syn::Ident>,
+    pub delocal_list: Vec<Place>,
+    outer: Vec<syn::Ident>,
+    hoisted: Vec<syn::Stmt>,
+    types: Vec<(syn::Ident, syn::Type)>,
+    disjoint: bool,
+    inverses: Vec<(BinOp, BinOp)>,
+    errors: Option<syn::Error>,
 }
 
 impl RFolder {
@@ -31,19 +51,67 @@ impl RFolder {
         RFolder::default()
     }
 
-    fn reverse_stmt(&mut self, node: syn::Stmt) -> syn::Stmt {
+    fn error(&mut self, error: syn::Error) {
+        push_error(&mut self.errors, error);
+    }
+
+    fn reverse_stmt(&mut self, mut node: syn::Stmt) -> syn::Stmt {
+        if take_skip(&mut node) {
+            return node;
+        }
         match node {
             syn::Stmt::Local(l) => self.local(l),
-            syn::Stmt::Item(_) => panic!("Not yet implemented: Stmt::Item"),
+            syn::Stmt::Item(syn::Item::Macro(m))
+                if macro_ident(&m.mac.path).is_some_and(|i| i == "disjoint") =>
+            {
+                syn::Stmt::Expr(self.disjoint_group(&m.mac))
+            }
+            syn::Stmt::Item(i) => {
+                self.error(syn::Error::new_spanned(
+                    &i,
+                    "Items are not supported in reversible code",
+                ));
+                syn::Stmt::Item(i)
+            }
             syn::Stmt::Expr(e) => self.expr(e),
             syn::Stmt::Semi(e, s) => self.semi(e, s),
         }
     }
 
+    /// Register the operators of an `inverse_ops!` as inverses of each
+    /// other for the rest of the block.
+    fn inverse_ops(&mut self, expr: &syn::Expr) {
+        match inverse_ops_pair(expr) {
+            Ok((op, inverse)) => {
+                self.inverses.push((op, inverse));
+                self.inverses.push((inverse, op));
+            }
+            Err(e) => self.error(e),
+        }
+    }
+
     fn local(&mut self, local: syn::Local) -> syn::Stmt {
-        let i = local_ident(&local);
-        let expr = local.init.unwrap().1;
-        self.delocal_list.push(i.clone());
+        let i = match local_ident(&local) {
+            Ok(i) => i,
+            Err(e) => {
+                self.error(e);
+                return syn::Stmt::Local(local);
+            }
+        };
+        let expr = match &local.init {
+            Some((_, expr)) => expr,
+            None => {
+                self.error(syn::Error::new_spanned(
+                    &local,
+                    "Locals in reversible code have to be initialized",
+                ));
+                return syn::Stmt::Local(local);
+            }
+        };
+        if let Some(ty) = local_type(&local) {
+            self.types.push((i.clone(), ty.clone()));
+        }
+        self.delocal_list.push(Place::local(&i));
         let m: syn::Stmt = syn::parse_quote! {
             ::rrust::delocal!(#i, #expr);
         };
@@ -51,144 +119,406 @@ impl RFolder {
     }
 
     fn expr(&mut self, expr: syn::Expr) -> syn::Stmt {
-        let (b, expr) = self.delocal(expr);
-        if b {
-            syn::Stmt::Expr(expr)
-        } else {
-            syn::Stmt::Expr(reverse_expr(self.fold_expr(expr)))
-        }
+        syn::Stmt::Expr(self.reverse(expr))
     }
 
     fn semi(&mut self, expr: syn::Expr, semi: syn::Token![;]) -> syn::Stmt {
+        syn::Stmt::Semi(self.reverse(expr), semi)
+    }
+
+    fn reverse(&mut self, expr: syn::Expr) -> syn::Expr {
+        if let syn::Expr::Macro(m) = &expr {
+            match macro_ident(&m.mac.path) {
+                Some(i) if i == "disjoint" => return self.disjoint_group(&m.mac),
+                Some(i) if i == "inverse_ops" => {
+                    self.inverse_ops(&expr);
+                    return expr;
+                }
+                _ => {}
+            }
+        }
         let (b, expr) = self.delocal(expr);
         if b {
-            syn::Stmt::Semi(expr, semi)
-        } else {
-            syn::Stmt::Semi(reverse_expr(self.fold_expr(expr)), semi)
+            return expr;
+        }
+        if let Some(error) = side_effect_macros(&expr) {
+            self.error(error);
+        }
+        let folded = self.fold_expr(expr);
+        match reverse_expr(folded, self.disjoint, &self.inverses) {
+            Ok(expr) => expr,
+            Err(e) => {
+                self.error(e);
+                syn::Expr::Verbatim(TokenStream::new())
+            }
+        }
+    }
+
+    /// A group of statements in `disjoint!`, the alias checks of its
+    /// assignment operations are done once at the top of the group.
+    fn disjoint_group(&mut self, mac: &syn::Macro) -> syn::Expr {
+        let block = match disjoint_block(mac) {
+            Ok(block) => block,
+            Err(e) => {
+                self.error(e);
+                return syn::Expr::Verbatim(mac.to_token_stream());
+            }
+        };
+        let guards = disjoint_guards(&block).unwrap_or_else(|e| {
+            self.error(e);
+            Vec::new()
+        });
+        let outer = std::mem::replace(&mut self.disjoint, true);
+        let block = self.fold_block(block);
+        self.disjoint = outer;
+        let path = &mac.path;
+        syn::parse_quote! {
+            #path! {
+                @group {
+                    #(#guards)*
+                    #block
+                }
+            }
         }
     }
 
     fn delocal(&mut self, expr: syn::Expr) -> (bool, syn::Expr) {
+        if let Some(locals) = outer_locals(&expr) {
+            self.outer.extend(locals);
+            return (true, expr);
+        }
         if let Some(i) = macro_ident_expr(&expr) {
-            let delocal: syn::Ident = syn::parse_quote! { delocal };
-            if i == delocal {
-                let di = delocal_ident(&expr).unwrap();
+            if i == "delocal" {
+                if let Some(slot) = delocal_slot(&expr) {
+                    if let Some(error) = delocal_use(&mut self.delocal_list, &slot) {
+                        self.error(error);
+                    }
+                    return (true, expr);
+                }
+                let di = match delocal_ident(&expr) {
+                    Ok(di) => di,
+                    Err(e) => {
+                        self.error(e);
+                        return (true, expr);
+                    }
+                };
+                if let Some(error) = delocal_self_reference(&expr, &di) {
+                    self.error(error);
+                }
                 if let Some(index) = self.delocal_list.iter().position(|l| *l == di) {
                     self.delocal_list.remove(index);
-                    return (true, delocal_val(expr));
+                    let ty = self.types.iter().rev().find(|(l, _)| *l == di);
+                    match delocal_val(&expr, ty.map(|(_, ty)| ty)) {
+                        Ok(local) => return (true, local),
+                        Err(e) => self.error(e),
+                    }
+                } else if let Some(index) = self.outer.iter().position(|l| *l == di) {
+                    // The local is declared before the `rif!`, so it is
+                    // only assigned here.
+                    self.outer.remove(index);
+                    match delocal_assign(&expr) {
+                        Ok(assign) => return (true, assign),
+                        Err(e) => self.error(e),
+                    }
                 } else {
-                    panic!("Attempt to delocal a non local variable: {}", di);
+                    self.error(syn::Error::new(
+                        di.span(),
+                        format!("Attempt to delocal a non local variable: {}", di),
+                    ));
+                }
+                return (true, expr);
+            } else if i == "rif" {
+                let (delocals, errors) = rif_delocals(&expr);
+                let marked: Vec<_> = delocals
+                    .into_iter()
+                    .filter(|l| self.delocal_list.iter().any(|p| p == l) || self.outer.contains(l))
+                    .collect();
+                // A local of this block is initialized in the branches
+                // in reverse, so it is declared before the `rif!`.
+                for l in marked
+                    .iter()
+                    .filter(|l| self.delocal_list.iter().any(|p| p == *l))
+                {
+                    let ty = self.types.iter().rev().find(|(t, _)| t == l);
+                    let ty = ty.map(|(_, ty)| quote::quote! { : #ty });
+                    self.hoisted.push(syn::parse_quote! {
+                        #[allow(unused_mut)]
+                        let mut #l #ty;
+                    });
+                }
+                self.delocal_list.retain(|p| !marked.iter().any(|l| p == l));
+                self.outer.retain(|l| !marked.contains(l));
+                if let Some(errors) = errors {
+                    self.error(errors);
                 }
+                return (false, mark_outer_locals(expr, &marked));
             }
         }
         (false, expr)
     }
 
-    fn delocal_check(&self) {
-        if !self.delocal_list.is_empty() {
-            let ident_list = self
-                .delocal_list
-                .iter()
-                .map(|i| i.to_string())
-                .collect::<Vec<_>>();
-            panic!(
-                "The following local(s) need to be consumed by delocal!: {:?}",
-                ident_list
-            );
+    fn delocal_check(&mut self) {
+        if let Some(error) = delocal_error(&self.delocal_list) {
+            self.error(error);
         }
     }
 }
 
 impl Fold for RFolder {
     fn fold_stmt(&mut self, node: syn::Stmt) -> syn::Stmt {
+        use_slots(&mut self.delocal_list, &node);
         self.reverse_stmt(node)
     }
 
     fn fold_block(&mut self, mut block: syn::Block) -> syn::Block {
+        remove_empty_stmts(&mut block);
+        implicit_delocals(&mut block);
+
         let mut block_visitor = RFolder::new();
+        block_visitor.disjoint = self.disjoint;
+        block_visitor.inverses = self.inverses.clone();
 
-        block.stmts.iter_mut().for_each(|n| {
-            *n = block_visitor.fold_stmt(n.clone());
-        });
+        let mut stmts = Vec::new();
+        for stmt in block.stmts {
+            stmts.push(block_visitor.fold_stmt(stmt));
+            stmts.append(&mut block_visitor.hoisted);
+        }
+        block.stmts = stmts;
         block.stmts.reverse();
 
         block_visitor.delocal_check();
 
+        if let Some(errors) = block_visitor.errors {
+            self.error(errors);
+        }
+
         block
     }
 }
 
-pub fn delocal_val(expr: syn::Expr) -> syn::Expr {
-    let punct: syn::punctuated::Punctuated<syn::Expr, syn::Token![,]> = match expr {
-        syn::Expr::Macro(syn::ExprMacro { attrs: _, mac }) => {
-            (|input: &syn::parse::ParseBuffer| syn::punctuated::Punctuated::parse_terminated(input))
-                .parse2(mac.tokens)
-                .unwrap()
-        }
-        _ => panic!(),
-    };
-    let name = punct.first().unwrap();
-    let val = punct.last().unwrap();
-    syn::parse_quote! {
-        let mut #name = #val
+/// The assignment a `delocal!` of a local declared outside of a `rif!`
+/// is reversed into.
+pub fn delocal_assign(expr: &syn::Expr) -> syn::Result<syn::Expr> {
+    let punct = macro_args(expr)?;
+    match (punct.first(), punct.last()) {
+        (Some(name), Some(val)) if punct.len() == 2 => Ok(syn::parse_quote! {
+            #name = #val
+        }),
+        _ => Err(syn::Error::new_spanned(
+            expr,
+            "delocal! expects a local and its value",
+        )),
     }
 }
 
+/// The local a `delocal!` is reversed into, with the type the local was
+/// declared with.
+pub fn delocal_val(expr: &syn::Expr, ty: Option<&syn::Type>) -> syn::Result<syn::Expr> {
+    let punct = macro_args(expr)?;
+    match (punct.first(), punct.last()) {
+        // The pattern of a `let` expression can not have a type.
+        (Some(name), Some(val)) if punct.len() == 2 => Ok(match ty {
+            Some(ty) => syn::Expr::Verbatim(quote::quote! {
+                let mut #name: #ty = #val
+            }),
+            None => syn::parse_quote! {
+                let mut #name = #val
+            },
+        }),
+        _ => Err(syn::Error::new_spanned(
+            expr,
+            "delocal! expects a local and its value",
+        )),
+    }
+}
+
+use syn::punctuated::Punctuated;
 use syn::{BinOp, Expr, ExprAssignOp, ExprMacro};
 
-fn reverse_bin_op(bin_op: BinOp) -> BinOp {
+/// Macros which are reversed by calling another macro, the first
+/// element is the name used in reversible code and the second is the
+/// macro in `rrust` it is replaced with in reverse. Any macro not in
+/// this list is left as it is.
+const MACRO_INVERSES: &[(&str, &str)] = &[
+    ("rif", "_reverse_rif"),
+    ("rloop", "_reverse_rloop"),
+    ("rfor", "_reverse_rfor"),
+    ("reverse_order", "_reverse_reverse_order"),
+    ("transpose", "transpose"),
+    ("add_mod", "sub_mod"),
+    ("sub_mod", "add_mod"),
+    ("rotate_ring", "_reverse_rotate_ring"),
+    ("negate_if", "negate_if"),
+    ("gray_inc", "gray_dec"),
+    ("gray_dec", "gray_inc"),
+    ("to_digits", "_reverse_to_digits"),
+    ("reverse_links", "reverse_links"),
+    ("add_carry", "sub_borrow"),
+    ("sub_borrow", "add_carry"),
+    ("masked_add", "_reverse_masked_add"),
+    ("feistel_round", "_reverse_feistel_round"),
+    ("counting_distribute", "_reverse_counting_distribute"),
+    ("horner", "_reverse_horner"),
+    ("isqrt_into", "_reverse_isqrt_into"),
+    ("checksum_frame", "_reverse_checksum_frame"),
+    ("rle_encode", "rle_decode"),
+    ("rle_decode", "rle_encode"),
+    ("bit_reverse_permute", "bit_reverse_permute"),
+    ("cell_add", "_reverse_cell_add"),
+    ("spush", "spop"),
+    ("spop", "spush"),
+    ("butterfly", "_reverse_butterfly"),
+    ("mul_mod_unit", "_reverse_mul_mod_unit"),
+    ("toggle", "toggle"),
+    ("gcd_reversible", "_reverse_gcd_reversible"),
+    ("sift_up", "_reverse_sift_up"),
+    ("with_scratch", "_reverse_with_scratch"),
+    ("pack_nibbles", "unpack_nibbles"),
+    ("unpack_nibbles", "pack_nibbles"),
+    ("to_bcd", "_reverse_to_bcd"),
+    ("sorted_insert", "_reverse_sorted_insert"),
+    ("crc_step", "_reverse_crc_step"),
+    ("cmul_unit", "_reverse_cmul_unit"),
+    ("debruijn_step", "_reverse_debruijn_step"),
+    ("atomic_add", "_reverse_atomic_add"),
+    ("bwt_forward", "bwt_backward"),
+    ("bwt_backward", "bwt_forward"),
+    ("affine", "_reverse_affine"),
+    ("interleave", "deinterleave"),
+    ("deinterleave", "interleave"),
+    ("toggle_case", "toggle_case"),
+    ("moving_avg_step", "_reverse_moving_avg_step"),
+    ("bucket_consume", "_reverse_bucket_consume"),
+    ("xor_list_advance", "xor_list_retreat"),
+    ("xor_list_retreat", "xor_list_advance"),
+    ("apply_lut", "_reverse_apply_lut"),
+    ("syndrome", "syndrome"),
+    ("zobrist_toggle", "zobrist_toggle"),
+    ("arith_encode_step", "arith_decode_step"),
+    ("arith_decode_step", "arith_encode_step"),
+    ("rmatch", "_reverse_rmatch"),
+    ("lifting_predict", "_reverse_lifting_predict"),
+    ("lifting_update", "_reverse_lifting_update"),
+    ("apply_perm", "_reverse_apply_perm"),
+    ("window_max_step", "_reverse_window_max_step"),
+    ("relax", "_reverse_relax"),
+    ("mul_accumulate", "_reverse_mul_accumulate"),
+    ("nonzero_add", "nonzero_sub"),
+    ("nonzero_sub", "nonzero_add"),
+    ("segtree_update", "_reverse_segtree_update"),
+    ("digit_rotate", "_reverse_digit_rotate"),
+    ("wide_add", "_reverse_wide_add"),
+    ("counting_bloom_add", "_reverse_counting_bloom_add"),
+    ("rng_next", "rng_prev"),
+    ("rng_prev", "rng_next"),
+];
+
+fn macro_inverse(ident: &syn::Ident) -> Option<syn::Path> {
+    MACRO_INVERSES
+        .iter()
+        .find(|(name, _)| ident == name)
+        .map(|(_, inverse)| {
+            let inverse = syn::Ident::new(inverse, ident.span());
+            syn::parse_quote! { ::rrust::#inverse }
+        })
+}
+
+/// The operators of an `inverse_ops!`.
+fn inverse_ops_pair(expr: &Expr) -> syn::Result<(BinOp, BinOp)> {
+    let error = || {
+        syn::Error::new_spanned(
+            expr,
+            "inverse_ops! expects an assignment operator and its inverse, like `*=, /=`",
+        )
+    };
+    let mac = match expr {
+        Expr::Macro(m) => &m.mac,
+        _ => return Err(error()),
+    };
+    let ops = mac
+        .parse_body_with(Punctuated::<BinOp, syn::Token![,]>::parse_terminated)
+        .map_err(|_| error())?;
+    match ops.iter().collect::<Vec<_>>()[..] {
+        [op, inverse] => Ok((*op, *inverse)),
+        _ => Err(error()),
+    }
+}
+
+fn reverse_bin_op(bin_op: BinOp, inverses: &[(BinOp, BinOp)]) -> syn::Result<BinOp> {
+    if let Some((_, inverse)) = inverses.iter().find(|(op, _)| *op == bin_op) {
+        return Ok(*inverse);
+    }
     match bin_op {
-        BinOp::Add(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::Sub(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::Mul(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::Div(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::Rem(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::And(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::Or(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::BitXor(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::BitAnd(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::BitOr(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::Shl(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::Shr(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::Eq(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::Lt(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::Le(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::Ne(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::Ge(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::Gt(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::AddEq(_) => BinOp::SubEq(syn::token::SubEq::default()),
-        BinOp::SubEq(_) => BinOp::AddEq(syn::token::AddEq::default()),
-        BinOp::MulEq(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::DivEq(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::RemEq(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::BitXorEq(x) => BinOp::BitXorEq(x),
-        BinOp::BitAndEq(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::BitOrEq(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::ShlEq(_) => panic!("disallowed binary operator. {}", line!()),
-        BinOp::ShrEq(_) => panic!("disallowed binary operator. {}", line!()),
+        BinOp::Add(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::Sub(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::Mul(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::Div(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::Rem(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::And(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::Or(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::BitXor(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::BitAnd(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::BitOr(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::Shl(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::Shr(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::Eq(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::Lt(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::Le(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::Ne(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::Ge(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::Gt(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::AddEq(_) => Ok(BinOp::SubEq(syn::token::SubEq::default())),
+        BinOp::SubEq(_) => Ok(BinOp::AddEq(syn::token::AddEq::default())),
+        BinOp::MulEq(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::DivEq(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::RemEq(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::BitXorEq(x) => Ok(BinOp::BitXorEq(x)),
+        BinOp::BitAndEq(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::BitOrEq(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::ShlEq(_) => Err(disallowed_bin_op(&bin_op)),
+        BinOp::ShrEq(_) => Err(disallowed_bin_op(&bin_op)),
     }
 }
 
-fn reverse_expr(e: Expr) -> Expr {
+fn disallowed_bin_op(bin_op: &BinOp) -> syn::Error {
+    syn::Error::new_spanned(bin_op, "disallowed binary operator")
+}
+
+fn not_implemented(e: &Expr) -> syn::Error {
+    syn::Error::new_spanned(e, "Not yet implemented in reversible code")
+}
+
+fn reverse_expr(e: Expr, disjoint: bool, inverses: &[(BinOp, BinOp)]) -> syn::Result<Expr> {
     match e {
-        Expr::Array(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Assign(_) => panic!("Not yet implemented {}", line!()),
+        Expr::Array(_) => Err(not_implemented(&e)),
+        Expr::Assign(_) => Err(not_implemented(&e)),
         Expr::AssignOp(ExprAssignOp {
             attrs,
             left,
             op,
             right,
         }) => {
-            let cmp: syn::Stmt = syn::parse_quote! {
-                if core::ptr::eq(&(#left), &(#right)) {
-                    panic!("{}:{}: Lefthand and righthand are aliases of each other", file!(), line!());
-                }
-            };
+            if let Some(ops) = tuple_assign_ops(&left, &op, &right) {
+                let ops = ops?
+                    .into_iter()
+                    .rev()
+                    .map(|op| reverse_expr(op, disjoint, inverses))
+                    .collect::<syn::Result<Vec<_>>>()?;
+                return Ok(syn::parse_quote! {{ #(#ops;)* }});
+            }
+
+            let op = reverse_bin_op(op, inverses)?;
+
+            if let Some(lowered) = range_assign_op(&left, &op, &right) {
+                return Ok(lowered);
+            }
+
+            let cmp = alias_guard(&left, &right).filter(|_| !disjoint);
 
             let aop = Expr::AssignOp(ExprAssignOp {
                 attrs,
                 left,
-                op: reverse_bin_op(op),
+                op,
                 right,
             });
 
@@ -198,75 +528,420 @@ fn reverse_expr(e: Expr) -> Expr {
                     #aop
                 }
             };
-            Expr::Block(block)
+            Ok(Expr::Block(block))
         }
-        Expr::Async(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Await(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Binary(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Block(b) => syn::Expr::Block(b),
-        Expr::Box(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Break(_) => panic!("Not yet implemented {}", line!()),
+        Expr::Async(_) => Err(not_implemented(&e)),
+        Expr::Await(mut a) => {
+            // Awaiting is the same in both directions, the future has to
+            // be pure, but a call of a reversible function is reversed.
+            if let Expr::Call(_) = &*a.base {
+                a.base = Box::new(reverse_expr(*a.base, disjoint, inverses)?);
+            }
+            Ok(Expr::Await(a))
+        }
+        Expr::Binary(_) => Err(not_implemented(&e)),
+        Expr::Block(b) => Ok(syn::Expr::Block(b)),
+        Expr::Box(_) => Err(not_implemented(&e)),
+        Expr::Break(_) => Err(not_implemented(&e)),
         Expr::Call(mut c) => {
             let func = *c.func.clone();
             if let Expr::Path(mut f) = func {
                 if let Some(last) = f.path.segments.pop() {
                     let forward: syn::PathSegment = syn::parse_quote! { forward };
-                    if last.value().clone() == forward {
-                        let backwards: syn::PathSegment = syn::parse_quote! { backwards };
+                    let backwards: syn::PathSegment = syn::parse_quote! { backwards };
+                    if *last.value() == forward {
                         f.path.segments.push(backwards);
                         c.func = Box::new(Expr::Path(f));
+                    } else if *last.value() == backwards {
+                        f.path.segments.push(forward);
+                        c.func = Box::new(Expr::Path(f));
                     }
                 }
             }
-            Expr::Call(c)
+            match swap_guard(&c) {
+                Some(cmp) => Ok(Expr::Block(syn::parse_quote! {
+                    {
+                        #cmp
+                        #c
+                    }
+                })),
+                None => Ok(Expr::Call(c)),
+            }
         }
-        Expr::Cast(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Closure(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Continue(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Field(_) => panic!("Not yet implemented {}", line!()),
-        Expr::ForLoop(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Group(_) => panic!("Not yet implemented {}", line!()),
-        Expr::If(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Index(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Let(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Lit(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Loop(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Macro(ExprMacro { attrs, mac }) => {
-            let mut cmac = mac.clone();
-            if let Some(i) = mac.path.get_ident() {
-                let rif: syn::Ident = syn::parse_quote! { rif };
-                let rloop: syn::Ident = syn::parse_quote! { rloop };
-                let ic = i.clone();
-                if ic == rif {
-                    let t: syn::Path = syn::parse_quote! { ::rrust::_reverse_rif };
-                    cmac.path = t;
-                } else if ic == rloop {
-                    let t: syn::Path = syn::parse_quote! { ::rrust::_reverse_rloop };
-                    cmac.path = t;
-                }
-                Expr::Macro(ExprMacro { attrs, mac: cmac })
+        Expr::Cast(_) => Err(not_implemented(&e)),
+        Expr::Closure(_) => Err(not_implemented(&e)),
+        Expr::Continue(_) => Err(not_implemented(&e)),
+        Expr::Field(_) => Err(not_implemented(&e)),
+        Expr::ForLoop(_) => Err(not_implemented(&e)),
+        Expr::Group(_) => Err(not_implemented(&e)),
+        Expr::If(_) => Err(not_implemented(&e)),
+        Expr::Index(_) => Err(not_implemented(&e)),
+        Expr::Let(_) => Err(not_implemented(&e)),
+        Expr::Lit(_) => Err(not_implemented(&e)),
+        Expr::Loop(_) => Err(not_implemented(&e)),
+        Expr::Macro(ExprMacro { attrs, mut mac }) => {
+            if let Some(inverse) = macro_ident(&mac.path).and_then(|i| macro_inverse(&i)) {
+                mac.path = inverse;
+            }
+            Ok(Expr::Macro(ExprMacro { attrs, mac }))
+        }
+        Expr::Match(_) => Err(not_implemented(&e)),
+        Expr::MethodCall(_) => Err(not_implemented(&e)),
+        Expr::Paren(_) => Err(not_implemented(&e)),
+        Expr::Path(_) => Err(not_implemented(&e)),
+        Expr::Range(r) => {
+            if mutates(&Expr::Range(r.clone())) {
+                Err(syn::Error::new_spanned(
+                    r,
+                    "Ranges of mutating expressions are not supported in reversible code",
+                ))
+            } else {
+                Ok(Expr::Range(r))
+            }
+        }
+        Expr::Reference(_) => Err(not_implemented(&e)),
+        Expr::Repeat(_) => Err(not_implemented(&e)),
+        Expr::Return(_) => Err(not_implemented(&e)),
+        Expr::Struct(_) => Err(not_implemented(&e)),
+        Expr::Try(_) => Err(not_implemented(&e)),
+        Expr::TryBlock(_) => Err(not_implemented(&e)),
+        Expr::Tuple(_) => Err(not_implemented(&e)),
+        Expr::Type(_) => Err(not_implemented(&e)),
+        Expr::Unary(u) => {
+            if mutates(&u.expr) {
+                Err(syn::Error::new_spanned(
+                    u,
+                    "Unary operators on mutating expressions are not supported in reversible code",
+                ))
             } else {
-                Expr::Macro(ExprMacro { attrs, mac: cmac })
+                Ok(Expr::Unary(u))
             }
         }
-        Expr::Match(_) => panic!("Not yet implemented {}", line!()),
-        Expr::MethodCall(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Paren(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Path(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Range(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Reference(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Repeat(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Return(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Struct(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Try(_) => panic!("Not yet implemented {}", line!()),
-        Expr::TryBlock(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Tuple(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Type(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Unary(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Unsafe(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Verbatim(_) => panic!("Not yet implemented {}", line!()),
-        Expr::While(_) => panic!("Not yet implemented {}", line!()),
-        Expr::Yield(_) => panic!("Not yet implemented {}", line!()),
-        _ => panic!("Not yet implemented {}", line!()),
+        Expr::Unsafe(u) => Ok(Expr::Unsafe(u)),
+        Expr::Verbatim(_) => Err(not_implemented(&e)),
+        Expr::While(_) => Err(not_implemented(&e)),
+        Expr::Yield(_) => Err(not_implemented(&e)),
+        _ => Err(not_implemented(&e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::reverse_impl;
+    use proc_macro2::TokenStream;
+    use quote::{quote, ToTokens};
+
+    /// The statements of the reversed block.
+    fn reversed(input: TokenStream) -> Vec<syn::Stmt> {
+        let output: syn::Block = syn::parse2(reverse_impl(input).unwrap()).unwrap();
+        match &output.stmts[..] {
+            [syn::Stmt::Expr(syn::Expr::Block(b))] => b.block.stmts.clone(),
+            _ => panic!("Expected a single block: {:?}", output),
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "unchecked"))]
+    fn reverse_assign_op() {
+        let stmts = reversed(quote! {{ *a += *c; *b ^= 2; }});
+        let expected: syn::Block = syn::parse_quote! {{
+            {
+                *b ^= 2
+            };
+            {
+                if core::ptr::eq(core::ptr::addr_of!(*a) as *const _ as *const (), core::ptr::addr_of!(*c) as *const _ as *const ()) {
+                    panic!("{}:{}: Lefthand and righthand are aliases of each other", file!(), line!());
+                }
+                *a -= *c
+            };
+        }};
+        assert_eq!(stmts, expected.stmts);
+    }
+
+    #[test]
+    #[cfg(feature = "debug-expand")]
+    fn reverse_pretty_block() {
+        let output = reverse_impl(quote! {{
+            let mut t = 0;
+            rif!(*a == 0, { *b += *c; }, *b == 0);
+            (*a, *b) ^= (1, t);
+            delocal!(t, 0);
+        }})
+        .unwrap();
+        let block: syn::Block = syn::parse2(output).unwrap();
+        let pretty = crate::utils::pretty_block(&block);
+        assert!(pretty.lines().count() > 10);
+        // The formatting adds trailing commas and semicolons, which do
+        // not change the meaning, so it is compared once formatted.
+        let parsed: syn::ItemFn = syn::parse_str(&pretty).unwrap();
+        assert_eq!(crate::utils::pretty_block(&parsed.block), pretty);
+    }
+
+    #[test]
+    fn reverse_comma_separated() {
+        let expected = reversed(quote! {{ *a += 1; *b -= 2; }});
+        assert_eq!(reversed(quote! {{ *a += 1, *b -= 2, }}), expected);
+        assert_eq!(reversed(quote! {{ *a += 1, *b -= 2; }}), expected);
+        assert_eq!(reversed(quote! {{ *a += 1;; *b -= 2,, }}), expected);
+        assert_eq!(
+            reversed(quote! {{ { *a += 1;; } }}),
+            reversed(quote! {{ { *a += 1; } }})
+        );
+    }
+
+    #[test]
+    fn reverse_call() {
+        let stmts = reversed(quote! {{ F::forward(s); G::backwards(s); h(s); }});
+        let expected: syn::Block = syn::parse_quote! {{
+            h(s);
+            G::forward(s);
+            F::backwards(s);
+        }};
+        assert_eq!(stmts, expected.stmts);
+    }
+
+    #[test]
+    fn reverse_await() {
+        let stmts = reversed(quote! {{ F::forward(s).await; ready(()).await; }});
+        let expected: syn::Block = syn::parse_quote! {{
+            ready(()).await;
+            F::backwards(s).await;
+        }};
+        assert_eq!(stmts, expected.stmts);
+    }
+
+    #[test]
+    fn reverse_self_inverse_macro() {
+        for (input, expected) in [
+            (
+                quote! {{ transpose!(m, 5); }},
+                quote! { ::rrust::transpose!(m, 5); },
+            ),
+            (
+                quote! {{ reverse_links!(next, *head); }},
+                quote! { ::rrust::reverse_links!(next, *head); },
+            ),
+            (
+                quote! {{ bit_reverse_permute!(x, 3); }},
+                quote! { ::rrust::bit_reverse_permute!(x, 3); },
+            ),
+        ] {
+            let expected: syn::Stmt = syn::parse2(expected).unwrap();
+            assert_eq!(reversed(input), vec![expected]);
+        }
+    }
+
+    #[test]
+    fn reverse_rng_step() {
+        let stmts = reversed(quote! {{ rng_next!(*rng); rng_prev!(*rng); }});
+        let expected: syn::Block = syn::parse_quote! {{
+            ::rrust::rng_next!(*rng);
+            ::rrust::rng_prev!(*rng);
+        }};
+        assert_eq!(stmts, expected.stmts);
+        // Other methods called next are not flipped.
+        assert!(reverse_impl(quote! {{ iter.next(); }}).is_err());
+    }
+
+    #[test]
+    fn reverse_tuple_assign_op() {
+        let stmts = reversed(quote! {{ (*a, *b) += (1, *a); }});
+        let expected: syn::Block = syn::parse_quote! {{
+            {
+                {
+                    *a -= 1
+                };
+                {
+                    if core::ptr::eq(core::ptr::addr_of!(*b) as *const _ as *const (), core::ptr::addr_of!(*a) as *const _ as *const ()) {
+                        panic!("{}:{}: Lefthand and righthand are aliases of each other", file!(), line!());
+                    }
+                    *b -= *a
+                };
+            };
+        }};
+        if cfg!(not(feature = "unchecked")) {
+            assert_eq!(stmts, expected.stmts);
+        }
+        let error = reverse_impl(quote! {{ (*a, *b) += 1; }}).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The righthand side has to be a tuple of the same length as the lefthand side"
+        );
+    }
+
+    #[test]
+    fn reverse_local() {
+        let stmts = reversed(quote! {{ let mut i = 0; i += 1; delocal!(i, 1); }});
+        let expected: syn::Stmt = syn::parse_quote! {
+            ::rrust::delocal!(i, 0);
+        };
+        assert_eq!(stmts.last(), Some(&expected));
+    }
+
+    #[test]
+    fn reverse_typed_local() {
+        let stmts = reversed(quote! {{ let mut s: u8 = 0; s += 1; delocal!(s, 1); }});
+        let first = stmts.first().map(|s| s.to_token_stream().to_string());
+        assert_eq!(first.as_deref(), Some("let mut s : u8 = 1 ;"));
+        let expected: syn::Stmt = syn::parse_quote! {
+            ::rrust::delocal!(s, 0);
+        };
+        assert_eq!(stmts.last(), Some(&expected));
+    }
+
+    #[test]
+    fn reverse_rif_outer_delocal() {
+        let stmts = reversed(quote! {{
+            let mut t = 0;
+            rif!(*x == 0, { delocal!(t, 0); }, { delocal!(t, 0); }, *x == 0);
+        }});
+        let first = stmts.first().map(|s| s.to_token_stream().to_string());
+        assert_eq!(first.as_deref(), Some("# [allow (unused_mut)] let mut t ;"));
+        let expected: syn::Stmt = syn::parse_quote! {
+            ::rrust::delocal!(t, 0);
+        };
+        assert_eq!(stmts.last(), Some(&expected));
+        assert_eq!(stmts.len(), 3);
+    }
+
+    #[test]
+    fn reverse_delocal_slot() {
+        let stmts = reversed(quote! {{ arr[k] += 1; arr[k] -= 1; delocal!(arr[k], 0); }});
+        let expected: syn::Stmt = syn::parse_quote! {
+            delocal!(arr[k], 0);
+        };
+        assert_eq!(stmts.first(), Some(&expected));
+    }
+
+    #[test]
+    #[cfg(feature = "unchecked")]
+    fn reverse_unchecked() {
+        let stmts = reversed(quote! {{ *a += *c; std::mem::swap(a, c); }});
+        let expected: syn::Block = syn::parse_quote! {{
+            std::mem::swap(a, c);
+            {
+                *a -= *c
+            };
+        }};
+        assert_eq!(stmts, expected.stmts);
+    }
+
+    #[test]
+    fn reverse_unary() {
+        let stmts = reversed(quote! {{ *a += -(*b); -(*b); }});
+        let expected: syn::Block = syn::parse_quote! {{
+            -(*b);
+            {
+                *a -= -(*b)
+            };
+        }};
+        assert_eq!(stmts, expected.stmts);
+    }
+
+    #[test]
+    fn reverse_unary_mutating() {
+        let error = reverse_impl(quote! {{ -({ *a += 1; *a }); }}).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unary operators on mutating expressions are not supported in reversible code"
+        );
+    }
+
+    #[test]
+    fn reverse_range() {
+        let stmts = reversed(quote! {{ *a += 1; 0..*n; }});
+        let expected: syn::Stmt = syn::parse_quote! { 0..*n; };
+        assert_eq!(stmts.first(), Some(&expected));
+
+        let error = reverse_impl(quote! {{ 0..f(a); }}).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Ranges of mutating expressions are not supported in reversible code"
+        );
+    }
+
+    #[test]
+    fn reverse_unsafe() {
+        let stmts = reversed(quote! {{ unsafe { *a += 1; *b ^= 2; } }});
+        let expected: syn::Block = syn::parse_quote! {{
+            unsafe {
+                {
+                    *b ^= 2
+                };
+                {
+                    *a -= 1
+                };
+            }
+        }};
+        assert_eq!(stmts, expected.stmts);
+    }
+
+    #[test]
+    fn reverse_disallowed_op() {
+        let error = reverse_impl(quote! {{ *a *= 2; }}).unwrap_err();
+        assert_eq!(error.to_string(), "disallowed binary operator");
+    }
+
+    #[test]
+    fn reverse_inverse_ops() {
+        let stmts = reversed(quote! {{ inverse_ops!(*=, /=); *a *= 2; { *b /= 3; } }});
+        let expected: syn::Block = syn::parse_quote! {{
+            {
+                {
+                    *b *= 3
+                };
+            }
+            {
+                *a /= 2
+            };
+            inverse_ops!(*=, /=);
+        }};
+        assert_eq!(stmts, expected.stmts);
+    }
+
+    #[test]
+    fn reverse_inverse_ops_later() {
+        let error = reverse_impl(quote! {{ *a *= 2; inverse_ops!(*=, /=); }}).unwrap_err();
+        assert_eq!(error.to_string(), "disallowed binary operator");
+        let error = reverse_impl(quote! {{ inverse_ops!(*=); }}).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "inverse_ops! expects an assignment operator and its inverse, like `*=, /=`"
+        );
+    }
+
+    #[test]
+    fn reverse_not_implemented() {
+        let error = reverse_impl(quote! {{ loop {} }}).unwrap_err();
+        assert_eq!(error.to_string(), "Not yet implemented in reversible code");
+    }
+
+    #[test]
+    fn reverse_missing_delocal() {
+        let error = reverse_impl(quote! {{ let mut i = 0; }}).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The following local(s) need to be consumed by delocal!: [\"i\"]"
+        );
+    }
+
+    #[test]
+    fn reverse_delocal_self_reference() {
+        let error =
+            reverse_impl(quote! {{ let mut z = 0; z += 1; delocal!(z, z + 1); }}).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The value of delocal! can not refer to the local `z` itself"
+        );
+    }
+
+    #[test]
+    fn reverse_side_effect_macro() {
+        let error = reverse_impl(quote! {{ std::println!("{}", a); }}).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`println!` has side effects which can not be reversed, mark the statement with #[rrust::skip] to allow it"
+        );
+        assert!(reverse_impl(quote! {{ #[rrust::skip] println!("{}", a); }}).is_ok());
     }
 }
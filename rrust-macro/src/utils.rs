@@ -1,37 +1,848 @@
+use quote::ToTokens;
 use syn::parse::Parser;
+use syn::visit::Visit;
 
-pub fn local_ident(local: &syn::Local) -> syn::Ident {
+/// Parse the block of reversible code given to `forward!` or
+/// `reverse!`. Generated code may separate the statements with commas,
+/// so when the block does not parse as it is every comma between the
+/// statements is read as a semicolon instead.
+pub fn parse_block(input: proc_macro2::TokenStream) -> syn::Result<syn::Block> {
+    use proc_macro2::{Delimiter, Group, Punct, Spacing, TokenTree};
+
+    syn::parse2(input.clone()).or_else(|error| {
+        let mut tokens = input.into_iter();
+        match (tokens.next(), tokens.next()) {
+            (Some(TokenTree::Group(g)), None) if g.delimiter() == Delimiter::Brace => {
+                let stmts = g
+                    .stream()
+                    .into_iter()
+                    .map(|token| match token {
+                        TokenTree::Punct(p) if p.as_char() == ',' => {
+                            let mut semi = Punct::new(';', Spacing::Alone);
+                            semi.set_span(p.span());
+                            TokenTree::Punct(semi)
+                        }
+                        token => token,
+                    })
+                    .collect();
+                let mut block = Group::new(Delimiter::Brace, stmts);
+                block.set_span(g.span());
+                syn::parse2(TokenTree::Group(block).into()).map_err(|_| error)
+            }
+            _ => Err(error),
+        }
+    })
+}
+
+/// Remove the empty statements of a block, like the one in `a += 1;;`.
+pub fn remove_empty_stmts(block: &mut syn::Block) {
+    block
+        .stmts
+        .retain(|stmt| !matches!(stmt, syn::Stmt::Semi(syn::Expr::Verbatim(v), _) if v.is_empty()));
+}
+
+/// Split the name of the function given by `rfn!` in front of the
+/// block, as in `Name, { ... }`, from the rest of the input.
+pub fn function_name(
+    input: proc_macro2::TokenStream,
+) -> (Option<syn::Ident>, proc_macro2::TokenStream) {
+    use proc_macro2::TokenTree;
+
+    let mut tokens = input.clone().into_iter();
+    match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Ident(name)), Some(TokenTree::Punct(p))) if p.as_char() == ',' => {
+            (Some(name), tokens.collect())
+        }
+        _ => (None, input),
+    }
+}
+
+/// Error when the function `name` calls its own `forward`, either as
+/// `name::forward` or `Self::forward`, outside of any `rif!` or loop,
+/// such a recursion never terminates.
+pub fn unconditional_recursion(name: &syn::Ident, block: &syn::Block) -> Option<syn::Error> {
+    let mut errors = None;
+    for stmt in &block.stmts {
+        let expr = match stmt {
+            syn::Stmt::Expr(e) | syn::Stmt::Semi(e, _) => e,
+            _ => continue,
+        };
+        match expr {
+            syn::Expr::Call(syn::ExprCall { func, .. }) => {
+                if let syn::Expr::Path(f) = &**func {
+                    let mut segments = f.path.segments.iter().rev();
+                    if let (Some(function), Some(ty)) = (segments.next(), segments.next()) {
+                        if function.ident == "forward" && (ty.ident == *name || ty.ident == "Self")
+                        {
+                            push_error(
+                                &mut errors,
+                                syn::Error::new_spanned(
+                                    expr,
+                                    format!(
+                                        "`{}::forward` is called unconditionally, so the recursion never terminates",
+                                        ty.ident
+                                    ),
+                                ),
+                            );
+                        }
+                    }
+                }
+            }
+            syn::Expr::Block(b) => {
+                if let Some(error) = unconditional_recursion(name, &b.block) {
+                    push_error(&mut errors, error);
+                }
+            }
+            syn::Expr::Unsafe(u) => {
+                if let Some(error) = unconditional_recursion(name, &u.block) {
+                    push_error(&mut errors, error);
+                }
+            }
+            _ => {}
+        }
+    }
+    errors
+}
+
+/// The pattern of a local without its type, if it is declared with one.
+pub fn local_pat(local: &syn::Local) -> &syn::Pat {
     match &local.pat {
-        syn::Pat::Ident(pi) => pi.ident.clone(),
-        _ => panic!("get_ident: Not implemented: {:?}", local.pat),
+        syn::Pat::Type(pt) => &pt.pat,
+        pat => pat,
+    }
+}
+
+/// The type a local is declared with, like `u8` in `let mut i: u8 = 0;`.
+pub fn local_type(local: &syn::Local) -> Option<&syn::Type> {
+    match &local.pat {
+        syn::Pat::Type(pt) => Some(&pt.ty),
+        _ => None,
+    }
+}
+
+pub fn local_ident(local: &syn::Local) -> syn::Result<syn::Ident> {
+    match local_pat(local) {
+        syn::Pat::Ident(pi) => Ok(pi.ident.clone()),
+        pat => Err(syn::Error::new_spanned(
+            pat,
+            "Only identifiers are supported as the pattern of a local",
+        )),
     }
 }
 
 pub fn macro_ident_expr(expr: &syn::Expr) -> Option<syn::Ident> {
     match expr {
-        syn::Expr::Macro(syn::ExprMacro { attrs: _, mac }) => mac.path.get_ident().cloned(),
+        syn::Expr::Macro(syn::ExprMacro { attrs: _, mac }) => macro_ident(&mac.path),
         _ => None,
     }
 }
 
-pub fn delocal_ident(expr: &syn::Expr) -> Option<syn::Ident> {
-    let punct: syn::punctuated::Punctuated<syn::Expr, syn::Token![,]> = match expr {
+/// Get the name of a macro called either by its name or through the
+/// `rrust` crate, this allows macros to call `::rrust::delocal!`.
+pub fn macro_ident(path: &syn::Path) -> Option<syn::Ident> {
+    if let Some(i) = path.get_ident() {
+        return Some(i.clone());
+    }
+    let mut segments = path.segments.iter();
+    match (segments.next(), segments.next(), segments.next()) {
+        (Some(krate), Some(name), None) if krate.ident == "rrust" => Some(name.ident.clone()),
+        _ => None,
+    }
+}
+
+/// Parse the comma separated arguments of a macro call.
+pub fn macro_args(
+    expr: &syn::Expr,
+) -> syn::Result<syn::punctuated::Punctuated<syn::Expr, syn::Token![,]>> {
+    match expr {
         syn::Expr::Macro(syn::ExprMacro { attrs: _, mac }) => {
             (|input: &syn::parse::ParseBuffer| syn::punctuated::Punctuated::parse_terminated(input))
                 .parse2(mac.tokens.clone())
-                .unwrap()
         }
-        _ => panic!(),
-    };
+        _ => Err(syn::Error::new_spanned(expr, "Expected a macro call")),
+    }
+}
 
-    let name = punct.first().unwrap();
-    let ident = match name {
-        syn::Expr::Path(syn::ExprPath {
+pub fn delocal_ident(expr: &syn::Expr) -> syn::Result<syn::Ident> {
+    let punct = macro_args(expr)?;
+
+    let ident = match punct.first() {
+        Some(syn::Expr::Path(syn::ExprPath {
             attrs: _,
             qself: _,
             path,
-        }) => path.get_ident().cloned(),
+        })) => path.get_ident().cloned(),
+        _ => None,
+    };
+    ident.ok_or_else(|| syn::Error::new_spanned(expr, "delocal! expects a local identifier"))
+}
+
+/// Error for a `delocal!` whose value refers to the local itself, like
+/// `delocal!(z, z + 1)`. The value is what the local is known to be, so
+/// writing it in terms of the local is always a mistake.
+pub fn delocal_self_reference(expr: &syn::Expr, ident: &syn::Ident) -> Option<syn::Error> {
+    struct Refers<'a>(&'a syn::Ident, Option<syn::Error>);
+
+    impl<'ast> Visit<'ast> for Refers<'_> {
+        fn visit_expr_path(&mut self, node: &'ast syn::ExprPath) {
+            if node.path.is_ident(self.0) {
+                push_error(
+                    &mut self.1,
+                    syn::Error::new_spanned(
+                        node,
+                        format!(
+                            "The value of delocal! can not refer to the local `{}` itself",
+                            self.0
+                        ),
+                    ),
+                );
+            }
+        }
+    }
+
+    let punct = macro_args(expr).ok()?;
+    let mut visitor = Refers(ident, None);
+    punct.iter().skip(1).for_each(|e| visitor.visit_expr(e));
+    visitor.1
+}
+
+/// A place consumed by `delocal!`, either a local like `i` or a slot of
+/// an array like `arr[k]`. Places are told apart by their tokens, so
+/// `arr[k]` and `arr[k + 0]` are different slots.
+#[derive(Clone)]
+pub struct Place {
+    expr: syn::Expr,
+    key: Vec<String>,
+}
+
+impl Place {
+    pub fn local(ident: &syn::Ident) -> Self {
+        Place {
+            expr: syn::parse_quote! { #ident },
+            key: vec![ident.to_string()],
+        }
+    }
+
+    fn slot(expr: &syn::Expr) -> Self {
+        Place {
+            expr: expr.clone(),
+            key: flat_tokens(expr.to_token_stream()),
+        }
+    }
+
+    fn is_local(&self) -> bool {
+        matches!(&self.expr, syn::Expr::Path(_))
+    }
+}
+
+impl PartialEq for Place {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl PartialEq<syn::Ident> for Place {
+    fn eq(&self, other: &syn::Ident) -> bool {
+        self.is_local() && self.key == [other.to_string()]
+    }
+}
+
+impl std::fmt::Display for Place {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.key.concat())
+    }
+}
+
+/// The slot of an array a `delocal!` is of, like `delocal!(arr[k], 0)`,
+/// rather than a local. Slots are not bound by a local so they are
+/// only checked, in both directions.
+pub fn delocal_slot(expr: &syn::Expr) -> Option<Place> {
+    match macro_args(expr).ok()?.first() {
+        Some(e @ syn::Expr::Index(_)) => Some(Place::slot(e)),
+        _ => None,
+    }
+}
+
+/// Mark the slots of arrays used by a statement as in use, so they can
+/// be delocaled afterwards. A `delocal!` is not a use of its slot, so
+/// delocaling a slot again without using it in between is an error.
+pub fn use_slots(delocal_list: &mut Vec<Place>, stmt: &syn::Stmt) {
+    struct Slots<'a>(&'a mut Vec<Place>);
+
+    impl<'ast> Visit<'ast> for Slots<'_> {
+        fn visit_expr_index(&mut self, node: &'ast syn::ExprIndex) {
+            let slot = Place::slot(&syn::Expr::Index(node.clone()));
+            if !self.0.contains(&slot) {
+                self.0.push(slot);
+            }
+            syn::visit::visit_expr_index(self, node);
+        }
+
+        fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+            if macro_ident(&mac.path).is_some_and(|i| i == "delocal") {
+                return;
+            }
+            let args = (|input: &syn::parse::ParseBuffer| {
+                syn::punctuated::Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated(input)
+            })
+            .parse2(mac.tokens.clone());
+            match args {
+                Ok(args) => args.iter().for_each(|e| self.visit_expr(e)),
+                // Macros like `rfor!` are not a list of expressions, but
+                // their bodies are still blocks.
+                Err(_) => mac
+                    .tokens
+                    .clone()
+                    .into_iter()
+                    .filter_map(|tt| syn::parse2::<syn::Block>(tt.into()).ok())
+                    .for_each(|b| self.visit_block(&b)),
+            }
+        }
+    }
+
+    Slots(delocal_list).visit_stmt(stmt);
+}
+
+/// Consume a slot delocaled by `delocal!`, it has to be in use.
+pub fn delocal_use(delocal_list: &mut Vec<Place>, slot: &Place) -> Option<syn::Error> {
+    match delocal_list.iter().position(|p| p == slot) {
+        Some(index) => {
+            delocal_list.remove(index);
+            None
+        }
+        None => Some(syn::Error::new_spanned(
+            &slot.expr,
+            format!("Attempt to delocal a slot which is not in use: {}", slot),
+        )),
+    }
+}
+
+/// Add an error to the errors collected so far.
+pub fn push_error(errors: &mut Option<syn::Error>, error: syn::Error) {
+    match errors {
+        Some(errors) => errors.combine(error),
+        None => *errors = Some(error),
+    }
+}
+
+/// Error for the locals that have not been consumed by `delocal!`. The
+/// slots of arrays are not bound by a local, so they do not have to be.
+pub fn delocal_error(delocal_list: &[Place]) -> Option<syn::Error> {
+    let locals: Vec<_> = delocal_list.iter().filter(|p| p.is_local()).collect();
+    let first = locals.first()?;
+    let ident_list = locals.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    Some(syn::Error::new_spanned(
+        &first.expr,
+        format!(
+            "The following local(s) need to be consumed by delocal!: {:?}",
+            ident_list
+        ),
+    ))
+}
+
+/// Find the locals from outside of a `rif!` which are delocaled in
+/// its branches. Both branches have to delocal the same locals, if
+/// not an error is returned along with the locals.
+pub fn rif_delocals(expr: &syn::Expr) -> (Vec<syn::Ident>, Option<syn::Error>) {
+    let args = match macro_args(expr) {
+        Ok(args) => args,
+        Err(_) => return (Vec::new(), None),
+    };
+    let mut branches = args.iter().filter_map(|arg| match arg {
+        syn::Expr::Block(b) => Some(outer_delocals(&b.block)),
+        _ => None,
+    });
+    let then = branches.next().unwrap_or_default();
+    let els = branches.next().unwrap_or_default();
+
+    let mut errors = None;
+    let mut delocals = then.clone();
+    for i in then.iter().filter(|i| !els.contains(i)) {
+        push_error(
+            &mut errors,
+            syn::Error::new(
+                i.span(),
+                format!(
+                    "`{}` is delocaled in the then branch of rif! but not in the else branch",
+                    i
+                ),
+            ),
+        );
+    }
+    for i in els.iter().filter(|i| !then.contains(i)) {
+        push_error(
+            &mut errors,
+            syn::Error::new(
+                i.span(),
+                format!(
+                    "`{}` is delocaled in the else branch of rif! but not in the then branch",
+                    i
+                ),
+            ),
+        );
+        delocals.push(i.clone());
+    }
+    (delocals, errors)
+}
+
+/// Tell the branches of a `rif!` about the locals from outside of it
+/// which they delocal. Every branch is expanded by its own `forward!`
+/// or `reverse!`, so an `_outer_locals!` statement naming the locals is
+/// put at the top of each branch.
+pub fn mark_outer_locals(expr: syn::Expr, locals: &[syn::Ident]) -> syn::Expr {
+    let mut m = match expr {
+        syn::Expr::Macro(m) if !locals.is_empty() => m,
+        expr => return expr,
+    };
+    let mut args = match macro_args(&syn::Expr::Macro(m.clone())) {
+        Ok(args) => args,
+        Err(_) => return syn::Expr::Macro(m),
+    };
+    for arg in args.iter_mut() {
+        if let syn::Expr::Block(b) = arg {
+            b.block.stmts.insert(
+                0,
+                syn::parse_quote! {
+                    ::rrust::_outer_locals!(#(#locals),*);
+                },
+            );
+        }
+    }
+    m.mac.tokens = args.to_token_stream();
+    syn::Expr::Macro(m)
+}
+
+/// The locals named by an `_outer_locals!` statement.
+pub fn outer_locals(expr: &syn::Expr) -> Option<Vec<syn::Ident>> {
+    if macro_ident_expr(expr)? != "_outer_locals" {
+        return None;
+    }
+    let args = macro_args(expr).ok()?;
+    Some(
+        args.iter()
+            .filter_map(|arg| match arg {
+                syn::Expr::Path(p) => p.path.get_ident().cloned(),
+                _ => None,
+            })
+            .collect(),
+    )
+}
+
+/// The identifiers delocaled in a block which are not introduced by
+/// the block itself.
+fn outer_delocals(block: &syn::Block) -> Vec<syn::Ident> {
+    let mut locals = Vec::new();
+    let mut delocals = Vec::new();
+    for stmt in &block.stmts {
+        match stmt {
+            syn::Stmt::Local(local) => {
+                if let Ok(i) = local_ident(local) {
+                    locals.push(i);
+                }
+            }
+            syn::Stmt::Expr(e) | syn::Stmt::Semi(e, _) => {
+                if macro_ident_expr(e).is_some_and(|i| i == "delocal") {
+                    if let Ok(i) = delocal_ident(e) {
+                        if !locals.contains(&i) {
+                            delocals.push(i);
+                        }
+                    }
+                }
+            }
+            syn::Stmt::Item(_) => (),
+        }
+    }
+    delocals
+}
+
+/// Add a `delocal!` for every immutable local initialized with a
+/// literal which is not delocaled in the block. The value of such a
+/// local is known so it does not need to be delocaled by hand. The
+/// `delocal!` is added where the local goes out of scope, that is
+/// where it is shadowed or at the end of the block.
+pub fn implicit_delocals(block: &mut syn::Block) {
+    let mut index = 0;
+    while index < block.stmts.len() {
+        if let Some((ident, value)) = constant_local(&block.stmts[index]) {
+            let end = block.stmts[index + 1..]
+                .iter()
+                .position(|stmt| stmt_local(stmt).is_some_and(|i| i == ident))
+                .map_or(block.stmts.len(), |p| index + 1 + p);
+            let delocaled = block.stmts[index + 1..end]
+                .iter()
+                .any(|stmt| stmt_delocals(stmt, &ident));
+            if !delocaled {
+                if let syn::Stmt::Expr(e) = &block.stmts[end - 1] {
+                    block.stmts[end - 1] = syn::Stmt::Semi(e.clone(), Default::default());
+                }
+                block.stmts.insert(
+                    end,
+                    syn::parse_quote! {
+                        ::rrust::delocal!(#ident, #value);
+                    },
+                );
+            }
+        }
+        index += 1;
+    }
+}
+
+/// The identifier and value of an immutable local initialized with a
+/// literal.
+fn constant_local(stmt: &syn::Stmt) -> Option<(syn::Ident, syn::Expr)> {
+    fn is_constant(expr: &syn::Expr) -> bool {
+        match expr {
+            syn::Expr::Lit(_) => true,
+            syn::Expr::Unary(syn::ExprUnary {
+                op: syn::UnOp::Neg(_),
+                expr,
+                ..
+            }) => is_constant(expr),
+            syn::Expr::Paren(p) => is_constant(&p.expr),
+            syn::Expr::Group(g) => is_constant(&g.expr),
+            _ => false,
+        }
+    }
+
+    match stmt {
+        syn::Stmt::Local(syn::Local {
+            pat: syn::Pat::Ident(pi),
+            init: Some((_, expr)),
+            ..
+        }) if pi.mutability.is_none()
+            && pi.by_ref.is_none()
+            && pi.subpat.is_none()
+            && is_constant(expr) =>
+        {
+            Some((pi.ident.clone(), (**expr).clone()))
+        }
+        _ => None,
+    }
+}
+
+fn stmt_local(stmt: &syn::Stmt) -> Option<syn::Ident> {
+    match stmt {
+        syn::Stmt::Local(local) => local_ident(local).ok(),
         _ => None,
+    }
+}
+
+/// Whether a statement delocals the local, either directly or in the
+/// branches of a `rif!`.
+fn stmt_delocals(stmt: &syn::Stmt, ident: &syn::Ident) -> bool {
+    let expr = match stmt {
+        syn::Stmt::Expr(e) | syn::Stmt::Semi(e, _) => e,
+        _ => return false,
     };
-    ident
+    match macro_ident_expr(expr) {
+        Some(i) if i == "delocal" => delocal_ident(expr).is_ok_and(|i| i == *ident),
+        Some(i) if i == "rif" => rif_delocals(expr).0.contains(ident),
+        _ => false,
+    }
+}
+
+/// Whether an expression can refer to a place in memory, only these
+/// can be aliases of the lefthand side of an assignment. This includes
+/// expressions marked with `place!`.
+pub fn is_place(expr: &syn::Expr) -> bool {
+    match expr {
+        syn::Expr::Path(_) | syn::Expr::Index(_) | syn::Expr::Field(_) => true,
+        syn::Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Deref(_),
+            ..
+        }) => true,
+        syn::Expr::Paren(p) => is_place(&p.expr),
+        syn::Expr::Group(g) => is_place(&g.expr),
+        syn::Expr::Macro(m) => macro_ident(&m.mac.path).is_some_and(|i| i == "place"),
+        _ => false,
+    }
+}
+
+/// Whether an expression may mutate state, i.e. it contains an
+/// assignment, a mutable borrow or a call of a function, method or
+/// macro. Expressions which do not mutate anything are the same in
+/// both directions.
+pub fn mutates(expr: &syn::Expr) -> bool {
+    struct Mutates(bool);
+
+    impl<'ast> Visit<'ast> for Mutates {
+        fn visit_expr(&mut self, expr: &'ast syn::Expr) {
+            match expr {
+                syn::Expr::Assign(_)
+                | syn::Expr::AssignOp(_)
+                | syn::Expr::Call(_)
+                | syn::Expr::MethodCall(_)
+                | syn::Expr::Macro(_) => self.0 = true,
+                syn::Expr::Reference(r) if r.mutability.is_some() => self.0 = true,
+                _ => syn::visit::visit_expr(self, expr),
+            }
+        }
+    }
+
+    let mut visitor = Mutates(false);
+    visitor.visit_expr(expr);
+    visitor.0
+}
+
+/// Macros which print, allocate or otherwise have side effects that
+/// can not be reversed. They are matched on the last segment of the
+/// path, so `std::println!` is caught as well. Other macros are passed
+/// through as they are, which is fine for pure macros like
+/// `assert_eq!`.
+const SIDE_EFFECT_MACROS: &[&str] = &[
+    "dbg", "eprint", "eprintln", "format", "print", "println", "vec", "write", "writeln",
+];
+
+/// Error for every macro with side effects used in an expression.
+pub fn side_effect_macros(expr: &syn::Expr) -> Option<syn::Error> {
+    struct SideEffects(Option<syn::Error>);
+
+    impl<'ast> Visit<'ast> for SideEffects {
+        fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+            if let Some(i) = mac.path.segments.last().map(|s| &s.ident) {
+                if SIDE_EFFECT_MACROS.iter().any(|m| i == m) {
+                    push_error(
+                        &mut self.0,
+                        syn::Error::new_spanned(
+                            mac,
+                            format!(
+                                "`{}!` has side effects which can not be reversed, mark the statement with #[rrust::skip] to allow it",
+                                i
+                            ),
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    let mut visitor = SideEffects(None);
+    visitor.visit_expr(expr);
+    visitor.0
+}
+
+/// The block formatted as Rust source, it is wrapped in a function as
+/// only whole files can be formatted.
+#[cfg(feature = "debug-expand")]
+pub fn pretty_block(block: &syn::Block) -> String {
+    prettyplease::unparse(&syn::parse_quote! { fn expanded() #block })
+}
+
+/// The runtime check that the two sides of an assignment are not
+/// aliases of each other. When the righthand side is not a place,
+/// e.g. a literal or an arithmetic expression, no check is needed.
+/// With the `unchecked` feature no checks are generated at all.
+///
+/// Dereferenced places are compared by their raw address, see
+/// [`address`].
+pub fn alias_guard(left: &syn::Expr, right: &syn::Expr) -> Option<syn::Stmt> {
+    if cfg!(feature = "unchecked") || !is_place(right) {
+        return None;
+    }
+    // The sides can be of different types, like a shift amount, so
+    // only the addresses are compared.
+    let (left, right) = (address(left), address(right));
+    Some(syn::parse_quote! {
+        if core::ptr::eq(#left as *const _ as *const (), #right as *const _ as *const ()) {
+            panic!("{}:{}: Lefthand and righthand are aliases of each other", file!(), line!());
+        }
+    })
+}
+
+/// The address of a place for an alias check. A dereference like
+/// `*ptr` may be of a raw pointer, so its address is taken with
+/// `addr_of!` which does not create a reference to the place. Other
+/// places, which can also be constants, are borrowed.
+pub fn address(place: &syn::Expr) -> syn::Expr {
+    match place {
+        syn::Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Deref(_),
+            ..
+        }) => syn::parse_quote! { core::ptr::addr_of!(#place) },
+        _ => syn::parse_quote! { &(#place) },
+    }
+}
+
+/// Lower an assignment operation into a range of a slice, such as
+/// `arr[2..5] += e`, to a loop applying the operation to every element
+/// in the range. The righthand side is evaluated for every element, so
+/// the borrow checker rejects it if it refers to the slice.
+pub fn range_assign_op(left: &syn::Expr, op: &syn::BinOp, right: &syn::Expr) -> Option<syn::Expr> {
+    let index = match left {
+        syn::Expr::Index(index) => index,
+        _ => return None,
+    };
+    let mut range = &*index.index;
+    while let syn::Expr::Paren(p) = range {
+        range = &p.expr;
+    }
+    if !matches!(range, syn::Expr::Range(_)) {
+        return None;
+    }
+    let base = &index.expr;
+    let elem = syn::Ident::new("elem", proc_macro2::Span::mixed_site());
+    Some(syn::parse_quote! {
+        for #elem in (#base)[#range].iter_mut() {
+            *#elem #op #right;
+        }
+    })
+}
+
+/// Split an assignment operation on tuples, like `(*a, *b) += (x, y)`,
+/// into an operation for every element. Returns `None` when the
+/// lefthand side is not a tuple.
+///
+/// The elements are updated as if at the same time, every righthand
+/// side sees the values from before the assignment. So an element is
+/// updated only after the elements whose righthand side reads it, when
+/// two elements read each other this is not possible and it is an
+/// error, such an assignment could not be reversed anyway.
+pub fn tuple_assign_ops(
+    left: &syn::Expr,
+    op: &syn::BinOp,
+    right: &syn::Expr,
+) -> Option<syn::Result<Vec<syn::Expr>>> {
+    let left = match left {
+        syn::Expr::Tuple(t) => t,
+        _ => return None,
+    };
+    let right = match right {
+        syn::Expr::Tuple(t) if t.elems.len() == left.elems.len() => t,
+        _ => {
+            return Some(Err(syn::Error::new_spanned(
+                right,
+                "The righthand side has to be a tuple of the same length as the lefthand side",
+            )))
+        }
+    };
+    let reads = |r: &syn::Expr, l: &syn::Expr| {
+        let needle = flat_tokens(l.to_token_stream());
+        flat_tokens(r.to_token_stream())
+            .windows(needle.len())
+            .any(|w| w == needle)
+    };
+
+    let mut pending: Vec<_> = left.elems.iter().zip(&right.elems).collect();
+    let mut ops = Vec::new();
+    while !pending.is_empty() {
+        // An element which is not read by any of the other pending
+        // righthand sides can be updated now.
+        let next = (0..pending.len()).find(|&i| {
+            pending
+                .iter()
+                .enumerate()
+                .all(|(j, (_, r))| i == j || !reads(r, pending[i].0))
+        });
+        match next {
+            Some(i) => {
+                let (l, r) = pending.remove(i);
+                ops.push(syn::parse_quote! { #l #op #r });
+            }
+            None => {
+                return Some(Err(syn::Error::new_spanned(
+                    right,
+                    "The righthand sides of a tuple assignment read each other's lefthand sides, so it can not be reversed",
+                )))
+            }
+        }
+    }
+    Some(Ok(ops))
+}
+
+/// The runtime alias check for a call of `mem::swap`, swapping a
+/// place with itself is harmless but it is most likely a mistake just
+/// like it is for `rswap!`. The function is recognized by its path, so
+/// `std::mem::swap`, `core::mem::swap` and `mem::swap` are checked. A
+/// bare `swap` may be any function, so it is left alone.
+pub fn swap_guard(call: &syn::ExprCall) -> Option<syn::Stmt> {
+    if cfg!(feature = "unchecked") {
+        return None;
+    }
+    let path = match &*call.func {
+        syn::Expr::Path(p) => &p.path,
+        _ => return None,
+    };
+    let segments: Vec<_> = path.segments.iter().map(|s| s.ident.to_string()).collect();
+    let segments: Vec<_> = segments.iter().map(String::as_str).collect();
+    let is_swap = matches!(
+        &segments[..],
+        ["mem", "swap"] | ["std" | "core", "mem", "swap"]
+    );
+    if !is_swap || call.args.len() != 2 {
+        return None;
+    }
+    let (a, b) = (&call.args[0], &call.args[1]);
+    Some(syn::parse_quote! {
+        if core::ptr::eq(&*(#a), &*(#b)) {
+            panic!("{}:{}: Lefthand and righthand are aliases of each other", file!(), line!());
+        }
+    })
+}
+
+/// Remove the `#[rrust::skip]` attribute from a statement, returns
+/// whether it was present. Skipped statements are passed through both
+/// directions untouched.
+pub fn take_skip(stmt: &mut syn::Stmt) -> bool {
+    let attrs = match stmt_attrs(stmt) {
+        Some(attrs) => attrs,
+        None => return false,
+    };
+    let is_skip = |attr: &syn::Attribute| {
+        let mut segments = attr.path.segments.iter();
+        match (segments.next(), segments.next(), segments.next()) {
+            (Some(krate), Some(name), None) => krate.ident == "rrust" && name.ident == "skip",
+            _ => false,
+        }
+    };
+    match attrs.iter().position(is_skip) {
+        Some(index) => {
+            attrs.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+fn stmt_attrs(stmt: &mut syn::Stmt) -> Option<&mut Vec<syn::Attribute>> {
+    macro_rules! attrs {
+        ($node:expr, $kind:ident: $($variant:ident),*) => {
+            match $node {
+                $(syn::$kind::$variant(n) => Some(&mut n.attrs),)*
+                _ => None,
+            }
+        };
+    }
+    match stmt {
+        syn::Stmt::Local(l) => Some(&mut l.attrs),
+        syn::Stmt::Item(i) => attrs!(i, Item: Const, Enum, ExternCrate, Fn, ForeignMod, Impl,
+            Macro, Macro2, Mod, Static, Struct, Trait, TraitAlias, Type, Union, Use),
+        syn::Stmt::Expr(e) | syn::Stmt::Semi(e, _) => attrs!(e, Expr: Array, Assign, AssignOp,
+            Async, Await, Binary, Block, Box, Break, Call, Cast, Closure, Continue, Field,
+            ForLoop, Group, If, Index, Let, Lit, Loop, Macro, Match, MethodCall, Paren, Path,
+            Range, Reference, Repeat, Return, Struct, Try, TryBlock, Tuple, Type, Unary, Unsafe,
+            While, Yield),
+    }
+}
+
+/// The tokens of an expression as strings, with the delimiters of the
+/// groups as separate tokens.
+pub fn flat_tokens(tokens: proc_macro2::TokenStream) -> Vec<String> {
+    let mut flat = Vec::new();
+    for tt in tokens {
+        match tt {
+            proc_macro2::TokenTree::Group(g) => {
+                let (open, close) = match g.delimiter() {
+                    proc_macro2::Delimiter::Parenthesis => ("(", ")"),
+                    proc_macro2::Delimiter::Brace => ("{", "}"),
+                    proc_macro2::Delimiter::Bracket => ("[", "]"),
+                    proc_macro2::Delimiter::None => ("", ""),
+                };
+                flat.push(open.to_string());
+                flat.extend(flat_tokens(g.stream()));
+                flat.push(close.to_string());
+            }
+            tt => flat.push(tt.to_string()),
+        }
+    }
+    flat
 }
@@ -1,5 +1,17 @@
 #[cfg(test)]
-use rrust::{delocal, rfn, rif, rloop};
+use rrust::{
+    add_mod, affine, apply_lut, apply_perm, arith_encode_step, atomic_add, bit_reverse_permute,
+    bucket_consume, butterfly, bwt_forward, cell_add, checksum_frame, cmul_unit,
+    counting_bloom_add, counting_distribute, crc_step, debruijn_step, deinterleave, delocal,
+    digit_rotate, disjoint, feistel_round, gcd_reversible, gray_inc, horner, interleave,
+    inverse_ops, isqrt_into, lifting_predict, lifting_update, masked_add, moving_avg_step,
+    mul_accumulate, mul_mod_unit, negate_if, nonzero_add, nonzero_sub, pack_nibbles, place,
+    rassert, relax, reverse_links, reverse_order, reversible, reversible_closure, rfn, rfn_async,
+    rfor, rif, rle_encode, rloop, rmatch, rng_next, rotate_ring, rswap, segtree_update, sift_up,
+    sorted_insert, spop, spush, sub_borrow, sub_mod, syndrome, to_bcd, to_digits, toggle,
+    toggle_case, transpose, unpack_nibbles, wide_add, window_max_step, with_scratch,
+    xor_list_advance, xor_list_retreat, zobrist_toggle, RevRng, Reversible, TransactionLog,
+};
 
 #[test]
 fn test_addone() {
@@ -194,6 +206,69 @@ fn test_delocal_block() {
 }
 
 #[test]
+fn test_delocal_slot() {
+    rfn!(Scratch, (arr: &mut [i32], x: &mut i32), {
+        arr[3] += *x;
+        arr[3] += 1;
+        arr[0] += arr[3] * arr[3];
+        arr[3] -= 1;
+        arr[3] -= *x;
+        delocal!(arr[3], 0);
+    });
+
+    let mut arr = [1, 2, 3, 0];
+    let mut x = 4;
+
+    Scratch::forward(&mut arr, &mut x);
+
+    assert_eq!(arr, [26, 2, 3, 0]);
+
+    Scratch::backwards(&mut arr, &mut x);
+
+    assert_eq!(arr, [1, 2, 3, 0]);
+}
+
+#[test]
+fn test_delocal_slot_place() {
+    struct Scratch {
+        buf: [i32; 2],
+    }
+
+    rfn!(Nested, (s: &mut Scratch, m: &mut [[i32; 2]; 2]), {
+        s.buf[1] += m[0][0];
+        m[1][1] += s.buf[1];
+        s.buf[1] -= m[0][0];
+        delocal!(s.buf[1], 0);
+        m[1][0] += m[0][1];
+        m[1][0] -= m[0][1];
+        delocal!(m[1][0], 0);
+    });
+
+    let mut s = Scratch { buf: [5, 0] };
+    let mut m = [[2, 3], [0, 1]];
+
+    Nested::forward(&mut s, &mut m);
+
+    assert_eq!((s.buf, m), ([5, 0], [[2, 3], [0, 3]]));
+
+    Nested::backwards(&mut s, &mut m);
+
+    assert_eq!((s.buf, m), ([5, 0], [[2, 3], [0, 1]]));
+}
+
+#[test]
+#[should_panic(expected = "Delocal failed 7 != 0")]
+fn test_delocal_slot_dirty() {
+    rfn!(Scratch, (arr: &mut [i32], x: &mut i32), {
+        arr[3] += *x;
+        delocal!(arr[3], 0);
+    });
+
+    Scratch::backwards(&mut [0, 0, 0, 7], &mut 1);
+}
+
+#[test]
+#[cfg(not(feature = "unchecked"))]
 #[should_panic]
 fn test_alias_arr() {
     rfn!(Alias, (arr: &mut [i32]), {
@@ -209,6 +284,7 @@ fn test_alias_arr() {
 }
 
 #[test]
+#[cfg(not(feature = "unchecked"))]
 #[should_panic]
 fn test_alias_var() {
     rfn!(Alias, (x: &mut i32), {
@@ -227,15 +303,148 @@ fn test_alias_arg() {
     t.compile_fail("src/tests/alias_arg.rs");
 }
 
+#[test]
+fn test_alias_overlapping_slices() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("src/tests/overlapping_slices.rs");
+}
+
+#[test]
+#[cfg(not(feature = "unchecked"))]
+#[should_panic(expected = "Slice arguments overlap each other")]
+fn test_alias_overlapping_raw_slices() {
+    rfn!(AddSlices, (a: &mut [i32], b: &mut [i32]), {
+        a[0] += b[1];
+    });
+
+    let mut arr = [1, 2, 3, 4, 5];
+    let ptr = arr.as_mut_ptr();
+    // The borrow checker can not see that these overlap.
+    let (a, b) = unsafe {
+        (
+            std::slice::from_raw_parts_mut(ptr, 3),
+            std::slice::from_raw_parts_mut(ptr.add(2), 3),
+        )
+    };
+
+    AddSlices::forward(a, b);
+}
+
+#[test]
+fn test_adjacent_slices() {
+    rfn!(AddSlices, (a: &mut [i32], b: &mut [i32]), {
+        a[0] += b[1];
+    });
+
+    let mut arr = [1, 2, 3, 4, 5];
+    let (a, b) = arr.split_at_mut(3);
+
+    AddSlices::forward(a, b);
+
+    assert_eq!(arr, [6, 2, 3, 4, 5]);
+}
+
 #[test]
 fn test_no_delocal() {
     let t = trybuild::TestCases::new();
     t.compile_fail("src/tests/no_delocal.rs");
 }
 
+#[test]
+fn test_delocal_self_reference() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("src/tests/delocal_self_reference.rs");
+}
+
+#[test]
+fn test_rif_balanced_delocal() {
+    rfn!(Balanced, (x: &mut i32, y: &mut i32), {
+        let mut t = *x * 2;
+        rif!(
+            *x > 0,
+            {
+                *y += t;
+                t -= *x * 2;
+                delocal!(t, 0);
+            },
+            {
+                *y -= t;
+                t += 5;
+                delocal!(t, *x * 2 + 5);
+            },
+            *x > 0
+        );
+    });
+
+    for x in [3, -4] {
+        let mut x = x;
+        let mut y = 1;
+
+        Balanced::forward(&mut x, &mut y);
+
+        assert_eq!(y, 1 + 2 * x.abs());
+
+        Balanced::backwards(&mut x, &mut y);
+
+        assert_eq!(y, 1);
+    }
+}
+
+#[test]
+fn test_rif_unbalanced_delocal() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("src/tests/rif_unbalanced_delocal.rs");
+}
+
+#[test]
+fn test_side_effect_macro() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("src/tests/side_effect_macro.rs");
+}
+
+#[test]
+fn test_immutable_local() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("src/tests/immutable_local.rs");
+}
+
+#[test]
+fn test_unconditional_recursion() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("src/tests/unconditional_recursion.rs");
+}
+
+#[test]
+fn test_forward_only() {
+    rfn!(forward AddOne, (x: &mut i32), {
+        *x += 1;
+    });
+
+    rfn!(backwards SubOne, (x: &mut i32), {
+        *x -= 1;
+    });
+
+    let mut x = 0;
+
+    AddOne::forward(&mut x);
+    SubOne::backwards(&mut x);
+
+    assert_eq!(x, 2);
+
+    let t = trybuild::TestCases::new();
+    t.compile_fail("src/tests/forward_only.rs");
+}
+
+#[test]
+#[cfg(feature = "inline")]
+fn test_inline() {
+    let t = trybuild::TestCases::new();
+    t.pass("src/tests/inline.rs");
+}
+
 #[test]
 fn test_factor() {
-    rfn!(Factor, (num: &mut usize, fact: &mut [usize; 20]), {
+    rfn!(stats Factor, (num: &mut usize, fact: &mut [usize; 20]), {
         let mut tryf = 0;
         let mut i = 0;
         rloop!(
@@ -251,7 +460,7 @@ fn test_factor() {
                         std::mem::swap(&mut z, num);
                         delocal!(z, *num * tryf);
                     },
-                    *num % tryf != 0
+                    !num.is_multiple_of(tryf)
                 );
             },
             tryf * tryf > *num
@@ -337,4 +546,3121 @@ fn test_factor() {
             println!(".");
         }
     }
+
+    assert_eq!(
+        Factor::stats(),
+        rrust::Stats {
+            adds: 3,
+            subs: 2,
+            xors: 3,
+            calls: 5,
+            loops: 3,
+        }
+    );
+}
+
+#[test]
+fn test_rotate_ring() {
+    rfn!(Rotate, (buf: &mut [i32], head: &mut usize, k: &mut usize), {
+        rotate_ring!(buf, *head, *k);
+    });
+
+    let mut buf = [1, 2, 3, 4, 5, 6, 7];
+    let mut head = 5;
+    let mut k = 4;
+
+    Rotate::forward(&mut buf, &mut head, &mut k);
+
+    assert_eq!(head, 2);
+    assert_eq!(buf, [1, 2, 3, 4, 5, 6, 7]);
+
+    Rotate::forward(&mut buf, &mut head, &mut k);
+
+    assert_eq!(head, 6);
+
+    Rotate::backwards(&mut buf, &mut head, &mut k);
+    Rotate::backwards(&mut buf, &mut head, &mut k);
+
+    assert_eq!(head, 5);
+    assert_eq!(buf, [1, 2, 3, 4, 5, 6, 7]);
+}
+
+#[test]
+fn test_mod_near_max() {
+    rfn!(Tick, (a: &mut u8, b: &mut u8), {
+        add_mod!(*a, 20, 250);
+        sub_mod!(*b, 20, 250);
+    });
+
+    let mut a = 240;
+    let mut b = 5;
+
+    Tick::forward(&mut a, &mut b);
+
+    assert_eq!((a, b), (10, 235));
+
+    Tick::backwards(&mut a, &mut b);
+
+    assert_eq!((a, b), (240, 5));
+}
+
+#[test]
+#[allow(unused_labels)]
+fn test_labeled_block() {
+    rfn!(Labeled, (a: &mut i32, b: &mut i32), {
+        'outer: {
+            *a += 1;
+            'inner: {
+                *b += *a;
+            }
+        }
+    });
+
+    let mut a = 1;
+    let mut b = 2;
+
+    Labeled::forward(&mut a, &mut b);
+
+    assert_eq!(a, 2);
+    assert_eq!(b, 4);
+
+    Labeled::backwards(&mut a, &mut b);
+
+    assert_eq!(a, 1);
+    assert_eq!(b, 2);
+}
+
+#[test]
+fn test_transpose() {
+    rfn!(Transpose, (m: &mut [i32]), {
+        transpose!(m, 5);
+    });
+
+    let original: Vec<i32> = (0..25).collect();
+    let mut m = original.clone();
+
+    Transpose::forward(&mut m);
+
+    for i in 0..5 {
+        for j in 0..5 {
+            assert_eq!(m[i * 5 + j], original[j * 5 + i]);
+        }
+    }
+
+    Transpose::backwards(&mut m);
+
+    assert_eq!(m, original);
+}
+
+#[test]
+#[should_panic]
+fn test_transpose_not_square() {
+    rfn!(Transpose, (m: &mut [i32]), {
+        transpose!(m, 5);
+    });
+
+    let mut m = [0; 24];
+
+    Transpose::forward(&mut m);
+}
+
+#[test]
+fn test_negate_if() {
+    rfn!(Negate, (control: &mut bool, x: &mut i32, y: &mut i32), {
+        negate_if!(*control, *x);
+        negate_if!(*x > 0, *y);
+    });
+
+    for (control, x, y) in [(true, 5, 3), (false, 5, 3), (true, -4, 8), (false, -4, 8)] {
+        let mut c = control;
+        let mut a = x;
+        let mut b = y;
+
+        Negate::forward(&mut c, &mut a, &mut b);
+
+        assert_eq!(a, if control { -x } else { x });
+        assert_eq!(b, if a > 0 { -y } else { y });
+
+        Negate::backwards(&mut c, &mut a, &mut b);
+
+        assert_eq!((c, a, b), (control, x, y));
+    }
+}
+
+#[test]
+#[should_panic(expected = "Minimum value can not be negated")]
+fn test_negate_if_min() {
+    rfn!(Negate, (control: &mut bool, x: &mut i8), {
+        negate_if!(*control, *x);
+    });
+
+    let mut x = i8::MIN;
+
+    Negate::forward(&mut true, &mut x);
+}
+
+#[test]
+fn test_lifetimes() {
+    rfn!(Shift<'a, 'b: 'a>, (x: &'a mut i32, y: &'b mut i32), {
+        *x += *y;
+        *y -= 1;
+    });
+
+    let mut x = 10;
+    let mut y = 3;
+
+    Shift::forward(&mut x, &mut y);
+
+    assert_eq!(x, 13);
+    assert_eq!(y, 2);
+
+    Shift::backwards(&mut x, &mut y);
+
+    assert_eq!(x, 10);
+    assert_eq!(y, 3);
+}
+
+#[test]
+fn test_gray_code() {
+    rfn!(GrayCount, (x: &mut u8, n: &mut usize), {
+        let mut i = 0;
+        rloop!(
+            i == 0,
+            {
+                gray_inc!(*x);
+                i += 1;
+            },
+            i == *n
+        );
+        delocal!(i, *n);
+    });
+
+    let mut x = 0;
+    let mut n = 300;
+
+    GrayCount::forward(&mut x, &mut n);
+
+    // 300 steps wraps around the 256 codes of a u8 to 44.
+    assert_eq!(x, 44 ^ (44 >> 1));
+
+    GrayCount::backwards(&mut x, &mut n);
+
+    assert_eq!(x, 0);
+
+    let mut x = 0;
+    let mut previous = 0u8;
+    for _ in 0..255 {
+        let mut one = 1;
+        GrayCount::forward(&mut x, &mut one);
+        assert_eq!((x ^ previous).count_ones(), 1);
+        previous = x;
+    }
+}
+
+#[test]
+fn test_to_digits() {
+    rfn!(Digits, (num: &mut u64, digits: &mut [u64], base: &mut u64), {
+        to_digits!(*num, digits, *base);
+    });
+
+    let mut num = 12345;
+    let mut digits = [0; 8];
+    let mut base = 10;
+
+    Digits::forward(&mut num, &mut digits, &mut base);
+
+    assert_eq!(num, 0);
+    assert_eq!(digits, [5, 4, 3, 2, 1, 0, 0, 0]);
+
+    Digits::backwards(&mut num, &mut digits, &mut base);
+
+    assert_eq!(num, 12345);
+    assert_eq!(digits, [0; 8]);
+
+    let mut digits = [0; 3];
+
+    Digits::forward(&mut num, &mut digits, &mut base);
+
+    assert_eq!(num, 12);
+    assert_eq!(digits, [5, 4, 3]);
+
+    Digits::backwards(&mut num, &mut digits, &mut base);
+
+    assert_eq!(num, 12345);
+    assert_eq!(digits, [0; 3]);
+}
+
+#[test]
+fn test_const_mask() {
+    const MASK: u32 = 0xFF00;
+
+    rfn!(Mask, (x: &mut u32, y: &mut u32), {
+        *x ^= MASK;
+        *y ^= MASK | 0x0F;
+        *y += 0x10;
+    });
+
+    let mut x = 0x1234;
+    let mut y = 0xABCD;
+
+    Mask::forward(&mut x, &mut y);
+
+    assert_eq!(x, 0xED34);
+    assert_eq!(y, 0x54D2);
+
+    Mask::backwards(&mut x, &mut y);
+
+    assert_eq!(x, 0x1234);
+    assert_eq!(y, 0xABCD);
+}
+
+#[test]
+fn test_reversible_block() {
+    let mut a = 1;
+    let mut b = 2;
+
+    {
+        let (a, b) = (&mut a, &mut b);
+        drop(reversible! {
+            *a += 1;
+            *b += 2;
+        });
+    }
+
+    assert_eq!(a, 2);
+    assert_eq!(b, 4);
+
+    let undo = reversible! {
+        a += 1;
+        b += a;
+    };
+    undo.undo();
+
+    assert_eq!(a, 2);
+    assert_eq!(b, 4);
+}
+
+#[test]
+fn test_sub_borrow() {
+    rfn!(Sub, (lo: &mut u32, hi: &mut u32, e: &mut u32), {
+        sub_borrow!(*lo, *hi, *e);
+    });
+
+    let cases = [
+        (1000, 7, 400),
+        (5, 7, 6),
+        (0, 0, 1),
+        (u32::MAX, u32::MAX, u32::MAX),
+        (0, 1, u32::MAX),
+    ];
+
+    for (l, h, d) in cases {
+        let (mut lo, mut hi, mut e) = (l, h, d);
+        let before = ((h as u64) << 32) | l as u64;
+
+        Sub::forward(&mut lo, &mut hi, &mut e);
+
+        let after = ((hi as u64) << 32) | lo as u64;
+        assert_eq!(after, before.wrapping_sub(d as u64));
+
+        Sub::backwards(&mut lo, &mut hi, &mut e);
+
+        assert_eq!((lo, hi, e), (l, h, d));
+    }
+}
+
+#[test]
+fn test_skip() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    rfn!(Skip, (a: &mut i32), {
+        *a += 1;
+        #[rrust::skip]
+        println!("a is {}", a);
+        #[rrust::skip]
+        CALLS.fetch_add(1, Ordering::SeqCst);
+    });
+
+    let mut a = 1;
+
+    Skip::forward(&mut a);
+
+    assert_eq!(a, 2);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    Skip::backwards(&mut a);
+
+    assert_eq!(a, 1);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_reverse_links() {
+    rfn!(Reverse, (next: &mut [usize], head: &mut usize), {
+        reverse_links!(next, *head);
+    });
+
+    fn walk(next: &[usize], head: usize) -> Vec<usize> {
+        let mut nodes = Vec::new();
+        let mut node = head;
+        while node != next.len() {
+            nodes.push(node);
+            node = next[node];
+        }
+        nodes
+    }
+
+    let mut next = [9, 8, 5, 7, 6, 1, 10, 0, 4, 2];
+    let mut head = 3;
+
+    assert_eq!(walk(&next, head), [3, 7, 0, 9, 2, 5, 1, 8, 4, 6]);
+
+    Reverse::forward(&mut next, &mut head);
+
+    assert_eq!(head, 6);
+    assert_eq!(walk(&next, head), [6, 4, 8, 1, 5, 2, 9, 0, 7, 3]);
+
+    Reverse::backwards(&mut next, &mut head);
+
+    assert_eq!(head, 3);
+    assert_eq!(next, [9, 8, 5, 7, 6, 1, 10, 0, 4, 2]);
+}
+
+#[test]
+fn test_masked_add() {
+    rfn!(Add, (dst: &mut [u64], src: &[u64], mask: &[bool]), {
+        masked_add!(dst, src, mask);
+    });
+
+    let original: Vec<u64> = (0..128).map(|i| i * 3).collect();
+    let src: Vec<u64> = (0..128).map(|i| i * i).collect();
+    let mask: Vec<bool> = (0..128).map(|i| i % 3 == 0 || i % 7 == 0).collect();
+    let mut dst = original.clone();
+
+    Add::forward(&mut dst, &src, &mask);
+
+    for i in 0..128 {
+        if mask[i] {
+            assert_eq!(dst[i], original[i] + src[i]);
+        } else {
+            assert_eq!(dst[i], original[i]);
+        }
+    }
+
+    Add::backwards(&mut dst, &src, &mask);
+
+    assert_eq!(dst, original);
+}
+
+#[test]
+fn test_unary_rhs() {
+    rfn!(Negated, (a: &mut i32, b: &mut i32), {
+        *a += -(*b);
+        *b ^= !0;
+    });
+
+    let mut a = 10;
+    let mut b = 3;
+
+    Negated::forward(&mut a, &mut b);
+
+    assert_eq!(a, 7);
+    assert_eq!(b, !3);
+
+    Negated::backwards(&mut a, &mut b);
+
+    assert_eq!(a, 10);
+    assert_eq!(b, 3);
+}
+
+#[test]
+fn test_feistel_round() {
+    fn round(x: u32) -> u32 {
+        x.wrapping_mul(0x9e37_79b9).rotate_left(7) ^ 0xdead_beef
+    }
+
+    rfn!(Encrypt, (left: &mut u32, right: &mut u32), {
+        feistel_round!(*left, *right, round);
+        feistel_round!(*left, *right, |x: u32| x.rotate_right(11) ^ 0x1234_5678);
+        feistel_round!(*left, *right, round);
+        feistel_round!(*left, *right, |x: u32| x.wrapping_add(0x0bad_f00d));
+    });
+
+    let mut left = 0x0123_4567;
+    let mut right = 0x89ab_cdef;
+
+    Encrypt::forward(&mut left, &mut right);
+
+    assert_ne!((left, right), (0x0123_4567, 0x89ab_cdef));
+
+    Encrypt::backwards(&mut left, &mut right);
+
+    assert_eq!((left, right), (0x0123_4567, 0x89ab_cdef));
+}
+
+#[test]
+fn test_read_only() {
+    rfn!(Check, (x: &i32, range: &std::ops::Range<i32>), {
+        rassert!(range.contains(x));
+        assert!(*x % 2 == 0);
+    });
+
+    Check::forward(&4, &(0..10));
+    Check::backwards(&4, &(0..10));
+}
+
+#[test]
+#[should_panic]
+fn test_read_only_violated() {
+    rfn!(Check, (x: &i32, range: &std::ops::Range<i32>), {
+        rassert!(range.contains(x));
+    });
+
+    Check::backwards(&12, &(0..10));
+}
+
+#[test]
+fn test_counting_distribute() {
+    rfn!(Distribute, (out: &mut [u32], input: &mut [u32], offsets: &mut [usize], keys: &[usize]), {
+        counting_distribute!(out, input, offsets, keys);
+    });
+
+    let original: Vec<u32> = (0..32).map(|i| (i * 37 + 11) % 101).collect();
+    let keys: Vec<usize> = original.iter().map(|v| (*v % 5) as usize).collect();
+
+    let mut starts = vec![0; 5];
+    for k in &keys {
+        starts[*k] += 1;
+    }
+    let mut sum = 0;
+    for start in starts.iter_mut() {
+        let count = *start;
+        *start = sum;
+        sum += count;
+    }
+
+    let mut out = vec![0; 32];
+    let mut input = original.clone();
+    let mut offsets = starts.clone();
+
+    Distribute::forward(&mut out, &mut input, &mut offsets, &keys);
+
+    let mut sorted = original.clone();
+    sorted.sort_by_key(|v| v % 5);
+    assert_eq!(out, sorted);
+    assert_eq!(input, vec![0; 32]);
+    assert_eq!(offsets[4], 32);
+
+    Distribute::backwards(&mut out, &mut input, &mut offsets, &keys);
+
+    assert_eq!(input, original);
+    assert_eq!(out, vec![0; 32]);
+    assert_eq!(offsets, starts);
+}
+
+#[test]
+fn test_implicit_delocal() {
+    rfn!(Step, (a: &mut i32, b: &mut i32), {
+        let step = 3;
+        *a += step;
+        rif!(
+            *a > 5,
+            {
+                let step = -2;
+                *b += step;
+            },
+            *a > 5
+        );
+    });
+
+    let mut a = 4;
+    let mut b = 0;
+
+    Step::forward(&mut a, &mut b);
+
+    assert_eq!((a, b), (7, -2));
+
+    Step::backwards(&mut a, &mut b);
+
+    assert_eq!((a, b), (4, 0));
+}
+
+#[test]
+fn test_horner() {
+    rfn!(Eval, (acc: &mut i64, coeffs: &[i64], x: &i64), {
+        horner!(*acc, coeffs, *x);
+    });
+
+    let coeffs = [7, -3, 0, 5, 2, -1];
+
+    for x in [-4i64, -1, 1, 2, 3, 10] {
+        let expected: i64 = coeffs
+            .iter()
+            .enumerate()
+            .map(|(i, c)| c * x.pow(i as u32))
+            .sum();
+
+        let mut acc = 0;
+
+        Eval::forward(&mut acc, &coeffs, &x);
+
+        assert_eq!(acc, expected);
+
+        Eval::backwards(&mut acc, &coeffs, &x);
+
+        assert_eq!(acc, 0);
+    }
+
+    let mut acc = 9;
+
+    Eval::forward(&mut acc, &coeffs, &2);
+    Eval::backwards(&mut acc, &coeffs, &2);
+
+    assert_eq!(acc, 9);
+}
+
+#[test]
+#[cfg(not(feature = "unchecked"))]
+#[should_panic(expected = "Lefthand and righthand are aliases of each other")]
+fn test_alias_mem_swap() {
+    rfn!(Swap, (x1: &mut i32, x2: &mut i32), {
+        std::mem::swap(x1, x2);
+    });
+
+    let mut x = 1;
+    let p: *mut i32 = &mut x;
+
+    unsafe {
+        Swap::forward(&mut *p, &mut *p);
+    }
+}
+
+#[test]
+fn test_bit_reverse_permute() {
+    rfn!(Permute, (arr: &mut [u8]), {
+        bit_reverse_permute!(arr, 4);
+    });
+
+    let original: Vec<u8> = (0..16).map(|i| i * 3 + 1).collect();
+    let mut arr = original.clone();
+
+    Permute::forward(&mut arr);
+
+    for (i, v) in arr.iter().enumerate() {
+        let j = (i as u8).reverse_bits() >> 4;
+        assert_eq!(*v, original[j as usize]);
+    }
+
+    Permute::forward(&mut arr);
+
+    assert_eq!(arr, original);
+
+    Permute::forward(&mut arr);
+    Permute::backwards(&mut arr);
+
+    assert_eq!(arr, original);
+}
+
+#[test]
+#[should_panic]
+fn test_bit_reverse_permute_length() {
+    rfn!(Permute, (arr: &mut [u8]), {
+        bit_reverse_permute!(arr, 3);
+    });
+
+    Permute::forward(&mut [0; 12]);
+}
+
+#[test]
+fn test_range_assign_op() {
+    rfn!(AddRange, (arr: &mut [i32], x: &mut i32), {
+        arr[2..5] += *x;
+        arr[..2] ^= 0b101;
+        arr[7..] -= *x * 2;
+    });
+
+    let mut arr = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+    let mut x = 10;
+
+    AddRange::forward(&mut arr, &mut x);
+
+    assert_eq!(arr, [5, 4, 12, 13, 14, 5, 6, -13, -12]);
+
+    AddRange::backwards(&mut arr, &mut x);
+
+    assert_eq!(arr, [0, 1, 2, 3, 4, 5, 6, 7, 8]);
+}
+
+#[test]
+#[should_panic]
+fn test_range_assign_op_out_of_bounds() {
+    rfn!(AddRange, (arr: &mut [i32], x: &mut i32), {
+        arr[2..5] += *x;
+    });
+
+    AddRange::forward(&mut [0; 4], &mut 1);
+}
+
+#[test]
+fn test_isqrt_into() {
+    rfn!(Sqrt, (n: &mut u64, root: &mut u64, rem: &mut u64), {
+        isqrt_into!(*n, *root, *rem);
+    });
+
+    for value in [0, 1, 2, 3, 4, 15, 16, 17, 99, 100, 12345, 1 << 20] {
+        let mut n = value;
+        let mut root = 0;
+        let mut rem = 0;
+
+        Sqrt::forward(&mut n, &mut root, &mut rem);
+
+        assert_eq!(n, 0);
+        assert!(root * root <= value && (root + 1) * (root + 1) > value);
+        assert_eq!(root * root + rem, value);
+
+        Sqrt::backwards(&mut n, &mut root, &mut rem);
+
+        assert_eq!((n, root, rem), (value, 0, 0));
+    }
+}
+
+#[test]
+fn test_unsafe_block() {
+    rfn!(Unchecked, (arr: &mut [i32], x: &mut i32), {
+        unsafe {
+            *arr.get_unchecked_mut(1) += *x;
+            *arr.get_unchecked_mut(0) -= *arr.get_unchecked(1);
+        }
+    });
+
+    let mut arr = [1, 2];
+    let mut x = 3;
+
+    Unchecked::forward(&mut arr, &mut x);
+
+    assert_eq!(arr, [-4, 5]);
+
+    Unchecked::backwards(&mut arr, &mut x);
+
+    assert_eq!(arr, [1, 2]);
+}
+
+#[test]
+fn test_checksum_frame() {
+    rfn!(Frame, (payload: &[u8], trailer: &mut u8), {
+        checksum_frame!(payload, *trailer);
+    });
+
+    let payload = b"reversible frames";
+    let mut trailer = 0;
+
+    Frame::forward(payload, &mut trailer);
+
+    assert_eq!(
+        trailer,
+        payload.iter().map(|b| *b as u32).sum::<u32>() as u8
+    );
+
+    Frame::backwards(payload, &mut trailer);
+
+    assert_eq!(trailer, 0);
+}
+
+#[test]
+#[should_panic(expected = "Checksum does not match the payload")]
+fn test_checksum_frame_corrupted() {
+    rfn!(Frame, (payload: &[u8], trailer: &mut u8), {
+        checksum_frame!(payload, *trailer);
+    });
+
+    let mut payload = *b"reversible frames";
+    let mut trailer = 0;
+
+    Frame::forward(&payload, &mut trailer);
+
+    payload[3] ^= 0x10;
+
+    Frame::backwards(&payload, &mut trailer);
+}
+
+#[test]
+fn test_rfor() {
+    rfn!(Prefix, (arr: &mut [i64]), {
+        rfor!(i in 1..arr.len(), {
+            arr[i] += arr[i - 1];
+        });
+    });
+
+    let mut arr = [3, 1, 4, 1, 5, 9, 2, 6];
+
+    Prefix::forward(&mut arr);
+
+    assert_eq!(arr, [3, 4, 8, 9, 14, 23, 25, 31]);
+
+    Prefix::backwards(&mut arr);
+
+    assert_eq!(arr, [3, 1, 4, 1, 5, 9, 2, 6]);
+}
+
+#[test]
+fn test_rle() {
+    rfn!(Encode, (input: &mut [u8], runs: &mut [(u8, usize)]), {
+        rle_encode!(input, runs);
+    });
+
+    let original = *b"aaaabccddddddeaab";
+    let mut input = original;
+    let mut runs = [(0, 0); 17];
+
+    Encode::forward(&mut input, &mut runs);
+
+    assert_eq!(input, [0; 17]);
+    assert_eq!(
+        runs[..8],
+        [
+            (b'a', 4),
+            (b'b', 1),
+            (b'c', 2),
+            (b'd', 6),
+            (b'e', 1),
+            (b'a', 2),
+            (b'b', 1),
+            (0, 0)
+        ]
+    );
+
+    Encode::backwards(&mut input, &mut runs);
+
+    assert_eq!(input, original);
+    assert_eq!(runs, [(0, 0); 17]);
+}
+
+#[test]
+#[should_panic(expected = "Not enough room for the runs")]
+fn test_rle_no_room() {
+    rfn!(Encode, (input: &mut [u8], runs: &mut [(u8, usize)]), {
+        rle_encode!(input, runs);
+    });
+
+    let mut input = *b"abc";
+
+    Encode::forward(&mut input, &mut [(0, 0); 2]);
+}
+
+#[test]
+fn test_reverse_order() {
+    #[derive(Default)]
+    struct Trace {
+        stack: Vec<usize>,
+        calls: Vec<(&'static str, usize)>,
+    }
+
+    type Step = Box<dyn Reversible<Trace>>;
+
+    rfn!(Pipeline, (steps: &mut [Step], trace: &mut Trace), {
+        reverse_order!(steps, *trace);
+    });
+
+    let mut steps: Vec<Step> = (0..5)
+        .map(|k| -> Step {
+            Box::new((
+                move |trace: &mut Trace| {
+                    trace.stack.push(k);
+                    trace.calls.push(("forward", k));
+                },
+                move |trace: &mut Trace| {
+                    assert_eq!(trace.stack.pop(), Some(k));
+                    trace.calls.push(("backwards", k));
+                },
+            ))
+        })
+        .collect();
+    let mut trace = Trace::default();
+
+    Pipeline::forward(&mut steps, &mut trace);
+
+    assert_eq!(trace.stack, [0, 1, 2, 3, 4]);
+
+    Pipeline::backwards(&mut steps, &mut trace);
+
+    assert!(trace.stack.is_empty());
+    assert_eq!(
+        trace.calls,
+        [
+            ("forward", 0),
+            ("forward", 1),
+            ("forward", 2),
+            ("forward", 3),
+            ("forward", 4),
+            ("backwards", 4),
+            ("backwards", 3),
+            ("backwards", 2),
+            ("backwards", 1),
+            ("backwards", 0),
+        ]
+    );
+}
+
+#[test]
+fn test_cell_add() {
+    use std::cell::{Cell, RefCell};
+
+    rfn!(Shared, (a: &Cell<i32>, b: &RefCell<i32>, x: &mut i32), {
+        cell_add!(*a, *x * 2);
+        *b.borrow_mut() += a.get();
+    });
+
+    let a = Cell::new(5);
+    let b = RefCell::new(1);
+    let mut x = 3;
+
+    Shared::forward(&a, &b, &mut x);
+
+    assert_eq!(a.get(), 11);
+    assert_eq!(*b.borrow(), 12);
+
+    Shared::backwards(&a, &b, &mut x);
+
+    assert_eq!(a.get(), 5);
+    assert_eq!(*b.borrow(), 1);
+}
+
+#[test]
+fn test_stack() {
+    rfn!(PushAll, (stack: &mut [u32], top: &mut usize, values: &[u32]), {
+        rfor!(i in 0..values.len(), {
+            spush!(stack, *top, values[i]);
+        });
+    });
+
+    rfn!(PopAll, (stack: &mut [u32], top: &mut usize, values: &[u32]), {
+        rfor!(i in 0..values.len(), {
+            spop!(stack, *top, values[values.len() - 1 - i]);
+        });
+    });
+
+    let values = [3, 1, 4, 1, 5];
+    let mut stack = [0; 8];
+    let mut top = 0;
+
+    PushAll::forward(&mut stack, &mut top, &values);
+
+    assert_eq!(top, 5);
+    assert_eq!(stack, [3, 1, 4, 1, 5, 0, 0, 0]);
+
+    PopAll::forward(&mut stack, &mut top, &values);
+
+    assert_eq!((stack, top), ([0; 8], 0));
+
+    PopAll::backwards(&mut stack, &mut top, &values);
+    PushAll::backwards(&mut stack, &mut top, &values);
+
+    assert_eq!((stack, top), ([0; 8], 0));
+}
+
+#[test]
+#[should_panic(expected = "Stack overflow")]
+fn test_stack_overflow() {
+    rfn!(Push, (stack: &mut [u32], top: &mut usize), {
+        spush!(stack, *top, 1);
+    });
+
+    let mut stack = [1, 2];
+    let mut top = 2;
+
+    Push::forward(&mut stack, &mut top);
+}
+
+#[test]
+fn test_butterfly() {
+    type Complex = (i64, i64);
+
+    rfn!(Stage, (lo: &mut [Complex], hi: &mut [Complex], twiddles: &[Complex]), {
+        rfor!(k in 0..lo.len(), {
+            butterfly!(lo[k], hi[k], twiddles[k]);
+        });
+    });
+
+    let original_lo = [(1, 0), (2, -1), (0, 3), (-4, 2)];
+    let original_hi = [(5, 5), (-1, 0), (7, -3), (2, 2)];
+    let twiddles = [(1, 0), (0, -1), (-1, 0), (3, 4)];
+    let mut lo = original_lo;
+    let mut hi = original_hi;
+
+    Stage::forward(&mut lo, &mut hi, &twiddles);
+
+    assert_eq!(lo, [(6, 5), (2, 0), (-7, 6), (-6, 16)]);
+    assert_eq!(hi, [(-4, -5), (2, -2), (7, 0), (-2, -12)]);
+
+    Stage::backwards(&mut lo, &mut hi, &twiddles);
+
+    assert_eq!(lo, original_lo);
+    assert_eq!(hi, original_hi);
+}
+
+#[test]
+fn test_butterfly_in_place() {
+    type Complex = (i64, i64);
+
+    rfn!(FirstStage, (x: &mut [Complex; 8]), {
+        bit_reverse_permute!(*x, 3);
+        rfor!(k in 0..4, {
+            butterfly!(x[2 * k], x[2 * k + 1], (1, 0));
+        });
+    });
+
+    let original: [Complex; 8] = [
+        (1, 0),
+        (2, -1),
+        (0, 3),
+        (-4, 2),
+        (5, 5),
+        (-1, 0),
+        (7, -3),
+        (2, 2),
+    ];
+    let mut x = original;
+
+    FirstStage::forward(&mut x);
+
+    // After the permutation the pairs are the inputs 0 and 4, 2 and 6,
+    // 1 and 5 and 3 and 7.
+    assert_eq!(x[..4], [(6, 5), (-4, -5), (7, 0), (-7, 6)]);
+
+    FirstStage::backwards(&mut x);
+
+    assert_eq!(x, original);
+}
+
+#[test]
+#[should_panic(expected = "Lefthand and righthand are aliases of each other")]
+fn test_butterfly_alias() {
+    rfn!(Same, (x: &mut [(i64, i64)], i: &usize), {
+        butterfly!(x[*i], x[*i], (1, 0));
+    });
+
+    Same::forward(&mut [(1, 2)], &0);
+}
+
+#[test]
+fn test_pure_macro() {
+    rfn!(Checked, (x: &mut i32, y: &i32), {
+        assert_eq!(*y % 2, 0);
+        *x += *y;
+        assert!(*x >= *y, "x must not be negative");
+    });
+
+    let mut x = 1;
+
+    Checked::forward(&mut x, &4);
+
+    assert_eq!(x, 5);
+
+    Checked::backwards(&mut x, &4);
+
+    assert_eq!(x, 1);
+}
+
+#[test]
+fn test_mul_mod_unit() {
+    rfn!(Scale, (a: &mut u64, k: &u64), {
+        mul_mod_unit!(*a, *k, 101);
+        mul_mod_unit!(*a, 45, 101);
+    });
+
+    for value in [0, 1, 2, 50, 99, 100] {
+        let mut a = value;
+
+        Scale::forward(&mut a, &7);
+
+        assert_eq!(a, value * 7 * 45 % 101);
+
+        Scale::backwards(&mut a, &7);
+
+        assert_eq!(a, value);
+    }
+}
+
+#[test]
+#[should_panic(expected = "Factor is not a unit modulo the modulus")]
+fn test_mul_mod_unit_not_unit() {
+    rfn!(Scale, (a: &mut u64), {
+        mul_mod_unit!(*a, 6, 9);
+    });
+
+    Scale::forward(&mut 4);
+}
+
+#[test]
+#[should_panic(expected = "Value is out of modular range")]
+fn test_mul_mod_unit_negative() {
+    rfn!(Scale, (a: &mut i64), {
+        mul_mod_unit!(*a, 3, 7);
+    });
+
+    Scale::forward(&mut -1);
+}
+
+#[test]
+fn test_rswap_tuple_fields() {
+    rfn!(SwapFields, (t: &mut (i32, i32), pair: &mut (u8, (u8, u8))), {
+        rswap!(t.0, t.1);
+        rswap!(pair.1 .0, pair.1 .1);
+        t.0 += 10;
+    });
+
+    let mut t = (1, 2);
+    let mut pair = (0, (3, 4));
+
+    SwapFields::forward(&mut t, &mut pair);
+
+    assert_eq!(t, (12, 1));
+    assert_eq!(pair, (0, (4, 3)));
+
+    SwapFields::backwards(&mut t, &mut pair);
+
+    assert_eq!(t, (1, 2));
+    assert_eq!(pair, (0, (3, 4)));
+}
+
+#[test]
+fn test_rswap_literal_indices() {
+    rfn!(Shuffle, (arr: &mut [i32], other: &mut [i32; 2]), {
+        rswap!(arr[0], arr[2]);
+        rswap!(arr[1], other[1]);
+        rswap!(other[0], arr[0]);
+    });
+
+    let mut arr = [1, 2, 3];
+    let mut other = [4, 5];
+
+    Shuffle::forward(&mut arr, &mut other);
+
+    assert_eq!(arr, [4, 5, 1]);
+    assert_eq!(other, [3, 2]);
+
+    Shuffle::backwards(&mut arr, &mut other);
+
+    assert_eq!(arr, [1, 2, 3]);
+    assert_eq!(other, [4, 5]);
+}
+
+#[test]
+fn test_rswap_same_literal_index() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("src/tests/rswap_same_literal.rs");
+}
+
+#[test]
+fn test_toggle() {
+    rfn!(Toggle, (flag: &mut bool), {
+        toggle!(*flag);
+    });
+
+    rfn!(ToggleTwice, (flag: &mut bool), {
+        toggle!(*flag);
+        toggle!(*flag);
+    });
+
+    let mut flag = true;
+
+    ToggleTwice::forward(&mut flag);
+
+    assert!(flag);
+
+    Toggle::forward(&mut flag);
+
+    assert!(!flag);
+
+    Toggle::backwards(&mut flag);
+
+    assert!(flag);
+}
+
+#[test]
+#[allow(clippy::ptr_arg)]
+fn test_vec_index() {
+    rfn!(Mix, (v: &mut Vec<i32>, i: &usize), {
+        v[*i] += v[0];
+        v[1] -= v[*i] * 2;
+        v[0] ^= v[1];
+    });
+
+    let mut v = vec![3, 5, 7];
+
+    Mix::forward(&mut v, &2);
+
+    assert_eq!(v, vec![3 ^ -15, -15, 10]);
+
+    Mix::backwards(&mut v, &2);
+
+    assert_eq!(v, vec![3, 5, 7]);
+}
+
+#[test]
+#[cfg(not(feature = "unchecked"))]
+#[should_panic(expected = "Lefthand and righthand are aliases of each other")]
+#[allow(clippy::ptr_arg)]
+fn test_alias_vec() {
+    rfn!(Alias, (v: &mut Vec<i32>, i: &usize), {
+        v[1] -= v[*i];
+    });
+
+    Alias::forward(&mut vec![1, 2, 3], &1);
+}
+
+#[test]
+fn test_gcd_reversible() {
+    rfn!(Gcd, (a: &mut u64, b: &mut u64, steps: &mut [u8; 64]), {
+        gcd_reversible!(*a, *b, *steps);
+    });
+
+    for (x, y, gcd) in [(12, 18, 6), (7, 7, 7), (1, 30, 1), (48, 36, 12), (17, 5, 1)] {
+        let mut a = x;
+        let mut b = y;
+        let mut steps = [0; 64];
+
+        Gcd::forward(&mut a, &mut b, &mut steps);
+
+        assert_eq!((a, b), (gcd, gcd));
+
+        Gcd::backwards(&mut a, &mut b, &mut steps);
+
+        assert_eq!((a, b), (x, y));
+        assert_eq!(steps, [0; 64]);
+    }
+}
+
+#[test]
+fn test_sift_up() {
+    rfn!(Push, (heap: &mut [u32; 10], len: &mut usize, value: &mut u32, path: &mut [usize; 3]), {
+        rswap!(heap[*len], *value);
+        sift_up!(*heap, *len, *path);
+        *len += 1;
+    });
+
+    let mut heap = [9, 5, 8, 1, 3, 4, 0, 0, 0, 0];
+    let mut len = 6;
+
+    for (value, expected) in [
+        (7, [9, 5, 8, 1, 3, 4, 7, 0, 0, 0]),
+        (10, [10, 9, 8, 5, 3, 4, 7, 1, 0, 0]),
+        (6, [10, 9, 8, 6, 3, 4, 7, 1, 5, 0]),
+    ] {
+        let before = heap;
+        let mut value = value;
+        let mut path = [0; 3];
+
+        Push::forward(&mut heap, &mut len, &mut value, &mut path);
+
+        assert_eq!(heap, expected);
+        assert_eq!(value, 0);
+
+        Push::backwards(&mut heap, &mut len, &mut value, &mut path);
+
+        assert_eq!(heap, before);
+        assert_eq!(path, [0; 3]);
+
+        Push::forward(&mut heap, &mut len, &mut value, &mut path);
+    }
+
+    assert_eq!(len, 9);
+}
+
+#[test]
+fn test_with_scratch() {
+    rfn!(Cube, (s: &mut i64, x: &i64), {
+        *s += *x * *x * *x;
+    });
+
+    rfn!(AddCubes, (a: &mut i64, b: &mut i64, x: &i64), {
+        with_scratch!(s = 0, {
+            Cube::forward(&mut s, x);
+            *a += s;
+            *b -= s;
+            Cube::backwards(&mut s, x);
+        });
+    });
+
+    let mut a = 1;
+    let mut b = 2;
+
+    AddCubes::forward(&mut a, &mut b, &3);
+
+    assert_eq!((a, b), (28, -25));
+
+    AddCubes::backwards(&mut a, &mut b, &3);
+
+    assert_eq!((a, b), (1, 2));
+}
+
+#[test]
+#[should_panic(expected = "Delocal failed 27 != 0")]
+fn test_with_scratch_not_restored() {
+    rfn!(Cube, (s: &mut i64, x: &i64), {
+        *s += *x * *x * *x;
+    });
+
+    rfn!(AddCube, (a: &mut i64, x: &i64), {
+        with_scratch!(s = 0, {
+            Cube::forward(&mut s, x);
+            *a += s;
+        });
+    });
+
+    AddCube::forward(&mut 1, &3);
+}
+
+#[test]
+fn test_nibbles() {
+    rfn!(Pack, (out: &mut [u8], nibbles: &mut [u8]), {
+        rfor!(i in 0..out.len(), {
+            pack_nibbles!(out[i], nibbles[2 * i], nibbles[2 * i + 1]);
+        });
+    });
+
+    rfn!(Unpack, (out: &mut [u8], nibbles: &mut [u8]), {
+        rfor!(i in 0..out.len(), {
+            unpack_nibbles!(out[i], nibbles[2 * i], nibbles[2 * i + 1]);
+        });
+    });
+
+    let original = [0x12, 0xab, 0xf0, 0x07];
+    let mut out = original;
+    let mut nibbles = [0; 8];
+
+    Unpack::forward(&mut out, &mut nibbles);
+
+    assert_eq!(out, [0; 4]);
+    assert_eq!(nibbles, [0x1, 0x2, 0xa, 0xb, 0xf, 0x0, 0x0, 0x7]);
+
+    Pack::forward(&mut out, &mut nibbles);
+
+    assert_eq!(out, original);
+    assert_eq!(nibbles, [0; 8]);
+
+    Pack::backwards(&mut out, &mut nibbles);
+    Unpack::backwards(&mut out, &mut nibbles);
+
+    assert_eq!(out, original);
+    assert_eq!(nibbles, [0; 8]);
+}
+
+#[test]
+#[should_panic(expected = "Value is not a nibble")]
+fn test_nibbles_too_large() {
+    rfn!(Pack, (out: &mut u8, hi: &mut u8, lo: &mut u8), {
+        pack_nibbles!(*out, *hi, *lo);
+    });
+
+    Pack::forward(&mut 0, &mut 16, &mut 0);
+}
+
+#[test]
+fn test_generic_slice_kernel() {
+    rfn!(AddSlices<T: Copy + core::ops::AddAssign + core::ops::SubAssign>, (a: &mut [T], b: &[T]), {
+        rfor!(i in 0..a.len(), {
+            a[i] += b[i];
+        });
+    });
+
+    let mut a = [1, 2, 3];
+
+    AddSlices::forward(&mut a, &[10, 20, 30]);
+
+    assert_eq!(a, [11, 22, 33]);
+
+    AddSlices::backwards(&mut a, &[10, 20, 30]);
+
+    assert_eq!(a, [1, 2, 3]);
+
+    let mut a = [0.5, 1.5];
+
+    AddSlices::forward(&mut a[..], &[0.25, 2.0]);
+
+    assert_eq!(a, [0.75, 3.5]);
+
+    AddSlices::backwards(&mut a[..], &[0.25, 2.0]);
+
+    assert_eq!(a, [0.5, 1.5]);
+}
+
+#[test]
+fn test_to_bcd() {
+    rfn!(Bcd, (value: &mut u64, bcd: &mut [u8; 4]), {
+        to_bcd!(*value, *bcd);
+    });
+
+    for (original, expected) in [
+        (0, [0, 0, 0, 0]),
+        (7, [0x07, 0, 0, 0]),
+        (1234, [0x34, 0x12, 0, 0]),
+        (10203040, [0x40, 0x30, 0x20, 0x10]),
+        (99999999, [0x99; 4]),
+    ] {
+        let mut value = original;
+        let mut bcd = [0; 4];
+
+        Bcd::forward(&mut value, &mut bcd);
+
+        assert_eq!(value, 0);
+        assert_eq!(bcd, expected);
+
+        Bcd::backwards(&mut value, &mut bcd);
+
+        assert_eq!(value, original);
+        assert_eq!(bcd, [0; 4]);
+    }
+}
+
+#[test]
+#[should_panic(expected = "Value does not fit in the BCD digits")]
+fn test_to_bcd_too_large() {
+    rfn!(Bcd, (value: &mut u64, bcd: &mut [u8; 2]), {
+        to_bcd!(*value, *bcd);
+    });
+
+    Bcd::forward(&mut 10000, &mut [0; 2]);
+}
+
+#[test]
+fn test_sorted_insert() {
+    rfn!(InsertAll, (arr: &mut [i32; 6], len: &mut usize, xs: &mut [i32; 4], pos: &mut [usize; 4]), {
+        rfor!(i in 0..xs.len(), {
+            sorted_insert!(*arr, *len, xs[i], pos[i]);
+        });
+    });
+
+    let mut arr = [0; 6];
+    let mut len = 0;
+    let mut xs = [5, 1, 3, 5];
+    let mut pos = [0; 4];
+
+    InsertAll::forward(&mut arr, &mut len, &mut xs, &mut pos);
+
+    assert_eq!(arr, [1, 3, 5, 5, 0, 0]);
+    assert_eq!(len, 4);
+    assert_eq!(xs, [0; 4]);
+    assert_eq!(pos, [0, 0, 1, 3]);
+
+    InsertAll::backwards(&mut arr, &mut len, &mut xs, &mut pos);
+
+    assert_eq!(arr, [0; 6]);
+    assert_eq!(len, 0);
+    assert_eq!(xs, [5, 1, 3, 5]);
+    assert_eq!(pos, [0; 4]);
+}
+
+#[test]
+#[should_panic(expected = "Array is full")]
+fn test_sorted_insert_full() {
+    rfn!(Insert, (arr: &mut [i32; 2], len: &mut usize, x: &mut i32, pos: &mut usize), {
+        sorted_insert!(*arr, *len, *x, *pos);
+    });
+
+    Insert::forward(&mut [1, 2], &mut 2, &mut 3, &mut 0);
+}
+
+#[test]
+fn test_crc_step() {
+    rfn!(Crc8, (reg: &mut u8, bits: &[bool], outs: &mut [bool]), {
+        rfor!(i in 0..bits.len(), {
+            crc_step!(*reg, bits[i], outs[i], 0x07);
+        });
+    });
+
+    let bits: Vec<bool> = b"123456789"
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+        .collect();
+    let mut reg = 0;
+    let mut outs = vec![false; bits.len()];
+
+    Crc8::forward(&mut reg, &bits, &mut outs);
+
+    assert_eq!(reg, 0xf4);
+
+    Crc8::backwards(&mut reg, &bits, &mut outs);
+
+    assert_eq!(reg, 0);
+    assert!(outs.iter().all(|out| !out));
+}
+
+#[test]
+fn test_place() {
+    struct Ring {
+        cells: [i32; 4],
+    }
+
+    impl Ring {
+        fn at_mut(&mut self, i: usize) -> &mut i32 {
+            &mut self.cells[i % 4]
+        }
+    }
+
+    rfn!(Spread, (ring: &mut Ring, x: &mut i32), {
+        place!(ring.at_mut(5)) += *x;
+        place!(ring.at_mut(6)) -= *x * 2;
+        *x ^= place!(ring.at_mut(1));
+    });
+
+    let mut ring = Ring {
+        cells: [1, 2, 3, 4],
+    };
+    let mut x = 3;
+
+    Spread::forward(&mut ring, &mut x);
+
+    assert_eq!(ring.cells, [1, 5, -3, 4]);
+    assert_eq!(x, 3 ^ 5);
+
+    Spread::backwards(&mut ring, &mut x);
+
+    assert_eq!(ring.cells, [1, 2, 3, 4]);
+    assert_eq!(x, 3);
+}
+
+#[test]
+#[cfg(not(feature = "unchecked"))]
+#[should_panic(expected = "Lefthand and righthand are aliases of each other")]
+fn test_place_alias() {
+    struct Ring {
+        cells: [i32; 4],
+    }
+
+    impl Ring {
+        fn at(&self, i: usize) -> &i32 {
+            &self.cells[i % 4]
+        }
+    }
+
+    rfn!(Double, (ring: &mut Ring), {
+        ring.cells[1] += place!(ring.at(5));
+    });
+
+    Double::forward(&mut Ring { cells: [0; 4] });
+}
+
+#[test]
+fn test_cmul_unit() {
+    rfn!(Rotate, (re: &mut i32, im: &mut i32), {
+        cmul_unit!(*re, *im, 0, 1);
+    });
+
+    let mut re = 5;
+    let mut im = -2;
+
+    for _ in 0..4 {
+        Rotate::forward(&mut re, &mut im);
+    }
+
+    assert_eq!((re, im), (5, -2));
+
+    Rotate::forward(&mut re, &mut im);
+
+    assert_eq!((re, im), (2, 5));
+
+    Rotate::backwards(&mut re, &mut im);
+
+    assert_eq!((re, im), (5, -2));
+}
+
+#[test]
+#[should_panic(expected = "Multiplier is not a unit")]
+fn test_cmul_unit_not_unit() {
+    rfn!(Scale, (re: &mut i32, im: &mut i32), {
+        cmul_unit!(*re, *im, 1, 1);
+    });
+
+    Scale::forward(&mut 1, &mut 1);
+}
+
+#[test]
+fn test_debruijn_step() {
+    rfn!(Step, (state: &mut u16), {
+        debruijn_step!(*state, 0xb400);
+    });
+
+    let seed = 0xace1;
+    let mut state = seed;
+    let mut states = vec![state];
+
+    for _ in 0..65535 {
+        Step::forward(&mut state);
+        states.push(state);
+    }
+
+    assert_eq!(state, seed);
+    states.sort_unstable();
+    states.dedup();
+    assert_eq!(states.len(), 65535);
+
+    for _ in 0..3 {
+        Step::forward(&mut state);
+    }
+    for _ in 0..3 {
+        Step::backwards(&mut state);
+    }
+
+    assert_eq!(state, seed);
+
+    Step::backwards(&mut state);
+    Step::forward(&mut state);
+
+    assert_eq!(state, seed);
+}
+
+#[test]
+#[should_panic(expected = "State does not fit in the register")]
+fn test_debruijn_step_too_wide() {
+    rfn!(Step, (state: &mut u8), {
+        debruijn_step!(*state, 0b1001);
+    });
+
+    Step::forward(&mut 0x10);
+}
+
+#[test]
+fn test_atomic_add() {
+    use std::sync::atomic::{AtomicI32, AtomicU8, Ordering};
+
+    rfn!(Shared, (a: &AtomicI32, b: &AtomicU8, x: &mut i32), {
+        atomic_add!(*a, *x * 2);
+        atomic_add!(*b, 200);
+        *x += a.load(Ordering::SeqCst);
+    });
+
+    let a = AtomicI32::new(5);
+    let b = AtomicU8::new(100);
+    let mut x = 3;
+
+    Shared::forward(&a, &b, &mut x);
+
+    assert_eq!(a.load(Ordering::SeqCst), 11);
+    assert_eq!(b.load(Ordering::SeqCst), 44);
+    assert_eq!(x, 14);
+
+    Shared::backwards(&a, &b, &mut x);
+
+    assert_eq!(a.load(Ordering::SeqCst), 5);
+    assert_eq!(b.load(Ordering::SeqCst), 100);
+    assert_eq!(x, 3);
+}
+
+#[test]
+fn test_bwt() {
+    rfn!(Bwt, (data: &mut [u8], index: &mut usize), {
+        bwt_forward!(data, *index);
+    });
+
+    for (original, transformed, primary) in [
+        (&b"banana"[..], &b"nnbaaa"[..], 3),
+        (b"abracadabra", b"rdarcaaaabb", 2),
+        (b"abab", b"bbaa", 0),
+        (b"x", b"x", 0),
+        (b"", b"", 0),
+    ] {
+        let mut data = original.to_vec();
+        let mut index = 0;
+
+        Bwt::forward(&mut data, &mut index);
+
+        assert_eq!((&data[..], index), (transformed, primary));
+
+        Bwt::backwards(&mut data, &mut index);
+
+        assert_eq!((&data[..], index), (original, 0));
+    }
+}
+
+#[test]
+fn test_mixed_direction_calls() {
+    rfn!(Step, (x: &mut i32), {
+        *x += 2;
+        *x ^= 1;
+    });
+
+    rfn!(Mixed, (x: &mut i32, y: &mut i32), {
+        let mut i = 0;
+        rloop!(
+            i == 0,
+            {
+                Step::forward(x);
+                Step::backwards(y);
+                i += 1;
+            },
+            i == 3
+        );
+        delocal!(i, 3);
+    });
+
+    let mut x = 0;
+    let mut y = 0;
+
+    Mixed::forward(&mut x, &mut y);
+
+    assert_eq!((x, y), (7, -5));
+
+    Mixed::backwards(&mut x, &mut y);
+
+    assert_eq!((x, y), (0, 0));
+}
+
+#[test]
+fn test_affine() {
+    rfn!(Permute, (xs: &mut [u64; 26]), {
+        rfor!(i in 0..xs.len(), {
+            affine!(xs[i], 7, 3, 26);
+        });
+    });
+
+    let original: [u64; 26] = core::array::from_fn(|i| i as u64);
+    let mut xs = original;
+
+    Permute::forward(&mut xs);
+
+    let mut sorted = xs;
+    sorted.sort_unstable();
+    assert_eq!(sorted, original);
+    assert_eq!(xs[..4], [3, 10, 17, 24]);
+
+    Permute::backwards(&mut xs);
+
+    assert_eq!(xs, original);
+}
+
+#[test]
+fn test_range_argument() {
+    rfn!(AddRange, (arr: &mut [i32], range: core::ops::Range<usize>, e: &i32), {
+        rfor!(i in range.clone(), {
+            arr[i] += *e;
+        });
+    });
+
+    rfn!(Bump, (arr: &mut [i32]), {
+        AddRange::forward(arr, 1..3, &5);
+        arr[2..4] += 1;
+    });
+
+    let mut arr = [0; 4];
+
+    Bump::forward(&mut arr);
+
+    assert_eq!(arr, [0, 5, 6, 1]);
+
+    Bump::backwards(&mut arr);
+
+    assert_eq!(arr, [0; 4]);
+}
+
+#[test]
+fn test_interleave() {
+    rfn!(Zip, (out: &mut [u32; 128], a: &mut [u32; 64], b: &mut [u32; 64]), {
+        interleave!(*out, *a, *b);
+    });
+
+    rfn!(Unzip, (out: &mut [u32; 128], a: &mut [u32; 64], b: &mut [u32; 64]), {
+        deinterleave!(*out, *a, *b);
+    });
+
+    let original_a: [u32; 64] = core::array::from_fn(|i| i as u32);
+    let original_b: [u32; 64] = core::array::from_fn(|i| 1000 + i as u32);
+    let mut out = [0; 128];
+    let mut a = original_a;
+    let mut b = original_b;
+
+    Zip::forward(&mut out, &mut a, &mut b);
+
+    assert_eq!(out[..6], [0, 1000, 1, 1001, 2, 1002]);
+    assert_eq!(out[127], 1063);
+    assert_eq!((a, b), ([0; 64], [0; 64]));
+
+    Unzip::forward(&mut out, &mut a, &mut b);
+
+    assert_eq!((a, b), (original_a, original_b));
+
+    Unzip::backwards(&mut out, &mut a, &mut b);
+    Zip::backwards(&mut out, &mut a, &mut b);
+
+    assert_eq!(out, [0; 128]);
+    assert_eq!((a, b), (original_a, original_b));
+}
+
+#[test]
+#[should_panic(expected = "Lengths of the slices do not match")]
+fn test_interleave_length() {
+    rfn!(Zip, (out: &mut [u32], a: &mut [u32], b: &mut [u32]), {
+        interleave!(out, a, b);
+    });
+
+    Zip::forward(&mut [0; 5], &mut [1, 2], &mut [3, 4]);
+}
+
+#[test]
+#[should_panic(expected = "Slice to move into does not hold default values")]
+fn test_deinterleave_not_default() {
+    rfn!(Unzip, (out: &mut [u32], a: &mut [u32], b: &mut [u32]), {
+        deinterleave!(out, a, b);
+    });
+
+    Unzip::forward(&mut [1, 3, 2, 4], &mut [0, 0], &mut [0, 5]);
+}
+
+#[test]
+#[cfg(feature = "budget")]
+fn test_budget() {
+    rfn!(Deep, (n: &mut u32, depth: &mut u32), {
+        rif!(
+            *n == 0,
+            {},
+            {
+                *n -= 1;
+                *depth += 1;
+                Deep::forward(n, depth);
+                *n += 1;
+            },
+            *n == 0
+        );
+    });
+
+    let mut n = 100;
+    let mut depth = 0;
+
+    Deep::forward_with_budget(101, &mut n, &mut depth);
+
+    assert_eq!((n, depth), (100, 100));
+
+    Deep::backwards_with_budget(101, &mut n, &mut depth);
+
+    assert_eq!((n, depth), (100, 0));
+
+    // Without a budget there is no limit.
+    Deep::forward(&mut n, &mut depth);
+
+    assert_eq!(depth, 100);
+}
+
+#[test]
+#[cfg(feature = "budget")]
+#[should_panic(expected = "Budget of reversible steps exhausted")]
+fn test_budget_exhausted() {
+    rfn!(Deep, (n: &mut u32, depth: &mut u32), {
+        rif!(
+            *n == 0,
+            {},
+            {
+                *n -= 1;
+                *depth += 1;
+                Deep::forward(n, depth);
+                *n += 1;
+            },
+            *n == 0
+        );
+    });
+
+    let mut n = 100_000;
+    let mut depth = 0;
+
+    Deep::forward_with_budget(1000, &mut n, &mut depth);
+}
+
+#[test]
+#[cfg(feature = "budget")]
+#[should_panic(expected = "Budget of reversible steps exhausted")]
+fn test_budget_loop() {
+    rfn!(Spin, (x: &mut u32), {
+        rloop!(*x == 0, { *x += 2; }, *x == 5);
+    });
+
+    Spin::forward_with_budget(1000, &mut 0);
+}
+
+#[test]
+fn test_toggle_case() {
+    rfn!(Toggle, (bytes: &mut [u8]), {
+        toggle_case!(bytes);
+    });
+
+    rfn!(Twice, (bytes: &mut [u8]), {
+        toggle_case!(bytes);
+        toggle_case!(bytes);
+    });
+
+    let original = *b"Reversible Rust, 2024 [a-z] @`{";
+    let mut bytes = original;
+
+    Twice::forward(&mut bytes);
+
+    assert_eq!(bytes, original);
+
+    Toggle::backwards(&mut bytes);
+
+    assert_eq!(&bytes, b"rEVERSIBLE rUST, 2024 [A-Z] @`{");
+
+    Toggle::forward(&mut bytes);
+
+    assert_eq!(bytes, original);
+
+    Toggle::forward(&mut []);
+}
+
+#[test]
+fn test_moving_avg_step() {
+    rfn!(Stream, (sum: &mut u32, ring: &mut [u32; 3], head: &mut usize, samples: &mut [u32]), {
+        rfor!(i in 0..samples.len(), {
+            moving_avg_step!(*sum, *ring, *head, samples[i]);
+        });
+    });
+
+    let mut sum = 0;
+    let mut ring = [0; 3];
+    let mut head = 0;
+    let mut samples = [5, 1, 4, 2, 8];
+
+    Stream::forward(&mut sum, &mut ring, &mut head, &mut samples);
+
+    assert_eq!((sum, ring, head), (14, [2, 8, 4], 2));
+    assert_eq!(samples, [0, 0, 0, 5, 1]);
+
+    Stream::backwards(&mut sum, &mut ring, &mut head, &mut samples);
+
+    assert_eq!((sum, ring, head), (0, [0; 3], 0));
+    assert_eq!(samples, [5, 1, 4, 2, 8]);
+}
+
+#[test]
+fn test_bucket_consume() {
+    rfn!(Requests, (tokens: &mut u32, sizes: &[u32; 4], ok: &mut [bool; 4]), {
+        rfor!(i in 0..4, {
+            bucket_consume!(*tokens, sizes[i], ok[i]);
+        });
+    });
+
+    let sizes = [3, 4, 1, 2];
+    let mut tokens = 6;
+    let mut ok = [false; 4];
+
+    Requests::forward(&mut tokens, &sizes, &mut ok);
+
+    assert_eq!(tokens, 0);
+    assert_eq!(ok, [true, false, true, true]);
+
+    Requests::backwards(&mut tokens, &sizes, &mut ok);
+
+    assert_eq!(tokens, 6);
+    assert_eq!(ok, [false; 4]);
+}
+
+#[test]
+#[should_panic(expected = "Ok bit is already set")]
+fn test_bucket_consume_ok_set() {
+    rfn!(Consume, (tokens: &mut u32, ok: &mut bool), {
+        bucket_consume!(*tokens, 1, *ok);
+    });
+
+    Consume::forward(&mut 3, &mut true);
+}
+
+#[test]
+fn test_disjoint() {
+    rfn!(Group, (arr: &mut [i32], x: &mut i32, i: &usize, j: &usize), {
+        disjoint! {
+            arr[*i] += *x;
+            *x -= arr[*j];
+            arr[*j] ^= arr[*i];
+            let k = 2;
+            arr[*i] += k;
+        }
+        arr[*i] += 1;
+    });
+
+    let mut arr = [1, 2, 3, 4];
+    let mut x = 5;
+
+    Group::forward(&mut arr, &mut x, &1, &3);
+
+    assert_eq!(arr, [1, 10, 3, 3]);
+    assert_eq!(x, 1);
+
+    Group::backwards(&mut arr, &mut x, &1, &3);
+
+    assert_eq!(arr, [1, 2, 3, 4]);
+    assert_eq!(x, 5);
+}
+
+#[test]
+#[cfg(not(feature = "unchecked"))]
+#[should_panic(expected = "Places in disjoint! are aliases of each other")]
+fn test_disjoint_alias() {
+    rfn!(Group, (arr: &mut [i32], i: &usize, j: &usize), {
+        disjoint! {
+            arr[*i] += 1;
+            arr[*j] -= 1;
+        }
+    });
+
+    let mut arr = [1, 2, 3, 4];
+
+    Group::forward(&mut arr, &2, &2);
+}
+
+#[test]
+fn test_xor_list() {
+    rfn!(ToEnd, (cur: &mut usize, prev: &mut usize, steps: &mut usize, links: &[usize]), {
+        rloop!(
+            *prev == 0,
+            {
+                xor_list_advance!(*cur, *prev, links);
+                *steps += 1;
+            },
+            *cur == 0
+        );
+    });
+
+    rfn!(Back, (cur: &mut usize, prev: &mut usize, links: &[usize]), {
+        xor_list_retreat!(*cur, *prev, links);
+    });
+
+    // The list 3, 1, 4, 2.
+    let links = [0, 3 ^ 4, 4, 1, 1 ^ 2];
+    let mut cur = 3;
+    let mut prev = 0;
+    let mut steps = 0;
+
+    ToEnd::forward(&mut cur, &mut prev, &mut steps, &links);
+
+    assert_eq!((cur, prev, steps), (0, 2, 4));
+
+    Back::forward(&mut cur, &mut prev, &links);
+
+    assert_eq!((cur, prev), (2, 4));
+
+    Back::backwards(&mut cur, &mut prev, &links);
+    ToEnd::backwards(&mut cur, &mut prev, &mut steps, &links);
+
+    assert_eq!((cur, prev, steps), (3, 0, 0));
+}
+
+#[test]
+fn test_reversible_closure() {
+    let mut a = 1;
+    let mut b = 2;
+    let mut arr = [1, 2, 3];
+    let x = &mut a;
+
+    let mut actions: Vec<Box<dyn Reversible<()>>> = vec![
+        Box::new(reversible_closure!(move || {
+            *x += 10;
+        })),
+        Box::new(reversible_closure!(|| {
+            b ^= 5;
+            b += 1;
+        })),
+        Box::new(reversible_closure!(|| {
+            arr[0] += arr[2];
+            rswap!(arr[1], arr[2]);
+        })),
+    ];
+
+    rrust::forward_all(&mut actions, &mut ());
+    rrust::forward_all(&mut actions[1..], &mut ());
+    rrust::backwards_all(&mut actions, &mut ());
+    drop(actions);
+
+    assert_eq!((a, b, arr), (1, 8, [4, 3, 2]));
+
+    let mut undo = reversible_closure!(|| {
+        b -= 1;
+        b ^= 5;
+        arr[0] -= arr[1];
+        rswap!(arr[1], arr[2]);
+    });
+
+    undo.forward();
+    undo.forward();
+    undo.backwards();
+    undo.backwards();
+
+    assert_eq!((b, arr), (8, [4, 3, 2]));
+}
+
+#[test]
+fn test_apply_lut() {
+    rfn!(Equalize, (pixels: &mut [u8], lut: &[u8; 256], inv_lut: &[u8; 256]), {
+        apply_lut!(pixels, lut, inv_lut);
+    });
+
+    let lut: [u8; 256] = core::array::from_fn(|v| (v as u8).wrapping_mul(5).wrapping_add(3));
+    let mut inv_lut = [0; 256];
+    for (v, m) in lut.iter().enumerate() {
+        inv_lut[*m as usize] = v as u8;
+    }
+
+    let original: [u8; 8] = [0, 10, 20, 128, 200, 255, 10, 7];
+    let mut pixels = original;
+
+    Equalize::forward(&mut pixels, &lut, &inv_lut);
+
+    assert_eq!(pixels, [3, 53, 103, 131, 235, 254, 53, 38]);
+
+    Equalize::backwards(&mut pixels, &lut, &inv_lut);
+
+    assert_eq!(pixels, original);
+}
+
+#[test]
+#[should_panic(expected = "Lookup tables are not inverses of each other")]
+fn test_apply_lut_not_bijective() {
+    rfn!(Remap, (pixels: &mut [u8], lut: &[u8; 4], inv_lut: &[u8; 4]), {
+        apply_lut!(pixels, lut, inv_lut);
+    });
+
+    Remap::forward(&mut [0, 1, 2, 3], &[0, 1, 1, 2], &[0, 1, 3, 3]);
+}
+
+#[test]
+fn test_raw_pointer() {
+    rfn!(AddRaw, (p: *mut i32, q: *mut i32), {
+        unsafe {
+            *p += *q;
+            *q ^= 3;
+            *p -= 1;
+        }
+    });
+
+    let mut a = 1;
+    let mut b = 2;
+
+    AddRaw::forward(&mut a, &mut b);
+
+    assert_eq!((a, b), (2, 1));
+
+    AddRaw::backwards(&mut a, &mut b);
+
+    assert_eq!((a, b), (1, 2));
+}
+
+#[test]
+#[cfg(not(feature = "unchecked"))]
+#[should_panic(expected = "Lefthand and righthand are aliases of each other")]
+fn test_raw_pointer_alias() {
+    rfn!(AddRaw, (p: *mut i32, q: *mut i32), {
+        unsafe {
+            *p += *q;
+        }
+    });
+
+    let mut a = 1;
+    let p: *mut i32 = &mut a;
+
+    AddRaw::forward(p, p);
+}
+
+#[test]
+fn test_syndrome() {
+    // Parity-check matrix of the Hamming(7, 4) code, column `c` is the
+    // binary representation of `c + 1`.
+    const H: [[bool; 7]; 3] = {
+        let mut h = [[false; 7]; 3];
+        let mut c = 0;
+        while c < 7 {
+            let mut r = 0;
+            while r < 3 {
+                h[r][c] = (c + 1) >> r & 1 == 1;
+                r += 1;
+            }
+            c += 1;
+        }
+        h
+    };
+
+    rfn!(Syndrome, (s: &mut [bool; 3], x: &[bool; 7]), {
+        syndrome!(*s, H, *x);
+    });
+
+    let codeword = [true, true, true, false, false, false, false];
+    let mut s = [false; 3];
+
+    Syndrome::forward(&mut s, &codeword);
+
+    assert_eq!(s, [false; 3]);
+
+    let mut received = codeword;
+    received[4] ^= true;
+
+    Syndrome::forward(&mut s, &received);
+
+    assert_eq!(s, [true, false, true]);
+
+    Syndrome::backwards(&mut s, &received);
+
+    assert_eq!(s, [false; 3]);
+}
+
+#[test]
+fn test_rfn_async() {
+    fn block_on<F: core::future::Future>(future: F) -> F::Output {
+        let mut future = core::pin::pin!(future);
+        let mut cx = core::task::Context::from_waker(core::task::Waker::noop());
+        loop {
+            if let core::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    async fn load(value: i32) -> i32 {
+        core::future::ready(value).await
+    }
+
+    rfn_async!(Step, (x: &mut i32), {
+        *x += load(10).await;
+        core::future::ready(()).await;
+        *x ^= 1;
+    });
+
+    rfn_async!(Twice, (x: &mut i32, y: &mut i32), {
+        Step::forward(x).await;
+        Step::backwards(y).await;
+        *y += *x;
+    });
+
+    let mut x = 1;
+    let mut y = 20;
+
+    block_on(Twice::forward(&mut x, &mut y));
+
+    assert_eq!((x, y), (10, 21));
+
+    block_on(Twice::backwards(&mut x, &mut y));
+
+    assert_eq!((x, y), (1, 20));
+}
+
+#[test]
+fn test_transaction_log() {
+    #[derive(Debug, Clone, PartialEq)]
+    struct Account {
+        balance: i64,
+        fee: i64,
+        flags: u8,
+    }
+
+    rfn!(Deposit, (account: &mut Account, amount: &i64), {
+        account.balance += *amount;
+    });
+
+    rfn!(ChargeFee, (account: &mut Account), {
+        account.balance -= account.fee;
+    });
+
+    rfn!(Flag, (account: &mut Account), {
+        account.flags ^= 0b100;
+    });
+
+    let original = Account {
+        balance: 100,
+        fee: 3,
+        flags: 1,
+    };
+    let mut account = original.clone();
+    let amount = 50;
+    let mut log = TransactionLog::new();
+
+    log.apply(
+        (
+            |a: &mut Account| Deposit::forward(a, &amount),
+            |a: &mut Account| Deposit::backwards(a, &amount),
+        ),
+        &mut account,
+    );
+    log.apply((ChargeFee::forward, ChargeFee::backwards), &mut account);
+    log.apply((Flag::forward, Flag::backwards), &mut account);
+
+    assert_eq!(log.len(), 3);
+    assert_eq!(
+        account,
+        Account {
+            balance: 147,
+            fee: 3,
+            flags: 0b101
+        }
+    );
+
+    log.rollback(&mut account);
+
+    assert!(log.is_empty());
+    assert_eq!(account, original);
+
+    log.apply((Flag::forward, Flag::backwards), &mut account);
+    log.commit();
+    log.rollback(&mut account);
+
+    assert_eq!(account.flags, 0b101);
+}
+
+#[test]
+fn test_zobrist_toggle() {
+    // Keys for 2 kinds of pieces on 9 squares, from a splitmix64 sequence.
+    let mut seed: u64 = 42;
+    let keys: [[u64; 9]; 2] = core::array::from_fn(|_| {
+        core::array::from_fn(|_| {
+            seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+            let z = (seed ^ (seed >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            let z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        })
+    });
+
+    rfn!(Move, (hash: &mut u64, keys: &[[u64; 9]; 2], piece: &usize, from: &usize, to: &usize), {
+        zobrist_toggle!(*hash, keys[*piece][*from]);
+        zobrist_toggle!(*hash, keys[*piece][*to]);
+    });
+
+    let original = keys[0][4] ^ keys[1][0];
+    let mut hash = original;
+    let moves = [(0, 4, 2), (1, 0, 8), (0, 2, 6)];
+
+    for (piece, from, to) in &moves {
+        Move::forward(&mut hash, &keys, piece, from, to);
+    }
+
+    assert_eq!(hash, keys[0][6] ^ keys[1][8]);
+
+    for (piece, from, to) in moves.iter().rev() {
+        Move::backwards(&mut hash, &keys, piece, from, to);
+    }
+
+    assert_eq!(hash, original);
+}
+
+#[test]
+#[should_panic(expected = "Lefthand and righthand are aliases of each other")]
+fn test_zobrist_toggle_alias() {
+    rfn!(Toggle, (hash: &mut u64), {
+        zobrist_toggle!(*hash, *hash);
+    });
+
+    Toggle::forward(&mut 5);
+}
+
+#[test]
+fn test_tuple_assign_op() {
+    rfn!(Move, (x: &mut i32, y: &mut i32, v: &(i32, i32)), {
+        (*x, *y) += (v.0, v.1);
+        (*y, *x) ^= (3, *y);
+        (*x, *y) -= (1, *x);
+    });
+
+    let mut x = 10;
+    let mut y = 20;
+
+    Move::forward(&mut x, &mut y, &(2, -5));
+
+    assert_eq!((x, y), (2, 9));
+
+    Move::backwards(&mut x, &mut y, &(2, -5));
+
+    assert_eq!((x, y), (10, 20));
+}
+
+#[test]
+#[cfg(not(feature = "unchecked"))]
+#[should_panic(expected = "Lefthand and righthand are aliases of each other")]
+fn test_tuple_assign_op_alias() {
+    rfn!(Double, (x: &mut i32, y: &mut i32), {
+        (*x, *y) += (*y, *y);
+    });
+
+    Double::forward(&mut 1, &mut 2);
+}
+
+#[test]
+fn test_rloop_preserves() {
+    rfn!(Rotate, (v: &mut [i64; 2], turn: &mut bool, n: &mut u32), {
+        rloop!(
+            *n == 0,
+            {
+                rswap!(v[0], v[1]);
+                negate_if!(*turn, v[0]);
+                *n += 1;
+            },
+            *n == 3,
+            preserves: v[0] * v[0] + v[1] * v[1]
+        );
+    });
+
+    let mut v = [3, -4];
+    let mut n = 0;
+
+    Rotate::forward(&mut v, &mut true, &mut n);
+
+    assert_eq!((v, n), ([-4, -3], 3));
+
+    Rotate::backwards(&mut v, &mut true, &mut n);
+
+    assert_eq!((v, n), ([3, -4], 0));
+}
+
+#[test]
+#[should_panic(expected = "Loop iteration does not preserve the norm")]
+fn test_rloop_preserves_broken() {
+    rfn!(Rotate, (v: &mut [i64; 2], n: &mut u32), {
+        rloop!(
+            *n == 0,
+            {
+                rswap!(v[0], v[1]);
+                v[0] += 1;
+                *n += 1;
+            },
+            *n == 3,
+            preserves: v[0] * v[0] + v[1] * v[1]
+        );
+    });
+
+    Rotate::forward(&mut [3, -4], &mut 0);
+}
+
+#[test]
+#[allow(clippy::explicit_auto_deref)]
+fn test_boxed_field() {
+    struct State {
+        x: i32,
+        y: i32,
+    }
+
+    rfn!(Step, (boxed: &mut Box<State>, dx: &i32), {
+        (**boxed).x += *dx;
+        (**boxed).y -= (**boxed).x;
+        boxed.x ^= 0b11;
+    });
+
+    let mut boxed = Box::new(State { x: 1, y: 10 });
+
+    Step::forward(&mut boxed, &4);
+
+    assert_eq!((boxed.x, boxed.y), (6, 5));
+
+    Step::backwards(&mut boxed, &4);
+
+    assert_eq!((boxed.x, boxed.y), (1, 10));
+}
+
+#[test]
+#[cfg(not(feature = "unchecked"))]
+#[should_panic(expected = "Lefthand and righthand are aliases of each other")]
+#[allow(clippy::explicit_auto_deref)]
+fn test_boxed_field_alias() {
+    struct State {
+        x: i32,
+    }
+
+    rfn!(Double, (boxed: &mut Box<State>), {
+        (**boxed).x += boxed.x;
+    });
+
+    Double::forward(&mut Box::new(State { x: 1 }));
+}
+
+#[test]
+fn test_arith_coding() {
+    const FREQS: [u64; 3] = [1, 2, 1];
+
+    rfn!(Encode, (low: &mut u64, range: &mut u64, syms: &[usize]), {
+        rfor!(i in 0..syms.len(), {
+            arith_encode_step!(*low, *range, syms[i], FREQS);
+        });
+    });
+
+    let syms = [1, 0, 2];
+    let mut low = 0;
+    let mut range = 64;
+
+    Encode::forward(&mut low, &mut range, &syms);
+
+    assert_eq!((low, range), (22, 2));
+
+    Encode::backwards(&mut low, &mut range, &syms);
+
+    assert_eq!((low, range), (0, 64));
+}
+
+#[test]
+#[should_panic(expected = "Range is not divisible by the total frequency")]
+fn test_arith_coding_inexact() {
+    rfn!(Encode, (low: &mut u64, range: &mut u64, sym: &usize), {
+        arith_encode_step!(*low, *range, *sym, [1, 2, 1]);
+    });
+
+    Encode::forward(&mut 0, &mut 10, &1);
+}
+
+#[test]
+fn test_rev_rng() {
+    rfn!(Shuffle, (rng: &mut RevRng, arr: &mut [u64; 4]), {
+        rfor!(i in 0..arr.len(), {
+            rng_next!(*rng);
+            arr[i] ^= rng.value();
+        });
+    });
+
+    let mut rng = RevRng::new(42);
+    let mut arr = [1, 2, 3, 4];
+
+    Shuffle::forward(&mut rng, &mut arr);
+
+    assert_ne!(rng, RevRng::new(42));
+    assert_ne!(arr, [1, 2, 3, 4]);
+
+    Shuffle::backwards(&mut rng, &mut arr);
+
+    assert_eq!(rng, RevRng::new(42));
+    assert_eq!(arr, [1, 2, 3, 4]);
+}
+
+#[test]
+fn test_rmatch() {
+    enum Token {
+        Num(i64),
+        Pair(i64, i64),
+    }
+
+    rfn!(Eval, (token: &mut Token, acc: &mut i64), {
+        rmatch!(*token,
+            Token::Num(n) => {
+                *acc += *n;
+                *n ^= 1;
+            } => Token::Num(n),
+            Token::Pair(a, b) => {
+                *acc -= *a;
+                *b += *acc;
+            } => Token::Pair(a, b),
+        );
+    });
+
+    let mut num = Token::Num(6);
+    let mut pair = Token::Pair(3, 4);
+    let mut acc = 10;
+
+    Eval::forward(&mut num, &mut acc);
+    Eval::forward(&mut pair, &mut acc);
+
+    assert!(matches!(num, Token::Num(7)));
+    assert!(matches!(pair, Token::Pair(3, 17)));
+    assert_eq!(acc, 13);
+
+    Eval::backwards(&mut pair, &mut acc);
+    Eval::backwards(&mut num, &mut acc);
+
+    assert!(matches!(num, Token::Num(6)));
+    assert!(matches!(pair, Token::Pair(3, 4)));
+    assert_eq!(acc, 10);
+}
+
+#[test]
+#[should_panic(expected = "Postcondition of the arm does not hold")]
+fn test_rmatch_postcondition() {
+    rfn!(Step, (x: &mut u32), {
+        rmatch!(*x,
+            0 => { *x += 2; } => 1,
+            1.. => {} => 2..,
+        );
+    });
+
+    Step::forward(&mut 0);
+}
+
+#[test]
+fn test_lifting() {
+    rfn!(Wavelet, (x: &mut [i64]), {
+        lifting_predict!(x);
+        lifting_update!(x);
+    });
+
+    // A smooth signal leaves small details at the odd indices.
+    let mut x = [10, 12, 14, 16, 18, 20, 22, 24];
+
+    Wavelet::forward(&mut x);
+
+    assert_eq!(x, [10, 0, 14, 0, 18, 0, 23, 2]);
+
+    Wavelet::backwards(&mut x);
+
+    assert_eq!(x, [10, 12, 14, 16, 18, 20, 22, 24]);
+
+    for len in 0..12 {
+        let signal: Vec<i64> = (0..len).map(|i| (i * i * 7 % 23) as i64 - 11).collect();
+        let mut x = signal.clone();
+
+        Wavelet::forward(&mut x);
+        Wavelet::backwards(&mut x);
+
+        assert_eq!(x, signal);
+    }
+}
+
+#[test]
+fn test_is_sorted_flag() {
+    rfn!(Descents, (count: &mut usize, arr: &[i32]), {
+        rfor!(i in 0..arr.len().saturating_sub(1), {
+            rif!(arr[i] > arr[i + 1], { *count += 1; }, arr[i] > arr[i + 1]);
+        });
+    });
+
+    rfn!(IsSorted, (sorted: &mut bool, arr: &[i32]), {
+        with_scratch!(descents = 0, {
+            Descents::forward(&mut descents, arr);
+            *sorted ^= descents == 0;
+            Descents::backwards(&mut descents, arr);
+        });
+    });
+
+    let mut sorted = false;
+
+    IsSorted::forward(&mut sorted, &[1, 2, 2, 5]);
+
+    assert!(sorted);
+
+    IsSorted::backwards(&mut sorted, &[1, 2, 2, 5]);
+
+    assert!(!sorted);
+
+    IsSorted::forward(&mut sorted, &[1, 3, 2, 5]);
+
+    assert!(!sorted);
+
+    // Both an empty and a single element slice are sorted, so the flag
+    // is toggled twice.
+    IsSorted::forward(&mut sorted, &[]);
+    IsSorted::forward(&mut sorted, &[4]);
+
+    assert!(!sorted);
+}
+
+#[test]
+fn test_apply_perm() {
+    rfn!(Permute, (arr: &mut [u32; 8], cycles: &[Vec<usize>]), {
+        apply_perm!(*arr, cycles);
+    });
+
+    let cycles = vec![vec![0, 7, 1], vec![], vec![2, 5], vec![4], vec![3, 6]];
+    let mut arr = [10, 11, 12, 13, 14, 15, 16, 17];
+
+    Permute::forward(&mut arr, &cycles);
+
+    assert_eq!(arr, [11, 17, 15, 16, 14, 12, 13, 10]);
+
+    Permute::backwards(&mut arr, &cycles);
+
+    assert_eq!(arr, [10, 11, 12, 13, 14, 15, 16, 17]);
+}
+
+#[test]
+#[should_panic(expected = "Lefthand and righthand are aliases of each other")]
+fn test_apply_perm_repeated_index() {
+    rfn!(Permute, (arr: &mut [u32], cycles: &[&[usize]]), {
+        apply_perm!(arr, cycles);
+    });
+
+    Permute::forward(&mut [1, 2, 3], &[&[0, 1, 0]]);
+}
+
+#[test]
+fn test_inverse_ops() {
+    // Bits shifted with `<<=` and `>>=` are rotated, so nothing is lost.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Bits(u8);
+
+    impl std::ops::ShlAssign<u32> for Bits {
+        fn shl_assign(&mut self, n: u32) {
+            self.0 = self.0.rotate_left(n);
+        }
+    }
+
+    impl std::ops::ShrAssign<u32> for Bits {
+        fn shr_assign(&mut self, n: u32) {
+            self.0 = self.0.rotate_right(n);
+        }
+    }
+
+    rfn!(Scramble, (bits: &mut Bits, n: &u32), {
+        inverse_ops!(<<=, >>=);
+        *bits <<= *n;
+        bits.0 ^= 0b1;
+        *bits >>= 1;
+    });
+
+    let mut bits = Bits(0b1001_0110);
+
+    Scramble::forward(&mut bits, &3);
+
+    assert_eq!(bits, Bits(0b1101_1010));
+
+    Scramble::backwards(&mut bits, &3);
+
+    assert_eq!(bits, Bits(0b1001_0110));
+}
+
+#[test]
+fn test_window_max_step() {
+    use std::collections::VecDeque;
+
+    rfn!(Stream, (deque: &mut VecDeque<(usize, i32)>, history: &mut Vec<Vec<(usize, i32)>>, values: &[i32]), {
+        rfor!(i in 0..values.len(), {
+            window_max_step!(*deque, *history, values[i], 3);
+        });
+    });
+
+    let values = [1, 3, -1, -3, 5, 3, 6, 7, 2, 2, 1];
+    let mut deque = VecDeque::new();
+    let mut history = Vec::new();
+    let mut maxima = Vec::new();
+
+    for i in 0..values.len() {
+        Stream::forward(&mut deque, &mut history, &values[i..=i]);
+        maxima.push(deque.front().unwrap().1);
+    }
+
+    assert_eq!(maxima, [1, 3, 3, 3, 5, 5, 6, 7, 7, 7, 2]);
+
+    let mut restored = VecDeque::new();
+    let mut restored_history = Vec::new();
+    Stream::forward(&mut restored, &mut restored_history, &values[..8]);
+
+    Stream::backwards(&mut deque, &mut history, &values[8..]);
+
+    assert_eq!((deque, history), (restored, restored_history));
+
+    let mut deque = VecDeque::new();
+    let mut history = Vec::new();
+
+    Stream::forward(&mut deque, &mut history, &values);
+    Stream::backwards(&mut deque, &mut history, &values);
+
+    assert!(deque.is_empty() && history.is_empty());
+}
+
+#[test]
+fn test_rfn_returned_value() {
+    rfn!(Extract, (word: &mut u32) -> low: u32, {
+        low ^= *word & 0xffff;
+        *word ^= low;
+    });
+
+    let mut word = 0x1234_5678;
+
+    let low = Extract::forward(&mut word);
+
+    assert_eq!((word, low), (0x1234_0000, 0x5678));
+
+    Extract::backwards(&mut word, low);
+
+    assert_eq!(word, 0x1234_5678);
+
+    rfn!(Sum<T: Copy + Default + PartialEq + core::ops::AddAssign + core::ops::SubAssign>, (values: &[T]) -> sum: T, {
+        rfor!(i in 0..values.len(), {
+            sum += values[i];
+        });
+    });
+
+    let sum = Sum::forward(&[1.5, 2.0, 0.25]);
+
+    assert_eq!(sum, 3.75);
+
+    Sum::backwards(&[1.5, 2.0, 0.25], sum);
+}
+
+#[test]
+#[should_panic(expected = "Returned value is not uncomputed")]
+fn test_rfn_returned_value_wrong() {
+    rfn!(Take, (stock: &mut u32, n: &u32) -> taken: u32, {
+        *stock -= *n;
+        taken += *n;
+    });
+
+    let mut stock = 10;
+    let taken = Take::forward(&mut stock, &3);
+
+    Take::backwards(&mut stock, &3, taken + 1);
+}
+
+#[test]
+fn test_relax() {
+    const INF: i64 = i64::MAX / 2;
+    const EDGES: [(usize, usize, i64); 6] = [
+        (0, 1, 6),
+        (0, 2, 7),
+        (1, 3, 5),
+        (2, 3, -3),
+        (3, 1, -2),
+        (1, 4, -4),
+    ];
+
+    rfn!(BellmanFord, (dist: &mut [i64; 5], pred: &mut [usize; 5], history: &mut Vec<Option<(i64, usize)>>), {
+        rfor!(round in 0..dist.len() - 1, {
+            rfor!(e in 0..EDGES.len(), {
+                relax!(*dist, *pred, EDGES[e].0, EDGES[e].1, EDGES[e].2, *history);
+            });
+        });
+    });
+
+    let mut dist = [0, INF, INF, INF, INF];
+    let mut pred = [0, usize::MAX, usize::MAX, usize::MAX, usize::MAX];
+    let mut history = Vec::new();
+
+    BellmanFord::forward(&mut dist, &mut pred, &mut history);
+
+    assert_eq!(dist, [0, 2, 7, 4, -2]);
+    assert_eq!(pred, [0, 3, 0, 2, 1]);
+    assert_eq!(history.len(), 4 * EDGES.len());
+
+    BellmanFord::backwards(&mut dist, &mut pred, &mut history);
+
+    assert_eq!(dist, [0, INF, INF, INF, INF]);
+    assert_eq!(pred, [0, usize::MAX, usize::MAX, usize::MAX, usize::MAX]);
+    assert!(history.is_empty());
+}
+
+#[test]
+fn test_relax_overflow() {
+    rfn!(Relax, (dist: &mut [u32; 3], pred: &mut [usize; 3], history: &mut Vec<Option<(u32, usize)>>), {
+        relax!(*dist, *pred, 1, 2, 1, *history);
+        relax!(*dist, *pred, 0, 1, 3, *history);
+        relax!(*dist, *pred, 1, 2, 1, *history);
+    });
+
+    let mut dist = [0, u32::MAX, u32::MAX];
+    let mut pred = [0, usize::MAX, usize::MAX];
+    let mut history = Vec::new();
+
+    Relax::forward(&mut dist, &mut pred, &mut history);
+
+    assert_eq!((dist, pred), ([0, 3, 4], [0, 0, 1]));
+    assert_eq!(history[0], None);
+
+    Relax::backwards(&mut dist, &mut pred, &mut history);
+
+    assert_eq!(
+        (dist, pred),
+        ([0, u32::MAX, u32::MAX], [0, usize::MAX, usize::MAX])
+    );
+    assert!(history.is_empty());
+}
+
+#[test]
+fn test_mul_accumulate() {
+    fn value(limbs: &[u32]) -> u128 {
+        limbs
+            .iter()
+            .rev()
+            .fold(0, |v, limb| (v << 32) | u128::from(*limb))
+    }
+
+    rfn!(Mul, (acc: &mut [u32; 4], a: &[u32; 3], b: &[u32; 2]), {
+        mul_accumulate!(*acc, *a, *b);
+    });
+
+    let a = [0xdead_beef, 0x0123_4567, 0x89ab];
+    let b = [0xffff_ffff, 0x7654_3210];
+    let start = [0xffff_ffff, 0xffff_ffff, 0x1234, 0xffff_fff0];
+    let mut acc = start;
+
+    Mul::forward(&mut acc, &a, &b);
+
+    assert_eq!(
+        value(&acc),
+        value(&start).wrapping_add(value(&a).wrapping_mul(value(&b)))
+    );
+
+    Mul::backwards(&mut acc, &a, &b);
+
+    assert_eq!(acc, start);
+}
+
+#[test]
+fn test_nonzero() {
+    use std::num::{NonZeroI8, NonZeroU32};
+
+    rfn!(Step, (size: &mut NonZeroU32, delta: &mut NonZeroI8, x: &mut u32), {
+        nonzero_add!(*size, *x);
+        nonzero_sub!(*delta, 2);
+        nonzero_sub!(*size, 9);
+    });
+
+    let mut size = NonZeroU32::new(10).unwrap();
+    let mut delta = NonZeroI8::new(1).unwrap();
+    let mut x = 5;
+
+    Step::forward(&mut size, &mut delta, &mut x);
+
+    assert_eq!(size.get(), 6);
+    assert_eq!(delta.get(), -1);
+
+    Step::backwards(&mut size, &mut delta, &mut x);
+
+    assert_eq!(size.get(), 10);
+    assert_eq!(delta.get(), 1);
+}
+
+#[test]
+#[should_panic(expected = "Result of the non-zero operation is zero or overflows")]
+fn test_nonzero_zero() {
+    use std::num::NonZeroU32;
+
+    rfn!(Shrink, (size: &mut NonZeroU32, x: &mut u32), {
+        nonzero_sub!(*size, *x);
+    });
+
+    let mut size = NonZeroU32::new(4).unwrap();
+    let mut x = 4;
+
+    Shrink::forward(&mut size, &mut x);
+}
+
+#[test]
+fn test_segtree_update() {
+    fn sum(tree: &[i64], mut lo: usize, mut hi: usize) -> i64 {
+        let n = tree.len() / 2;
+        let mut total = 0;
+        lo += n;
+        hi += n;
+        while lo < hi {
+            if lo % 2 == 1 {
+                total += tree[lo];
+                lo += 1;
+            }
+            if hi % 2 == 1 {
+                hi -= 1;
+                total += tree[hi];
+            }
+            lo /= 2;
+            hi /= 2;
+        }
+        total
+    }
+
+    rfn!(Updates, (tree: &mut [i64], updates: &[(usize, i64)]), {
+        rfor!(u in 0..updates.len(), {
+            segtree_update!(*tree, updates[u].0, updates[u].1);
+        });
+    });
+
+    let leaves = [5, -2, 7, 0, 3, 3];
+    let mut tree = vec![0; 2 * leaves.len()];
+    tree[leaves.len()..].copy_from_slice(&leaves);
+    for i in (1..leaves.len()).rev() {
+        tree[i] = tree[2 * i] + tree[2 * i + 1];
+    }
+    let start = tree.clone();
+    let updates = [(0, 4), (3, -10), (5, 1), (0, -1)];
+
+    Updates::forward(&mut tree, &updates);
+
+    assert_eq!(&tree[leaves.len()..], [8, -2, 7, -10, 3, 4]);
+    assert_eq!(sum(&tree, 0, 6), 10);
+    assert_eq!(sum(&tree, 1, 4), -5);
+    assert_eq!(sum(&tree, 4, 6), 7);
+
+    Updates::backwards(&mut tree, &updates);
+
+    assert_eq!(tree, start);
+}
+
+#[test]
+fn test_state_cycle() {
+    #[derive(Clone, Copy)]
+    enum Light {
+        Red,
+        Green,
+        Yellow,
+    }
+
+    rfn!(Cycle, (visits: &mut [u32; 3], steps: &usize), {
+        let mut state: u8 = Light::Red as u8;
+        rfor!(step in 0..*steps, {
+            add_mod!(state, 1, Light::Yellow as u8 + 1);
+            visits[state as usize] += 1;
+        });
+        delocal!(state, Light::Red as u8);
+    });
+
+    let mut visits = [0; 3];
+
+    // Two rounds of the cycle, which ends where it started.
+    Cycle::forward(&mut visits, &6);
+
+    assert_eq!(visits[Light::Red as usize], 2);
+    assert_eq!(visits[Light::Green as usize], 2);
+    assert_eq!(visits[Light::Yellow as usize], 2);
+
+    Cycle::backwards(&mut visits, &6);
+
+    assert_eq!(visits, [0; 3]);
+}
+
+#[test]
+fn test_digit_rotate() {
+    rfn!(Rotate, (x: &mut u64, steps: &usize), {
+        rfor!(step in 0..*steps, {
+            digit_rotate!(*x, 4, 1000);
+        });
+    });
+
+    let record = 7_042_913_005;
+    let mut x = record;
+
+    Rotate::forward(&mut x, &1);
+
+    assert_eq!(x, 5_007_042_913);
+
+    Rotate::backwards(&mut x, &1);
+
+    assert_eq!(x, record);
+
+    Rotate::forward(&mut x, &4);
+
+    assert_eq!(x, record);
+
+    Rotate::forward(&mut x, &3);
+    Rotate::backwards(&mut x, &1);
+
+    assert_eq!(x, 913_005_007_042);
+}
+
+#[test]
+#[should_panic(expected = "Value has more digits than the groups")]
+fn test_digit_rotate_too_many_digits() {
+    rfn!(Rotate, (x: &mut u32), {
+        digit_rotate!(*x, 2, 10);
+    });
+
+    Rotate::forward(&mut 123);
+}
+
+#[test]
+#[should_panic(expected = "Digit groups do not fit in the type")]
+fn test_digit_rotate_too_many_groups() {
+    rfn!(Rotate, (x: &mut u8), {
+        digit_rotate!(*x, 4, 10);
+    });
+
+    Rotate::forward(&mut 12);
+}
+
+#[test]
+fn test_digit_rotate_near_max() {
+    rfn!(Rotate, (x: &mut u8), {
+        digit_rotate!(*x, 3, 6);
+    });
+
+    // 6^3 = 216 fits in a u8, so every value up to 215 can be rotated.
+    for value in [0, 35, 180, 210, 215] {
+        let mut x = value;
+
+        Rotate::forward(&mut x);
+        Rotate::backwards(&mut x);
+
+        assert_eq!(x, value);
+    }
+
+    let mut x = 215;
+    Rotate::forward(&mut x);
+
+    assert_eq!(x, 215);
+
+    let mut x = 5;
+    Rotate::forward(&mut x);
+
+    assert_eq!(x, 180);
+}
+
+#[test]
+#[should_panic(expected = "Digit groups do not fit in the type")]
+fn test_digit_rotate_overflow() {
+    rfn!(Rotate, (x: &mut u8), {
+        digit_rotate!(*x, 3, 7);
+    });
+
+    Rotate::backwards(&mut 48);
+}
+
+#[test]
+fn test_wide_add() {
+    rfn!(Count, (value: &mut u16, overflow: &mut u32, e: &mut u16, times: &usize), {
+        rfor!(i in 0..*times, {
+            wide_add!(*value, *overflow, *e);
+        });
+    });
+
+    let mut value = 1000;
+    let mut overflow = 0;
+    let mut e = 30_000;
+
+    Count::forward(&mut value, &mut overflow, &mut e, &7);
+
+    assert_eq!(
+        u64::from(overflow) << 16 | u64::from(value),
+        1000 + 7 * 30_000
+    );
+    assert_eq!(overflow, 3);
+
+    Count::backwards(&mut value, &mut overflow, &mut e, &7);
+
+    assert_eq!((value, overflow), (1000, 0));
+}
+
+#[test]
+fn test_counting_bloom_add() {
+    fn hashes(element: u64) -> [usize; 3] {
+        let mut rng = RevRng::new(element);
+        rng.next();
+        let first = rng.value() as usize;
+        rng.next();
+        let second = rng.value() as usize;
+        rng.next();
+        [first, second, rng.value() as usize]
+    }
+
+    fn contains(counts: &[u16], element: u64) -> bool {
+        hashes(element)
+            .iter()
+            .all(|h| counts[h % counts.len()] != 0)
+    }
+
+    rfn!(Insert, (counts: &mut [u16; 32], hashes: &[usize; 3]), {
+        counting_bloom_add!(*counts, *hashes);
+    });
+
+    let mut counts = [0; 32];
+
+    Insert::forward(&mut counts, &hashes(7));
+    Insert::forward(&mut counts, &hashes(42));
+
+    assert!(contains(&counts, 7));
+    assert!(contains(&counts, 42));
+    assert_eq!(counts.iter().sum::<u16>(), 6);
+
+    Insert::backwards(&mut counts, &hashes(42));
+
+    assert!(contains(&counts, 7));
+    assert_eq!(counts.iter().sum::<u16>(), 3);
+
+    Insert::backwards(&mut counts, &hashes(7));
+
+    assert_eq!(counts, [0; 32]);
+}
+
+#[test]
+#[should_panic(expected = "Counter of the Bloom filter is already zero")]
+fn test_counting_bloom_add_missing() {
+    rfn!(Insert, (counts: &mut [u8; 8], hashes: &[usize; 2]), {
+        counting_bloom_add!(*counts, *hashes);
+    });
+
+    let mut counts = [0; 8];
+
+    Insert::forward(&mut counts, &[1, 2]);
+    Insert::backwards(&mut counts, &[1, 3]);
 }
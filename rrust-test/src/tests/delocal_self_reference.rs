@@ -0,0 +1,14 @@
+use rrust::rfn;
+
+rfn!(SelfReference, (x: &mut i32), {
+    let mut z = 0;
+    z += *x;
+    delocal!(z, z + 1);
+});
+
+fn main() {
+    let mut x = 0;
+
+    SelfReference::forward(&mut x);
+    SelfReference::backwards(&mut x);
+}
@@ -0,0 +1,12 @@
+use rrust::rfn;
+
+rfn!(forward AddOne, (x: &mut i32), {
+    *x += 1;
+});
+
+fn main() {
+    let mut x = 0;
+
+    AddOne::forward(&mut x);
+    AddOne::backwards(&mut x);
+}
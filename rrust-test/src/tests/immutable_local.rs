@@ -0,0 +1,14 @@
+use rrust::rfn;
+
+rfn!(Decrement, (x: &mut i32), {
+    let a = 5;
+    a -= 1;
+    *x += a;
+    delocal!(a, 4);
+});
+
+fn main() {
+    let mut x = 0;
+
+    Decrement::forward(&mut x);
+}
@@ -0,0 +1,16 @@
+#![deny(unused_attributes)]
+
+use rrust::rfn;
+
+rfn!(Inline, (x: &mut i32), {
+    *x += 1;
+});
+
+fn main() {
+    let mut x = 0;
+
+    Inline::forward(&mut x);
+    Inline::backwards(&mut x);
+
+    assert_eq!(x, 0);
+}
@@ -0,0 +1,11 @@
+use rrust::rfn;
+
+rfn!(AddSlices, (a: &mut [i32], b: &mut [i32]), {
+    a[0] += b[1];
+});
+
+fn main() {
+    let mut arr = [1, 2, 3, 4, 5];
+
+    AddSlices::forward(&mut arr[0..3], &mut arr[2..5]);
+}
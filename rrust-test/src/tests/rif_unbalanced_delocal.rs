@@ -0,0 +1,23 @@
+use rrust::{delocal, rfn, rif};
+
+rfn!(Unbalanced, (x: &mut i32), {
+    let t = 0;
+    rif!(
+        *x == 0,
+        {
+            *x += 1;
+            delocal!(t, 0);
+        },
+        {
+            *x -= 1;
+        },
+        *x == 1
+    );
+});
+
+fn main() {
+    let mut x = 0;
+
+    Unbalanced::forward(&mut x);
+    Unbalanced::backwards(&mut x);
+}
@@ -0,0 +1,11 @@
+use rrust::{rfn, rswap};
+
+rfn!(Swap, (arr: &mut [i32]), {
+    rswap!(arr[1], arr[1]);
+});
+
+fn main() {
+    let mut arr = [1, 2, 3];
+
+    Swap::forward(&mut arr);
+}
@@ -0,0 +1,13 @@
+use rrust::rfn;
+
+rfn!(SideEffect, (x: &mut i32), {
+    *x += 1;
+    println!("x is {}", x);
+});
+
+fn main() {
+    let mut x = 0;
+
+    SideEffect::forward(&mut x);
+    SideEffect::backwards(&mut x);
+}
@@ -0,0 +1,12 @@
+use rrust::rfn;
+
+rfn!(Countdown, (n: &mut i32), {
+    *n -= 1;
+    Countdown::forward(n);
+});
+
+fn main() {
+    let mut n = 10;
+
+    Countdown::forward(&mut n);
+}
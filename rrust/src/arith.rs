@@ -0,0 +1,1544 @@
+//! Arithmetic primitives with a known reverse.
+
+/// Modular addition.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Adds `$e` to the place `$a` modulo `$m`. For this to be
+/// reversible `$a` has to be in the range `0..$m` before the
+/// operation, this is asserted. The reverse of [`add_mod`](crate::add_mod) is
+/// [`sub_mod`](crate::sub_mod).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, add_mod};
+/// rfn!(Tick, (hour: &mut u32), {
+///     add_mod!(*hour, 5, 12);
+/// });
+///
+/// let mut hour = 10;
+///
+/// Tick::forward(&mut hour);
+///
+/// assert_eq!(hour, 3);
+///
+/// Tick::backwards(&mut hour);
+///
+/// assert_eq!(hour, 10);
+/// ```
+#[macro_export]
+macro_rules! add_mod {
+    ($a:expr, $e:expr, $m:expr) => {{
+        ::rrust::_alias_check!($a, $e);
+        let m = $m;
+        assert!(
+            $a < m,
+            "{}:{}: Value is out of modular range",
+            file!(),
+            line!()
+        );
+        // `$e` is reduced first and the sum is never formed, so this
+        // does not overflow for values close to the end of the type.
+        let e = $e % m;
+        $a = if $a >= m - e { $a - (m - e) } else { $a + e };
+    }};
+}
+
+/// Modular subtraction.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Subtracts `$e` from the place `$a` modulo `$m`. `$a` has to be in
+/// the range `0..$m` before the operation, this is asserted. The
+/// reverse of [`sub_mod`](crate::sub_mod) is [`add_mod`](crate::add_mod).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, sub_mod};
+/// rfn!(Untick, (hour: &mut u32), {
+///     sub_mod!(*hour, 5, 12);
+/// });
+///
+/// let mut hour = 3;
+///
+/// Untick::forward(&mut hour);
+///
+/// assert_eq!(hour, 10);
+///
+/// Untick::backwards(&mut hour);
+///
+/// assert_eq!(hour, 3);
+/// ```
+#[macro_export]
+macro_rules! sub_mod {
+    ($a:expr, $e:expr, $m:expr) => {{
+        ::rrust::_alias_check!($a, $e);
+        let m = $m;
+        assert!(
+            $a < m,
+            "{}:{}: Value is out of modular range",
+            file!(),
+            line!()
+        );
+        let e = $e % m;
+        $a = if $a >= e { $a - e } else { $a + (m - e) };
+    }};
+}
+
+/// Conditional negation.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Negates the signed integer place `$x` if `$control` is true. Given
+/// the same control this is its own reverse. It will panic if
+/// `$control` and `$x` are aliases of each other, or if `$x` is the
+/// minimum value of its type, which has no negation.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, negate_if};
+/// rfn!(Flip, (control: &mut bool, x: &mut i32), {
+///     negate_if!(*control, *x);
+/// });
+///
+/// let mut control = true;
+/// let mut x = 7;
+///
+/// Flip::forward(&mut control, &mut x);
+///
+/// assert_eq!(x, -7);
+///
+/// Flip::backwards(&mut control, &mut x);
+///
+/// assert_eq!(x, 7);
+/// ```
+#[macro_export]
+macro_rules! negate_if {
+    ($control:expr, $x:expr) => {{
+        ::rrust::_alias_check!($control, $x);
+        if $control {
+            $x = $x.checked_neg().unwrap_or_else(|| {
+                panic!("{}:{}: Minimum value can not be negated", file!(), line!())
+            });
+        }
+    }};
+}
+
+/// Boolean toggle.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Negates the boolean place `$flag`, toggling it twice leaves it as
+/// it was so this is its own reverse.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, toggle};
+/// rfn!(Toggle, (flag: &mut bool), {
+///     toggle!(*flag);
+/// });
+///
+/// let mut flag = false;
+///
+/// Toggle::forward(&mut flag);
+///
+/// assert!(flag);
+///
+/// Toggle::backwards(&mut flag);
+///
+/// assert!(!flag);
+/// ```
+#[macro_export]
+macro_rules! toggle {
+    ($flag:expr) => {{
+        let flag: &mut bool = &mut $flag;
+        *flag = !*flag;
+    }};
+}
+
+/// Gray code increment.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Steps the unsigned integer place `$x` holding a Gray code to the
+/// next value in the sequence, wrapping around at the end of the
+/// range of the type. The reverse of [`gray_inc`](crate::gray_inc)
+/// is [`gray_dec`](crate::gray_dec).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, gray_inc};
+/// rfn!(Step, (x: &mut u8), {
+///     gray_inc!(*x);
+/// });
+///
+/// let mut x = 0b010;
+///
+/// Step::forward(&mut x);
+///
+/// assert_eq!(x, 0b110);
+///
+/// Step::backwards(&mut x);
+///
+/// assert_eq!(x, 0b010);
+/// ```
+#[macro_export]
+macro_rules! gray_inc {
+    ($x:expr) => {
+        ::rrust::_gray_step!($x, wrapping_add)
+    };
+}
+
+/// Gray code decrement.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Steps the unsigned integer place `$x` holding a Gray code to the
+/// previous value in the sequence, wrapping around at the start of
+/// the range of the type. The reverse of [`gray_dec`](crate::gray_dec)
+/// is [`gray_inc`](crate::gray_inc).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, gray_dec};
+/// rfn!(Step, (x: &mut u8), {
+///     gray_dec!(*x);
+/// });
+///
+/// let mut x = 0b110;
+///
+/// Step::forward(&mut x);
+///
+/// assert_eq!(x, 0b010);
+///
+/// Step::backwards(&mut x);
+///
+/// assert_eq!(x, 0b110);
+/// ```
+#[macro_export]
+macro_rules! gray_dec {
+    ($x:expr) => {
+        ::rrust::_gray_step!($x, wrapping_sub)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _gray_step {
+    ($x:expr, $step:ident) => {{
+        let mut binary = $x;
+        let mut shift = $x >> 1;
+        while shift != 0 {
+            binary ^= shift;
+            shift >>= 1;
+        }
+        binary = binary.$step(1);
+        $x = binary ^ (binary >> 1);
+    }};
+}
+
+/// Addition with carry.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Adds `$e` to the two limb number formed by the unsigned places
+/// `$hi` and `$lo`, the carry out of `$lo` is propagated into `$hi`.
+/// The number wraps around at the end of its range. The reverse of
+/// [`add_carry`](crate::add_carry) is [`sub_borrow`](crate::sub_borrow).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, add_carry};
+/// rfn!(Add, (lo: &mut u8, hi: &mut u8), {
+///     add_carry!(*lo, *hi, 10);
+/// });
+///
+/// let mut lo = 250;
+/// let mut hi = 1;
+///
+/// Add::forward(&mut lo, &mut hi);
+///
+/// assert_eq!((lo, hi), (4, 2));
+///
+/// Add::backwards(&mut lo, &mut hi);
+///
+/// assert_eq!((lo, hi), (250, 1));
+/// ```
+#[macro_export]
+macro_rules! add_carry {
+    ($lo:expr, $hi:expr, $e:expr) => {
+        ::rrust::_carry_step!($lo, $hi, $e, overflowing_add, wrapping_add)
+    };
+}
+
+/// Subtraction with borrow.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Subtracts `$e` from the two limb number formed by the unsigned
+/// places `$hi` and `$lo`, the borrow out of `$lo` is propagated into
+/// `$hi`. The number wraps around at the start of its range. The
+/// reverse of [`sub_borrow`](crate::sub_borrow) is
+/// [`add_carry`](crate::add_carry).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, sub_borrow};
+/// rfn!(Sub, (lo: &mut u8, hi: &mut u8), {
+///     sub_borrow!(*lo, *hi, 10);
+/// });
+///
+/// let mut lo = 4;
+/// let mut hi = 2;
+///
+/// Sub::forward(&mut lo, &mut hi);
+///
+/// assert_eq!((lo, hi), (250, 1));
+///
+/// Sub::backwards(&mut lo, &mut hi);
+///
+/// assert_eq!((lo, hi), (4, 2));
+/// ```
+#[macro_export]
+macro_rules! sub_borrow {
+    ($lo:expr, $hi:expr, $e:expr) => {
+        ::rrust::_carry_step!($lo, $hi, $e, overflowing_sub, wrapping_sub)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _carry_step {
+    ($lo:expr, $hi:expr, $e:expr, $overflowing:ident, $wrapping:ident) => {{
+        ::rrust::_alias_check!($lo, $hi);
+        ::rrust::_alias_check!($lo, $e);
+        ::rrust::_alias_check!($hi, $e);
+        let (lo, carry) = $lo.$overflowing($e);
+        $lo = lo;
+        if carry {
+            $hi = $hi.$wrapping(1);
+        }
+    }};
+}
+
+/// Addition into a wide counter.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Adds `$e` to the unsigned place `$value` and increments the place
+/// `$overflow` every time the addition wraps around, so together they
+/// count past the range of `$value`. Unlike
+/// [`add_carry`](crate::add_carry) the overflow count can be of any
+/// integer type and it does not wrap around itself. In reverse `$e` is
+/// subtracted again and `$overflow` is decremented when the
+/// subtraction borrows.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, wide_add};
+/// rfn!(Count, (value: &mut u8, overflow: &mut u64), {
+///     wide_add!(*value, *overflow, 100);
+/// });
+///
+/// let mut value = 200;
+/// let mut overflow = 0;
+///
+/// Count::forward(&mut value, &mut overflow);
+///
+/// assert_eq!((value, overflow), (44, 1));
+///
+/// Count::backwards(&mut value, &mut overflow);
+///
+/// assert_eq!((value, overflow), (200, 0));
+/// ```
+#[macro_export]
+macro_rules! wide_add {
+    ($value:expr, $overflow:expr, $e:expr) => {{
+        ::rrust::_alias_check!($value, $overflow);
+        ::rrust::_alias_check!($value, $e);
+        ::rrust::_alias_check!($overflow, $e);
+        let (value, wrapped) = $value.overflowing_add($e);
+        $value = value;
+        if wrapped {
+            $overflow += 1;
+        }
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_wide_add {
+    ($value:expr, $overflow:expr, $e:expr) => {{
+        ::rrust::_alias_check!($value, $overflow);
+        ::rrust::_alias_check!($value, $e);
+        ::rrust::_alias_check!($overflow, $e);
+        let (value, wrapped) = $value.overflowing_sub($e);
+        $value = value;
+        if wrapped {
+            $overflow -= 1;
+        }
+    }};
+}
+
+/// Multiplication into an accumulator.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Adds the product of the multi limb numbers `$a` and `$b` to the
+/// multi limb number `$acc`. All three are slices of unsigned limbs
+/// with the least significant limb first. The product is added with
+/// shift-and-add, for every set bit of `$b` the shifted `$a` is added
+/// limb by limb with the carry propagated as in
+/// [`add_carry`](crate::add_carry). The accumulator wraps around at the
+/// end of its range. `$a` and `$b` are kept, so in reverse the product
+/// is subtracted again.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, mul_accumulate};
+/// rfn!(Mul, (acc: &mut [u8; 3], a: &[u8; 2], b: &[u8; 1]), {
+///     mul_accumulate!(*acc, *a, *b);
+/// });
+///
+/// let mut acc = [1, 0, 0];
+///
+/// // (1 + 2 * 256) * 200 + 1
+/// Mul::forward(&mut acc, &[1, 2], &[200]);
+///
+/// assert_eq!(acc, [201, 144, 1]);
+///
+/// Mul::backwards(&mut acc, &[1, 2], &[200]);
+///
+/// assert_eq!(acc, [1, 0, 0]);
+/// ```
+#[macro_export]
+macro_rules! mul_accumulate {
+    ($acc:expr, $a:expr, $b:expr) => {
+        ::rrust::_mul_accumulate!($acc, $a, $b, overflowing_add)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_mul_accumulate {
+    ($acc:expr, $a:expr, $b:expr) => {
+        ::rrust::_mul_accumulate!($acc, $a, $b, overflowing_sub)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _mul_accumulate {
+    ($acc:expr, $a:expr, $b:expr, $overflowing:ident) => {{
+        let acc = &mut $acc[..];
+        let a = &$a[..];
+        let b = &$b[..];
+        let bits = 8 * ::core::mem::size_of_val(acc)
+            .checked_div(acc.len())
+            .unwrap_or(0);
+        for (j, limb) in b.iter().enumerate() {
+            for k in 0..bits {
+                if (*limb >> k) & 1 == 0 {
+                    continue;
+                }
+                // Adds `a << (j * bits + k)`, the limb `i` of `a` ends
+                // up in the limbs `j + i` and `j + i + 1`.
+                let mut carry = false;
+                for p in j..acc.len() {
+                    let i = p - j;
+                    let low = if i < a.len() { a[i] << k } else { 0 };
+                    let high = if k == 0 || i == 0 || i > a.len() {
+                        0
+                    } else {
+                        a[i - 1] >> (bits - k)
+                    };
+                    let (limb, c1) = acc[p].$overflowing(low | high);
+                    let (limb, c2) = if carry {
+                        limb.$overflowing(1)
+                    } else {
+                        (limb, false)
+                    };
+                    acc[p] = limb;
+                    carry = c1 || c2;
+                    if !carry && i >= a.len() {
+                        break;
+                    }
+                }
+            }
+        }
+    }};
+}
+
+/// Masked addition of slices.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Adds `$src[i]` to `$dst[i]` for every index `i` where `$mask[i]`
+/// is set, the other elements of `$dst` are left as they are. It will
+/// panic if the three slices are not of the same length. In reverse
+/// `$src[i]` is subtracted where the mask is set.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, masked_add};
+/// rfn!(Add, (dst: &mut [i32], src: &[i32], mask: &[bool]), {
+///     masked_add!(dst, src, mask);
+/// });
+///
+/// let mut dst = [1, 2, 3];
+///
+/// Add::forward(&mut dst, &[10, 20, 30], &[true, false, true]);
+///
+/// assert_eq!(dst, [11, 2, 33]);
+///
+/// Add::backwards(&mut dst, &[10, 20, 30], &[true, false, true]);
+///
+/// assert_eq!(dst, [1, 2, 3]);
+/// ```
+#[macro_export]
+macro_rules! masked_add {
+    ($dst:expr, $src:expr, $mask:expr) => {
+        ::rrust::_masked_add!(forward, $dst, $src, $mask)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_masked_add {
+    ($dst:expr, $src:expr, $mask:expr) => {
+        ::rrust::_masked_add!(reverse, $dst, $src, $mask)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _masked_add {
+    ($dir:ident, $dst:expr, $src:expr, $mask:expr) => {{
+        let dst = &mut $dst[..];
+        let src = &$src[..];
+        let mask = &$mask[..];
+        let len = dst.len();
+        assert!(
+            src.len() == len && mask.len() == len,
+            "{}:{}: Slices are not of the same length",
+            file!(),
+            line!()
+        );
+        ::rrust::$dir! {{
+            let mut i = 0;
+            ::rrust::rloop!(
+                i == 0,
+                {
+                    ::rrust::rif!(
+                        mask[i],
+                        {
+                            dst[i] += src[i];
+                        },
+                        mask[i]
+                    );
+                    i += 1;
+                },
+                i == len
+            );
+            ::rrust::delocal!(i, len);
+        }}
+    }};
+}
+
+/// Polynomial evaluation with Horner's method.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Evaluates the polynomial with the coefficients `$coeffs`, lowest
+/// degree first, at `$x` and accumulates it into the integer place
+/// `$acc`. For every coefficient, starting with the highest degree,
+/// `$acc` is multiplied by `$x` and the coefficient is added. So when
+/// `$acc` starts out as zero it ends up holding the value of the
+/// polynomial.
+///
+/// In reverse the coefficients are subtracted in the opposite order
+/// and `$acc` is divided by `$x`. For this to be possible `$x` can not
+/// be zero, this is asserted in both directions.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, horner};
+/// rfn!(Eval, (acc: &mut i64, coeffs: &[i64], x: &i64), {
+///     horner!(*acc, coeffs, *x);
+/// });
+///
+/// let mut acc = 0;
+///
+/// // 1 + 2x + 3x^2
+/// Eval::forward(&mut acc, &[1, 2, 3], &2);
+///
+/// assert_eq!(acc, 17);
+///
+/// Eval::backwards(&mut acc, &[1, 2, 3], &2);
+///
+/// assert_eq!(acc, 0);
+/// ```
+#[macro_export]
+macro_rules! horner {
+    ($acc:expr, $coeffs:expr, $x:expr) => {{
+        ::rrust::_alias_check!($acc, $x);
+        let coeffs = &$coeffs[..];
+        let x = $x;
+        let zero = x - x;
+        assert!(
+            x != zero,
+            "{}:{}: Horner's method is not reversible at zero",
+            file!(),
+            line!()
+        );
+        for c in coeffs.iter().rev() {
+            $acc = $acc * x + *c;
+        }
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_horner {
+    ($acc:expr, $coeffs:expr, $x:expr) => {{
+        ::rrust::_alias_check!($acc, $x);
+        let coeffs = &$coeffs[..];
+        let x = $x;
+        let zero = x - x;
+        assert!(
+            x != zero,
+            "{}:{}: Horner's method is not reversible at zero",
+            file!(),
+            line!()
+        );
+        for c in coeffs.iter() {
+            let rest = $acc - *c;
+            assert!(
+                rest % x == zero,
+                "{}:{}: Value is not the result of Horner's method",
+                file!(),
+                line!()
+            );
+            $acc = rest / x;
+        }
+    }};
+}
+
+/// Integer square root with remainder.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Moves the unsigned integer place `$n` into the places `$root` and
+/// `$rem` such that `$root` is the integer square root of `$n` and
+/// `$n == $root * $root + $rem`. Afterwards `$n` is zero, and `$root`
+/// and `$rem` have to be zero beforehand. The root is found by
+/// counting up, so this takes time proportional to the root.
+///
+/// In reverse `$n` is reconstructed from the root and the remainder
+/// which are cleared.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, isqrt_into};
+/// rfn!(Sqrt, (n: &mut u32, root: &mut u32, rem: &mut u32), {
+///     isqrt_into!(*n, *root, *rem);
+/// });
+///
+/// let mut n = 40;
+/// let mut root = 0;
+/// let mut rem = 0;
+///
+/// Sqrt::forward(&mut n, &mut root, &mut rem);
+///
+/// assert_eq!((n, root, rem), (0, 6, 4));
+///
+/// Sqrt::backwards(&mut n, &mut root, &mut rem);
+///
+/// assert_eq!((n, root, rem), (40, 0, 0));
+/// ```
+#[macro_export]
+macro_rules! isqrt_into {
+    ($n:expr, $root:expr, $rem:expr) => {
+        ::rrust::_isqrt_into!(forward, $n, $root, $rem)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_isqrt_into {
+    ($n:expr, $root:expr, $rem:expr) => {
+        ::rrust::_isqrt_into!(reverse, $n, $root, $rem)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _isqrt_into {
+    ($dir:ident, $n:expr, $root:expr, $rem:expr) => {{
+        let n = &mut $n;
+        let root = &mut $root;
+        let rem = &mut $rem;
+        ::rrust::$dir! {{
+            ::rrust::rassert!(*rem == 0);
+            ::rrust::rloop!(
+                *root == 0,
+                {
+                    *root += 1;
+                },
+                *root + 1 > *n / (*root + 1)
+            );
+            *rem += *n - *root * *root;
+            *n -= *root * *root + *rem;
+        }}
+    }};
+}
+
+/// Addition into a cell.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Adds `$e` to the value in the [`Cell`](core::cell::Cell) `$cell`,
+/// which allows reversible code to work on shared mutable state. In
+/// reverse `$e` is subtracted again. `$e` must not read the cell
+/// itself.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, cell_add};
+/// # use std::cell::Cell;
+/// rfn!(Bump, (counter: &Cell<i32>, x: &mut i32), {
+///     cell_add!(*counter, *x);
+/// });
+///
+/// let counter = Cell::new(1);
+/// let mut x = 2;
+///
+/// Bump::forward(&counter, &mut x);
+///
+/// assert_eq!(counter.get(), 3);
+///
+/// Bump::backwards(&counter, &mut x);
+///
+/// assert_eq!(counter.get(), 1);
+/// ```
+#[macro_export]
+macro_rules! cell_add {
+    ($cell:expr, $e:expr) => {{
+        let cell: &core::cell::Cell<_> = &$cell;
+        cell.set(cell.get() + $e);
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_cell_add {
+    ($cell:expr, $e:expr) => {{
+        let cell: &core::cell::Cell<_> = &$cell;
+        cell.set(cell.get() - $e);
+    }};
+}
+
+/// Addition into an atomic.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Adds `$e` to the atomic integer `$atomic`, like
+/// [`AtomicI32`](core::sync::atomic::AtomicI32), with `fetch_add`. In
+/// reverse `$e` is subtracted again with `fetch_sub`. Both wrap around
+/// on overflow. The updates are only reversible as long as no other
+/// thread changes the atomic in between, so this is meant for sharing
+/// types with concurrent code rather than for concurrent use. `$e`
+/// must not read the atomic itself.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, atomic_add};
+/// # use std::sync::atomic::{AtomicI32, Ordering};
+/// rfn!(Bump, (counter: &AtomicI32, x: &mut i32), {
+///     atomic_add!(*counter, *x);
+/// });
+///
+/// let counter = AtomicI32::new(1);
+/// let mut x = 2;
+///
+/// Bump::forward(&counter, &mut x);
+///
+/// assert_eq!(counter.load(Ordering::SeqCst), 3);
+///
+/// Bump::backwards(&counter, &mut x);
+///
+/// assert_eq!(counter.load(Ordering::SeqCst), 1);
+/// ```
+#[macro_export]
+macro_rules! atomic_add {
+    ($atomic:expr, $e:expr) => {{
+        $atomic.fetch_add($e, core::sync::atomic::Ordering::SeqCst);
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_atomic_add {
+    ($atomic:expr, $e:expr) => {{
+        $atomic.fetch_sub($e, core::sync::atomic::Ordering::SeqCst);
+    }};
+}
+
+/// Addition into a non-zero integer.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Adds `$e` to the place `$x` of a non-zero integer type, like
+/// [`NonZeroU32`](core::num::NonZeroU32), which does not support `+=`.
+/// The addition is done on the inner value, it will panic if the
+/// result is zero or overflows. The reverse of
+/// [`nonzero_add`](crate::nonzero_add) is
+/// [`nonzero_sub`](crate::nonzero_sub).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, nonzero_add};
+/// # use std::num::NonZeroU32;
+/// rfn!(Grow, (size: &mut NonZeroU32, x: &mut u32), {
+///     nonzero_add!(*size, *x);
+/// });
+///
+/// let mut size = NonZeroU32::new(4).unwrap();
+/// let mut x = 3;
+///
+/// Grow::forward(&mut size, &mut x);
+///
+/// assert_eq!(size.get(), 7);
+///
+/// Grow::backwards(&mut size, &mut x);
+///
+/// assert_eq!(size.get(), 4);
+/// ```
+#[macro_export]
+macro_rules! nonzero_add {
+    ($x:expr, $e:expr) => {
+        ::rrust::_nonzero_step!($x, $e, checked_add)
+    };
+}
+
+/// Subtraction from a non-zero integer.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Subtracts `$e` from the place `$x` of a non-zero integer type, like
+/// [`NonZeroU32`](core::num::NonZeroU32). The subtraction is done on
+/// the inner value, it will panic if the result is zero or overflows.
+/// The reverse of [`nonzero_sub`](crate::nonzero_sub) is
+/// [`nonzero_add`](crate::nonzero_add).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, nonzero_sub};
+/// # use std::num::NonZeroU32;
+/// rfn!(Shrink, (size: &mut NonZeroU32, x: &mut u32), {
+///     nonzero_sub!(*size, *x);
+/// });
+///
+/// let mut size = NonZeroU32::new(4).unwrap();
+/// let mut x = 3;
+///
+/// Shrink::forward(&mut size, &mut x);
+///
+/// assert_eq!(size.get(), 1);
+///
+/// Shrink::backwards(&mut size, &mut x);
+///
+/// assert_eq!(size.get(), 4);
+/// ```
+#[macro_export]
+macro_rules! nonzero_sub {
+    ($x:expr, $e:expr) => {
+        ::rrust::_nonzero_step!($x, $e, checked_sub)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _nonzero_step {
+    ($x:expr, $e:expr, $checked:ident) => {{
+        ::rrust::_alias_check!($x, $e);
+        $x = $x
+            .get()
+            .$checked($e)
+            .and_then(|v| ::core::convert::TryFrom::try_from(v).ok())
+            .unwrap_or_else(|| {
+                panic!(
+                    "{}:{}: Result of the non-zero operation is zero or overflows",
+                    file!(),
+                    line!()
+                )
+            });
+    }};
+}
+
+/// Radix-2 butterfly.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Applies the butterfly of a radix-2 FFT to the complex integers
+/// `$a` and `$b`, stored as `(re, im)` pairs, with the complex twiddle
+/// factor `$twiddle`. Afterwards `$a` holds `a + w * b` and `$b` holds
+/// `a - w * b`. The values are copied, so `$a` and `$b` can be two
+/// elements of the same slice, like in an in-place FFT stage, but they
+/// can not be the same place.
+///
+/// In reverse the sum and the difference are halved to get back `a`
+/// and `w * b`, which is then divided by the twiddle factor. This is
+/// exact for values produced by the butterfly, otherwise it will
+/// panic. The twiddle factor can not be zero.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, butterfly};
+/// rfn!(Butterfly, (a: &mut (i32, i32), b: &mut (i32, i32), w: &(i32, i32)), {
+///     butterfly!(*a, *b, *w);
+/// });
+///
+/// let mut a = (1, 2);
+/// let mut b = (3, 4);
+///
+/// Butterfly::forward(&mut a, &mut b, &(0, -1));
+///
+/// assert_eq!((a, b), ((5, -1), (-3, 5)));
+///
+/// Butterfly::backwards(&mut a, &mut b, &(0, -1));
+///
+/// assert_eq!((a, b), ((1, 2), (3, 4)));
+/// ```
+#[macro_export]
+macro_rules! butterfly {
+    ($a:expr, $b:expr, $twiddle:expr) => {{
+        ::rrust::_alias_check!($a, $b);
+        // The values are copied in and written back, so `$a` and `$b`
+        // can be elements of the same slice.
+        let (a, b) = ($a, $b);
+        let (wr, wi) = $twiddle;
+        assert!(
+            wr != 0 || wi != 0,
+            "{}:{}: Twiddle factor is zero",
+            file!(),
+            line!()
+        );
+        let (tr, ti) = (b.0 * wr - b.1 * wi, b.0 * wi + b.1 * wr);
+        $b = (a.0 - tr, a.1 - ti);
+        $a = (a.0 + tr, a.1 + ti);
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_butterfly {
+    ($a:expr, $b:expr, $twiddle:expr) => {{
+        ::rrust::_alias_check!($a, $b);
+        let (a, b) = ($a, $b);
+        let (wr, wi) = $twiddle;
+        assert!(
+            wr != 0 || wi != 0,
+            "{}:{}: Twiddle factor is zero",
+            file!(),
+            line!()
+        );
+        assert!(
+            (a.0 + b.0) % 2 == 0 && (a.1 + b.1) % 2 == 0,
+            "{}:{}: Values are not the result of a butterfly",
+            file!(),
+            line!()
+        );
+        let (tr, ti) = ((a.0 - b.0) / 2, (a.1 - b.1) / 2);
+        let norm = wr * wr + wi * wi;
+        let (br, bi) = (tr * wr + ti * wi, ti * wr - tr * wi);
+        assert!(
+            br % norm == 0 && bi % norm == 0,
+            "{}:{}: Values are not the result of a butterfly",
+            file!(),
+            line!()
+        );
+        $a = ((a.0 + b.0) / 2, (a.1 + b.1) / 2);
+        $b = (br / norm, bi / norm);
+    }};
+}
+
+/// Modular multiplication by a unit.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Multiplies the place `$a` by `$k` modulo `$m`. For this to be
+/// reversible `$k` has to be a unit modulo `$m`, that is `gcd($k, $m)`
+/// has to be 1, and `$a` has to be in the range `0..$m`, both are
+/// asserted. In reverse `$a` is multiplied by the modular inverse of
+/// `$k` instead.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, mul_mod_unit};
+/// rfn!(Scale, (a: &mut u32), {
+///     mul_mod_unit!(*a, 3, 7);
+/// });
+///
+/// let mut a = 5;
+///
+/// Scale::forward(&mut a);
+///
+/// assert_eq!(a, 1);
+///
+/// Scale::backwards(&mut a);
+///
+/// assert_eq!(a, 5);
+/// ```
+#[macro_export]
+macro_rules! mul_mod_unit {
+    ($a:expr, $k:expr, $m:expr) => {{
+        ::rrust::_alias_check!($a, $k);
+        let k = $k as i128;
+        let m = $m as i128;
+        let _ = ::rrust::_mod_inverse!(k, m);
+        assert!(
+            (0..m).contains(&($a as i128)),
+            "{}:{}: Value is out of modular range",
+            file!(),
+            line!()
+        );
+        $a = (($a as i128) * k.rem_euclid(m) % m) as _;
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_mul_mod_unit {
+    ($a:expr, $k:expr, $m:expr) => {{
+        ::rrust::_alias_check!($a, $k);
+        let k = $k as i128;
+        let m = $m as i128;
+        let inverse = ::rrust::_mod_inverse!(k, m);
+        assert!(
+            (0..m).contains(&($a as i128)),
+            "{}:{}: Value is out of modular range",
+            file!(),
+            line!()
+        );
+        $a = (($a as i128) * inverse % m) as _;
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _mod_inverse {
+    ($k:expr, $m:expr) => {{
+        let m: i128 = $m;
+        let (mut r0, mut r1) = (m, ($k as i128).rem_euclid(m));
+        let (mut t0, mut t1) = (0i128, 1i128);
+        while r1 != 0 {
+            let q = r0 / r1;
+            (r0, r1) = (r1, r0 - q * r1);
+            (t0, t1) = (t1, t0 - q * t1);
+        }
+        assert!(
+            r0 == 1,
+            "{}:{}: Factor is not a unit modulo the modulus",
+            file!(),
+            line!()
+        );
+        t0.rem_euclid(m)
+    }};
+}
+
+/// Greatest common divisor by subtraction.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Runs the subtractive Euclidean algorithm on the positive integer
+/// places `$a` and `$b`, afterwards both hold their greatest common
+/// divisor. The algorithm throws information away, so the decision of
+/// every step is recorded in the slice `$steps`, `1` when `$a` was
+/// reduced and `2` when `$b` was reduced. The slice has to be zero
+/// beforehand and long enough to hold every step.
+///
+/// In reverse the steps are replayed backwards to restore `$a` and
+/// `$b`, clearing `$steps` again.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, gcd_reversible};
+/// rfn!(Gcd, (a: &mut u32, b: &mut u32, steps: &mut [u8]), {
+///     gcd_reversible!(*a, *b, *steps);
+/// });
+///
+/// let mut a = 12;
+/// let mut b = 18;
+/// let mut steps = [0; 8];
+///
+/// Gcd::forward(&mut a, &mut b, &mut steps);
+///
+/// assert_eq!((a, b), (6, 6));
+/// assert_eq!(steps, [2, 1, 0, 0, 0, 0, 0, 0]);
+///
+/// Gcd::backwards(&mut a, &mut b, &mut steps);
+///
+/// assert_eq!((a, b), (12, 18));
+/// assert_eq!(steps, [0; 8]);
+/// ```
+#[macro_export]
+macro_rules! gcd_reversible {
+    ($a:expr, $b:expr, $steps:expr) => {
+        ::rrust::_gcd_reversible!(forward, $a, $b, $steps)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_gcd_reversible {
+    ($a:expr, $b:expr, $steps:expr) => {
+        ::rrust::_gcd_reversible!(reverse, $a, $b, $steps)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _gcd_reversible {
+    ($dir:ident, $a:expr, $b:expr, $steps:expr) => {{
+        let a = &mut $a;
+        let b = &mut $b;
+        let steps = &mut $steps;
+        ::rrust::$dir! {{
+            ::rrust::rassert!(*a > 0 && *b > 0, "{}:{}: Operands have to be positive", file!(), line!());
+            let mut n = 0;
+            ::rrust::rif!(
+                *a != *b,
+                {
+                    ::rrust::rloop!(
+                        n == 0,
+                        {
+                            ::rrust::rif!(
+                                *a > *b,
+                                {
+                                    *a -= *b;
+                                    steps[n] += 1;
+                                },
+                                {
+                                    *b -= *a;
+                                    steps[n] += 2;
+                                },
+                                steps[n] == 1
+                            );
+                            n += 1;
+                        },
+                        *a == *b
+                    );
+                },
+                {},
+                n != 0
+            );
+            ::rrust::delocal!(n, steps.iter().take_while(|s| **s != 0).count());
+        }}
+    }};
+}
+
+/// Complex multiplication by a unit.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Multiplies the complex number with the real part in the place `$re`
+/// and the imaginary part in the place `$im` by `$c_re + $c_im i`. The
+/// multiplier has to have modulus 1, so for integers it is a rotation
+/// by a multiple of 90 degrees, this is asserted. In reverse the
+/// number is multiplied by the conjugate of the multiplier, which is
+/// its inverse.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, cmul_unit};
+/// rfn!(Rotate, (re: &mut i32, im: &mut i32), {
+///     cmul_unit!(*re, *im, 0, 1);
+/// });
+///
+/// let mut re = 3;
+/// let mut im = 4;
+///
+/// Rotate::forward(&mut re, &mut im);
+///
+/// assert_eq!((re, im), (-4, 3));
+///
+/// Rotate::backwards(&mut re, &mut im);
+///
+/// assert_eq!((re, im), (3, 4));
+/// ```
+#[macro_export]
+macro_rules! cmul_unit {
+    ($re:expr, $im:expr, $c_re:expr, $c_im:expr) => {{
+        let (c_re, c_im) = ($c_re, $c_im);
+        ::rrust::_cmul_unit!($re, $im, c_re, c_im);
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_cmul_unit {
+    ($re:expr, $im:expr, $c_re:expr, $c_im:expr) => {{
+        let (c_re, c_im) = ($c_re, $c_im);
+        ::rrust::_cmul_unit!($re, $im, c_re, -c_im);
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _cmul_unit {
+    ($re:expr, $im:expr, $c_re:expr, $c_im:expr) => {{
+        ::rrust::_alias_check!($re, $im);
+        let (c_re, c_im) = ($c_re, $c_im);
+        assert!(
+            c_re * c_re + c_im * c_im == 1,
+            "{}:{}: Multiplier is not a unit",
+            file!(),
+            line!()
+        );
+        let (re, im) = ($re, $im);
+        $re = re * c_re - im * c_im;
+        $im = re * c_im + im * c_re;
+    }};
+}
+
+/// Linear feedback shift register step.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Steps the unsigned integer place `$state` holding a Fibonacci
+/// linear feedback shift register. The highest set bit of `$taps`
+/// gives the width of the register and the parity of the tapped bits
+/// is shifted in at the bottom. Since the top bit is always tapped the
+/// bit shifted out can be recovered, so the reverse steps the register
+/// back. With maximal-length taps the states run through every nonzero
+/// value of the width, like a de Bruijn sequence without the zero.
+///
+/// The state has to fit in the width of the register, this is
+/// asserted.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, debruijn_step};
+/// rfn!(Step, (state: &mut u8), {
+///     debruijn_step!(*state, 0b1001);
+///     debruijn_step!(*state, 0b1001);
+/// });
+///
+/// let mut state = 1;
+///
+/// Step::forward(&mut state);
+///
+/// assert_eq!(state, 7);
+///
+/// Step::backwards(&mut state);
+///
+/// assert_eq!(state, 1);
+/// ```
+#[macro_export]
+macro_rules! debruijn_step {
+    ($state:expr, $taps:expr) => {{
+        let (_, mask) = ::rrust::_lfsr_mask!($state, $taps);
+        let feedback = ($state & $taps).count_ones() % 2 == 1;
+        $state = $state << 1 & mask;
+        if feedback {
+            $state ^= 1;
+        }
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_debruijn_step {
+    ($state:expr, $taps:expr) => {{
+        let (high, _) = ::rrust::_lfsr_mask!($state, $taps);
+        let feedback = $state & 1 == 1;
+        $state >>= 1;
+        if (($state & $taps).count_ones() % 2 == 1) != feedback {
+            $state ^= high;
+        }
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _lfsr_mask {
+    ($state:expr, $taps:expr) => {{
+        let taps = $taps;
+        assert!(taps != 0, "{}:{}: No bits are tapped", file!(), line!());
+        let mut high = taps;
+        while high & (high - 1) != 0 {
+            high &= high - 1;
+        }
+        let mask = high | (high - 1);
+        assert!(
+            $state & !mask == 0,
+            "{}:{}: State does not fit in the register",
+            file!(),
+            line!()
+        );
+        (high, mask)
+    }};
+}
+
+/// Modular affine map.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Maps the place `$x` to `($a * $x + $b) % $m` using
+/// [`mul_mod_unit`](crate::mul_mod_unit) followed by
+/// [`add_mod`](crate::add_mod), so `$a` has to be a unit modulo `$m`
+/// and `$x` has to be in the range `0..$m`. The map is then a
+/// permutation of the range, and in reverse the inverse map is
+/// applied.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, affine};
+/// rfn!(Shuffle, (x: &mut u32), {
+///     affine!(*x, 3, 4, 10);
+/// });
+///
+/// let mut x = 7;
+///
+/// Shuffle::forward(&mut x);
+///
+/// assert_eq!(x, 5);
+///
+/// Shuffle::backwards(&mut x);
+///
+/// assert_eq!(x, 7);
+/// ```
+#[macro_export]
+macro_rules! affine {
+    ($x:expr, $a:expr, $b:expr, $m:expr) => {
+        ::rrust::_affine!(forward, $x, $a, $b, $m)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_affine {
+    ($x:expr, $a:expr, $b:expr, $m:expr) => {
+        ::rrust::_affine!(reverse, $x, $a, $b, $m)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _affine {
+    ($dir:ident, $x:expr, $a:expr, $b:expr, $m:expr) => {{
+        let x = &mut $x;
+        let (a, b, m) = ($a, $b, $m);
+        ::rrust::$dir! {{
+            ::rrust::mul_mod_unit!(*x, a, m);
+            ::rrust::add_mod!(*x, b, m);
+        }}
+    }};
+}
+
+/// Token bucket consumption.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Takes `$n` tokens from the bucket `$tokens` if there are enough of
+/// them, and records whether this succeeded in the boolean place
+/// `$ok`. `$ok` has to be `false` beforehand, this is asserted. In
+/// reverse the tokens are only added back if `$ok` is set, which is
+/// then cleared again.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, bucket_consume};
+/// rfn!(Consume, (tokens: &mut u32, n: &u32, ok: &mut bool), {
+///     bucket_consume!(*tokens, *n, *ok);
+/// });
+///
+/// let mut tokens = 5;
+/// let mut ok = false;
+///
+/// Consume::forward(&mut tokens, &3, &mut ok);
+///
+/// assert_eq!((tokens, ok), (2, true));
+///
+/// Consume::backwards(&mut tokens, &3, &mut ok);
+///
+/// assert_eq!((tokens, ok), (5, false));
+/// ```
+#[macro_export]
+macro_rules! bucket_consume {
+    ($tokens:expr, $n:expr, $ok:expr) => {
+        ::rrust::_bucket_consume!(forward, $tokens, $n, $ok)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_bucket_consume {
+    ($tokens:expr, $n:expr, $ok:expr) => {
+        ::rrust::_bucket_consume!(reverse, $tokens, $n, $ok)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _bucket_consume {
+    ($dir:ident, $tokens:expr, $n:expr, $ok:expr) => {{
+        let tokens = &mut $tokens;
+        let ok: &mut bool = &mut $ok;
+        let n = $n;
+        ::rrust::$dir! {{
+            assert!(!*ok, "{}:{}: Ok bit is already set", file!(), line!());
+            ::rrust::rif!(
+                *tokens >= n,
+                {
+                    *tokens -= n;
+                    ::rrust::toggle!(*ok);
+                },
+                {},
+                *ok
+            );
+        }}
+    }};
+}
+
+/// Zobrist hash update.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// XORs the key `$key` of a feature into the Zobrist hash `$hash`, like
+/// a piece on a square of a board. Toggling the same key again removes
+/// the feature from the hash, so this is its own reverse. It will panic
+/// if `$hash` and `$key` are aliases of each other, as this would clear
+/// the hash.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, zobrist_toggle};
+/// rfn!(Move, (hash: &mut u64, keys: &[u64; 4], from: &usize, to: &usize), {
+///     zobrist_toggle!(*hash, keys[*from]);
+///     zobrist_toggle!(*hash, keys[*to]);
+/// });
+///
+/// let keys = [0x9e37, 0x79b9, 0x7f4a, 0x7c15];
+/// let mut hash = keys[1];
+///
+/// Move::forward(&mut hash, &keys, &1, &3);
+///
+/// assert_eq!(hash, keys[3]);
+///
+/// Move::backwards(&mut hash, &keys, &1, &3);
+///
+/// assert_eq!(hash, keys[1]);
+/// ```
+#[macro_export]
+macro_rules! zobrist_toggle {
+    ($hash:expr, $key:expr) => {{
+        ::rrust::_alias_check!($hash, $key);
+        $hash ^= $key;
+    }};
+}
+
+/// Rotation of digit groups.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Rotates the digits of the unsigned place `$x` in base `$base` one
+/// step to the right, as a number of `$groups` digits. The lowest
+/// digit is moved to the top and the others are shifted down, so
+/// groups of decimal digits can be rotated with a base like `100`.
+/// Applying it `$groups` times gives back the original value. It will
+/// panic if `$x` has more than `$groups` digits or if `$base` to the
+/// power of `$groups` does not fit in the type, `$groups` is a `u32`.
+///
+/// In reverse the top digit is moved back to the bottom.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, digit_rotate};
+/// rfn!(Rotate, (x: &mut u32), {
+///     digit_rotate!(*x, 3, 100);
+/// });
+///
+/// let mut x = 12_34_56;
+///
+/// Rotate::forward(&mut x);
+///
+/// assert_eq!(x, 56_12_34);
+///
+/// Rotate::backwards(&mut x);
+///
+/// assert_eq!(x, 12_34_56);
+/// ```
+#[macro_export]
+macro_rules! digit_rotate {
+    ($x:expr, $groups:expr, $base:expr) => {{
+        let (top, base, low) = ::rrust::_digit_top!($x, $groups, $base);
+        $x = $x / base + low * top;
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_digit_rotate {
+    ($x:expr, $groups:expr, $base:expr) => {{
+        let (top, base, _) = ::rrust::_digit_top!($x, $groups, $base);
+        let high = $x / top;
+        $x = ($x - high * top) * base + high;
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _digit_top {
+    ($x:expr, $groups:expr, $base:expr) => {{
+        let groups: u32 = $groups;
+        let base = $base;
+        assert!(
+            groups > 0 && base > 1,
+            "{}:{}: Digit rotation needs a digit and a base of at least two",
+            file!(),
+            line!()
+        );
+        // Taking the lowest digit first gives the base the type of `$x`.
+        let low = $x % base;
+        // All `$groups` digits have to fit in the type, then no value
+        // in range overflows when it is rotated in either direction.
+        assert!(
+            base.checked_pow(groups).is_some(),
+            "{}:{}: Digit groups do not fit in the type",
+            file!(),
+            line!()
+        );
+        let top = base.pow(groups - 1);
+        assert!(
+            $x / top < base,
+            "{}:{}: Value has more digits than the groups",
+            file!(),
+            line!()
+        );
+        (top, base, low)
+    }};
+}
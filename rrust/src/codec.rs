@@ -0,0 +1,1025 @@
+//! Reversible encodings of numbers and data.
+
+/// Digit extraction.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Peels the digits of the place `$num` in base `$base` into the
+/// slice `$digits`, least significant digit first, dividing `$num`
+/// by the base for each digit. All elements of `$digits` have to be
+/// zero beforehand. Afterwards `$num` holds the part which did not
+/// fit in `$digits`, which is zero when there are enough digits. In
+/// reverse `$num` is reconstructed from the digits and the digits
+/// are cleared.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, to_digits};
+/// rfn!(Digits, (num: &mut u32, digits: &mut [u32]), {
+///     to_digits!(*num, digits, 10);
+/// });
+///
+/// let mut num = 907;
+/// let mut digits = [0; 4];
+///
+/// Digits::forward(&mut num, &mut digits);
+///
+/// assert_eq!(num, 0);
+/// assert_eq!(digits, [7, 0, 9, 0]);
+///
+/// Digits::backwards(&mut num, &mut digits);
+///
+/// assert_eq!(num, 907);
+/// assert_eq!(digits, [0; 4]);
+/// ```
+#[macro_export]
+macro_rules! to_digits {
+    ($num:expr, $digits:expr, $base:expr) => {
+        ::rrust::_to_digits!(forward, $num, $digits, $base)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_to_digits {
+    ($num:expr, $digits:expr, $base:expr) => {
+        ::rrust::_to_digits!(reverse, $num, $digits, $base)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _to_digits {
+    ($dir:ident, $num:expr, $digits:expr, $base:expr) => {{
+        let num = &mut $num;
+        let digits = &mut $digits[..];
+        let base = $base;
+        let len = digits.len();
+        ::rrust::$dir! {{
+            let mut i = 0;
+            ::rrust::rloop!(
+                i == 0,
+                {
+                    digits[i] += *num % base;
+                    let mut q = *num / base;
+                    ::rrust::rswap!(q, *num);
+                    ::rrust::delocal!(q, *num * base + digits[i]);
+                    i += 1;
+                },
+                i == len
+            );
+            ::rrust::delocal!(i, len);
+        }}
+    }};
+}
+
+/// Feistel round.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Runs a single round of a Feistel network on the block formed by
+/// the places `$left` and `$right`, `$left` is xored with the result
+/// of `$round_fn` applied to `$right` after which the two halves are
+/// swapped. In reverse the halves are swapped back before the same
+/// xor is applied again, which undoes it.
+///
+/// The round function does not need to be invertible, but it has to
+/// give the same result in both directions. To help with this it has
+/// to be a function or a closure which does not capture anything,
+/// and it only gets a copy of `$right`.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, feistel_round};
+/// fn round(x: u8) -> u8 {
+///     x.rotate_left(3) ^ 0x5a
+/// }
+///
+/// rfn!(Encrypt, (left: &mut u8, right: &mut u8), {
+///     feistel_round!(*left, *right, round);
+/// });
+///
+/// let mut left = 0x12;
+/// let mut right = 0x34;
+///
+/// Encrypt::forward(&mut left, &mut right);
+///
+/// assert_eq!((left, right), (0x34, 0x12 ^ round(0x34)));
+///
+/// Encrypt::backwards(&mut left, &mut right);
+///
+/// assert_eq!((left, right), (0x12, 0x34));
+/// ```
+#[macro_export]
+macro_rules! feistel_round {
+    ($left:expr, $right:expr, $round_fn:expr) => {
+        ::rrust::_feistel_round!(forward, $left, $right, $round_fn)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_feistel_round {
+    ($left:expr, $right:expr, $round_fn:expr) => {
+        ::rrust::_feistel_round!(reverse, $left, $right, $round_fn)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _feistel_round {
+    ($dir:ident, $left:expr, $right:expr, $round_fn:expr) => {{
+        let left = &mut $left;
+        let right = &mut $right;
+        let round_fn: fn(_) -> _ = $round_fn;
+        ::rrust::$dir! {{
+            *left ^= round_fn(*right);
+            ::rrust::rswap!(*left, *right);
+        }}
+    }};
+}
+
+/// Checksum trailer.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Stores the checksum of the byte slice `$payload` in the trailer
+/// place `$trailer`, the checksum is the wrapping sum of the bytes.
+/// The trailer has to be zero beforehand, this is asserted.
+///
+/// In reverse the checksum is verified and the trailer is cleared, it
+/// will panic if the checksum does not match the payload.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, checksum_frame};
+/// rfn!(Frame, (payload: &[u8], trailer: &mut u8), {
+///     checksum_frame!(payload, *trailer);
+/// });
+///
+/// let mut trailer = 0;
+///
+/// Frame::forward(&[1, 2, 255], &mut trailer);
+///
+/// assert_eq!(trailer, 2);
+///
+/// Frame::backwards(&[1, 2, 255], &mut trailer);
+///
+/// assert_eq!(trailer, 0);
+/// ```
+#[macro_export]
+macro_rules! checksum_frame {
+    ($payload:expr, $trailer:expr) => {{
+        let sum = ::rrust::_checksum!($payload);
+        assert!(
+            $trailer == 0,
+            "{}:{}: Trailer is not empty",
+            file!(),
+            line!()
+        );
+        $trailer ^= sum;
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_checksum_frame {
+    ($payload:expr, $trailer:expr) => {{
+        let sum = ::rrust::_checksum!($payload);
+        assert!(
+            $trailer == sum,
+            "{}:{}: Checksum does not match the payload",
+            file!(),
+            line!()
+        );
+        $trailer ^= sum;
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _checksum {
+    ($payload:expr) => {
+        $payload
+            .iter()
+            .fold(0u8, |sum: u8, byte: &u8| sum.wrapping_add(*byte))
+    };
+}
+
+/// Run-length encoding.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Moves the bytes of the slice `$input` into the slice of runs
+/// `$runs`, every run is a pair of a byte and the number of times it
+/// is repeated. Afterwards `$input` is zeroed and the runs are stored
+/// at the start of `$runs`, the rest of it is left as `(0, 0)`. All
+/// runs have to be `(0, 0)` beforehand and there has to be room for
+/// all of the runs, this is asserted. The reverse of
+/// [`rle_encode`](crate::rle_encode) is [`rle_decode`](crate::rle_decode).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, rle_encode};
+/// rfn!(Encode, (input: &mut [u8], runs: &mut [(u8, usize)]), {
+///     rle_encode!(input, runs);
+/// });
+///
+/// let mut input = *b"aaab";
+/// let mut runs = [(0, 0); 4];
+///
+/// Encode::forward(&mut input, &mut runs);
+///
+/// assert_eq!(input, [0; 4]);
+/// assert_eq!(runs, [(b'a', 3), (b'b', 1), (0, 0), (0, 0)]);
+///
+/// Encode::backwards(&mut input, &mut runs);
+///
+/// assert_eq!(input, *b"aaab");
+/// assert_eq!(runs, [(0, 0); 4]);
+/// ```
+#[macro_export]
+macro_rules! rle_encode {
+    ($input:expr, $runs:expr) => {{
+        let input = &mut $input[..];
+        let runs = &mut $runs[..];
+        assert!(
+            runs.iter().all(|run| *run == (0, 0)),
+            "{}:{}: Runs are not empty",
+            file!(),
+            line!()
+        );
+        let mut r = 0;
+        let mut i = 0;
+        while i < input.len() {
+            assert!(
+                r < runs.len(),
+                "{}:{}: Not enough room for the runs",
+                file!(),
+                line!()
+            );
+            let byte = input[i];
+            runs[r].0 ^= byte;
+            while i < input.len() && input[i] == byte {
+                input[i] ^= byte;
+                runs[r].1 += 1;
+                i += 1;
+            }
+            r += 1;
+        }
+    }};
+}
+
+/// Run-length decoding.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Moves the runs in `$runs` back into the byte slice `$input`, which
+/// has to be zeroed beforehand. The runs have to be as produced by
+/// [`rle_encode`](crate::rle_encode), that is two runs next to each
+/// other can not repeat the same byte, unused runs are `(0, 0)` and
+/// come last, and the runs have to cover all of `$input`. This is
+/// asserted. Afterwards all runs are `(0, 0)`. The reverse of
+/// [`rle_decode`](crate::rle_decode) is [`rle_encode`](crate::rle_encode).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, rle_decode};
+/// rfn!(Decode, (input: &mut [u8], runs: &mut [(u8, usize)]), {
+///     rle_decode!(input, runs);
+/// });
+///
+/// let mut input = [0; 3];
+/// let mut runs = [(b'x', 2), (b'y', 1), (0, 0)];
+///
+/// Decode::forward(&mut input, &mut runs);
+///
+/// assert_eq!(input, *b"xxy");
+/// assert_eq!(runs, [(0, 0); 3]);
+///
+/// Decode::backwards(&mut input, &mut runs);
+///
+/// assert_eq!(runs, [(b'x', 2), (b'y', 1), (0, 0)]);
+/// ```
+#[macro_export]
+macro_rules! rle_decode {
+    ($input:expr, $runs:expr) => {{
+        let input = &mut $input[..];
+        let runs = &mut $runs[..];
+        assert!(
+            input.iter().all(|byte| *byte == 0),
+            "{}:{}: Input is not empty",
+            file!(),
+            line!()
+        );
+        let mut i = 0;
+        let mut previous = None;
+        let mut ended = false;
+        for run in runs.iter_mut() {
+            let (byte, len) = *run;
+            assert!(
+                if len == 0 {
+                    byte == 0
+                } else {
+                    !ended && previous != Some(byte) && i + len <= input.len()
+                },
+                "{}:{}: Runs are not well formed",
+                file!(),
+                line!()
+            );
+            ended |= len == 0;
+            previous = Some(byte);
+            while run.1 > 0 {
+                input[i] ^= byte;
+                run.1 -= 1;
+                i += 1;
+            }
+            run.0 ^= byte;
+        }
+        assert!(
+            i == input.len(),
+            "{}:{}: Runs do not cover the input",
+            file!(),
+            line!()
+        );
+    }};
+}
+
+/// Nibble packing.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Moves the two 4-bit nibbles in the unsigned integer places `$hi`
+/// and `$lo` into the place `$out` as the byte `$hi << 4 | $lo`.
+/// Afterwards `$hi` and `$lo` are zero. `$out` has to be zero
+/// beforehand and both nibbles have to be less than 16, this is
+/// asserted. The reverse is [`unpack_nibbles`](crate::unpack_nibbles).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, pack_nibbles};
+/// rfn!(Pack, (out: &mut u8, hi: &mut u8, lo: &mut u8), {
+///     pack_nibbles!(*out, *hi, *lo);
+/// });
+///
+/// let mut out = 0;
+/// let mut hi = 0xa;
+/// let mut lo = 0x5;
+///
+/// Pack::forward(&mut out, &mut hi, &mut lo);
+///
+/// assert_eq!((out, hi, lo), (0xa5, 0, 0));
+///
+/// Pack::backwards(&mut out, &mut hi, &mut lo);
+///
+/// assert_eq!((out, hi, lo), (0, 0xa, 0x5));
+/// ```
+#[macro_export]
+macro_rules! pack_nibbles {
+    ($out:expr, $hi:expr, $lo:expr) => {{
+        ::rrust::_alias_check!($out, $hi);
+        ::rrust::_alias_check!($out, $lo);
+        ::rrust::_alias_check!($hi, $lo);
+        assert!($out == 0, "{}:{}: Output is not zero", file!(), line!());
+        assert!(
+            $hi < 16 && $lo < 16,
+            "{}:{}: Value is not a nibble",
+            file!(),
+            line!()
+        );
+        $out ^= $hi << 4 ^ $lo;
+        $hi ^= $out >> 4;
+        $lo ^= $out & 0xf;
+    }};
+}
+
+/// Nibble unpacking.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Moves the byte in the unsigned integer place `$out` into the places
+/// `$hi` and `$lo` as its high and low 4-bit nibbles. Afterwards
+/// `$out` is zero. `$hi` and `$lo` have to be zero beforehand and
+/// `$out` has to be less than 256, this is asserted. The reverse is
+/// [`pack_nibbles`](crate::pack_nibbles).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, unpack_nibbles};
+/// rfn!(Unpack, (out: &mut u8, hi: &mut u8, lo: &mut u8), {
+///     unpack_nibbles!(*out, *hi, *lo);
+/// });
+///
+/// let mut out = 0x3c;
+/// let mut hi = 0;
+/// let mut lo = 0;
+///
+/// Unpack::forward(&mut out, &mut hi, &mut lo);
+///
+/// assert_eq!((out, hi, lo), (0, 0x3, 0xc));
+///
+/// Unpack::backwards(&mut out, &mut hi, &mut lo);
+///
+/// assert_eq!((out, hi, lo), (0x3c, 0, 0));
+/// ```
+#[macro_export]
+macro_rules! unpack_nibbles {
+    ($out:expr, $hi:expr, $lo:expr) => {{
+        ::rrust::_alias_check!($out, $hi);
+        ::rrust::_alias_check!($out, $lo);
+        ::rrust::_alias_check!($hi, $lo);
+        assert!(
+            $hi == 0 && $lo == 0,
+            "{}:{}: Nibbles are not zero",
+            file!(),
+            line!()
+        );
+        assert!(
+            $out >> 4 < 16,
+            "{}:{}: Value is not a byte",
+            file!(),
+            line!()
+        );
+        $hi ^= $out >> 4;
+        $lo ^= $out & 0xf;
+        $out ^= $hi << 4 ^ $lo;
+    }};
+}
+
+/// Binary-coded decimal conversion.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Moves the unsigned integer place `$value` into the byte slice `$bcd`
+/// as packed binary-coded decimal, two decimal digits per byte with the
+/// less significant digit in the low nibble, least significant byte
+/// first. Afterwards `$value` is zero. All bytes of `$bcd` have to be
+/// zero beforehand and the value has to fit in the digits, this is
+/// asserted.
+///
+/// In reverse `$value` is reconstructed from the digits and the bytes
+/// are cleared, it panics if a nibble is not a decimal digit.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, to_bcd};
+/// rfn!(Bcd, (value: &mut u32, bcd: &mut [u8]), {
+///     to_bcd!(*value, bcd);
+/// });
+///
+/// let mut value = 90417;
+/// let mut bcd = [0; 3];
+///
+/// Bcd::forward(&mut value, &mut bcd);
+///
+/// assert_eq!(value, 0);
+/// assert_eq!(bcd, [0x17, 0x04, 0x09]);
+///
+/// Bcd::backwards(&mut value, &mut bcd);
+///
+/// assert_eq!(value, 90417);
+/// assert_eq!(bcd, [0; 3]);
+/// ```
+#[macro_export]
+macro_rules! to_bcd {
+    ($value:expr, $bcd:expr) => {{
+        let bcd = &mut $bcd[..];
+        assert!(
+            bcd.iter().all(|byte| *byte == 0),
+            "{}:{}: BCD digits are not zero",
+            file!(),
+            line!()
+        );
+        let mut value = $value as u128;
+        for byte in bcd.iter_mut() {
+            *byte ^= (value % 10) as u8;
+            value /= 10;
+            *byte ^= ((value % 10) as u8) << 4;
+            value /= 10;
+        }
+        assert!(
+            value == 0,
+            "{}:{}: Value does not fit in the BCD digits",
+            file!(),
+            line!()
+        );
+        $value = value as _;
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_to_bcd {
+    ($value:expr, $bcd:expr) => {{
+        let bcd = &mut $bcd[..];
+        assert!($value == 0, "{}:{}: Value is not zero", file!(), line!());
+        let mut value: u128 = 0;
+        for byte in bcd.iter_mut().rev() {
+            let (hi, lo) = (*byte >> 4, *byte & 0xf);
+            assert!(
+                hi < 10 && lo < 10,
+                "{}:{}: Nibble is not a decimal digit",
+                file!(),
+                line!()
+            );
+            value = value * 100 + hi as u128 * 10 + lo as u128;
+            *byte = 0;
+        }
+        $value = value as _;
+    }};
+}
+
+/// CRC register step.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Shifts the boolean `$bit` into the unsigned integer CRC register
+/// `$reg` with the generator polynomial `$poly`, most significant bit
+/// first. The bit shifted out of the register is stored in the boolean
+/// place `$out_bit`, which has to be `false` beforehand. Running a
+/// step for every bit of a message leaves its CRC in the register.
+///
+/// The polynomial has to have its lowest bit set, this is asserted,
+/// which makes it possible to shift the register back in reverse
+/// using the recorded bit. Afterwards `$out_bit` is `false` again.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, crc_step};
+/// rfn!(Step, (reg: &mut u8, bit: &bool, out_bit: &mut bool), {
+///     crc_step!(*reg, *bit, *out_bit, 0x07);
+/// });
+///
+/// let mut reg = 0x80;
+/// let mut out_bit = false;
+///
+/// Step::forward(&mut reg, &false, &mut out_bit);
+///
+/// assert_eq!((reg, out_bit), (0x07, true));
+///
+/// Step::backwards(&mut reg, &false, &mut out_bit);
+///
+/// assert_eq!((reg, out_bit), (0x80, false));
+/// ```
+#[macro_export]
+macro_rules! crc_step {
+    ($reg:expr, $bit:expr, $out_bit:expr, $poly:expr) => {{
+        let poly = $poly;
+        assert!(
+            poly & 1 == 1,
+            "{}:{}: Polynomial does not have the lowest bit set",
+            file!(),
+            line!()
+        );
+        assert!(!$out_bit, "{}:{}: Out bit is not zero", file!(), line!());
+        let high = !(!($reg ^ $reg) >> 1);
+        let out = $reg & high != 0;
+        $reg <<= 1;
+        if out ^ $bit {
+            $reg ^= poly;
+        }
+        $out_bit ^= out;
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_crc_step {
+    ($reg:expr, $bit:expr, $out_bit:expr, $poly:expr) => {{
+        let poly = $poly;
+        assert!(
+            poly & 1 == 1,
+            "{}:{}: Polynomial does not have the lowest bit set",
+            file!(),
+            line!()
+        );
+        let high = !(!($reg ^ $reg) >> 1);
+        let out = $out_bit;
+        if out ^ $bit {
+            $reg ^= poly;
+        }
+        assert!(
+            $reg & 1 == 0,
+            "{}:{}: Register does not match the out bit",
+            file!(),
+            line!()
+        );
+        $reg >>= 1;
+        if out {
+            $reg ^= high;
+        }
+        $out_bit ^= out;
+    }};
+}
+
+/// Burrows-Wheeler transform.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Replaces the slice `$data` by its Burrows-Wheeler transform, the
+/// last column of its sorted rotations, and adds the row of the
+/// original data among the sorted rotations, the primary index, to
+/// `$index`. `$index` has to be zero beforehand, this is asserted.
+/// The reverse is [`bwt_backward`](crate::bwt_backward).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, bwt_forward};
+/// rfn!(Bwt, (data: &mut [u8], index: &mut usize), {
+///     bwt_forward!(data, *index);
+/// });
+///
+/// let mut data = *b"banana";
+/// let mut index = 0;
+///
+/// Bwt::forward(&mut data, &mut index);
+///
+/// assert_eq!((&data, index), (b"nnbaaa", 3));
+///
+/// Bwt::backwards(&mut data, &mut index);
+///
+/// assert_eq!((&data, index), (b"banana", 0));
+/// ```
+#[macro_export]
+macro_rules! bwt_forward {
+    ($data:expr, $index:expr) => {{
+        let data = &mut $data[..];
+        let index = &mut $index;
+        assert!(*index == 0, "{}:{}: Index is not zero", file!(), line!());
+        let n = data.len();
+        let mut rows: Vec<usize> = (0..n).collect();
+        rows.sort_by(|a, b| {
+            let rotation = |r: usize| (0..n).map(move |k| (r + k) % n);
+            rotation(*a)
+                .map(|k| data[k])
+                .cmp(rotation(*b).map(|k| data[k]))
+        });
+        let last: Vec<_> = rows.iter().map(|r| data[(r + n - 1) % n]).collect();
+        *index += rows.iter().position(|r| *r == 0).unwrap_or(0);
+        data.copy_from_slice(&last);
+    }};
+}
+
+/// Inverse Burrows-Wheeler transform.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Replaces the slice `$data` holding a Burrows-Wheeler transform by
+/// the original data, using the primary index in `$index` which is
+/// cleared afterwards. The index has to be in bounds, this is
+/// asserted. The reverse is [`bwt_forward`](crate::bwt_forward).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, bwt_backward};
+/// rfn!(Unbwt, (data: &mut [u8], index: &mut usize), {
+///     bwt_backward!(data, *index);
+/// });
+///
+/// let mut data = *b"nnbaaa";
+/// let mut index = 3;
+///
+/// Unbwt::forward(&mut data, &mut index);
+///
+/// assert_eq!((&data, index), (b"banana", 0));
+///
+/// Unbwt::backwards(&mut data, &mut index);
+///
+/// assert_eq!((&data, index), (b"nnbaaa", 3));
+/// ```
+#[macro_export]
+macro_rules! bwt_backward {
+    ($data:expr, $index:expr) => {{
+        let data = &mut $data[..];
+        let index = &mut $index;
+        let n = data.len();
+        assert!(
+            *index < n || *index == 0,
+            "{}:{}: Index is out of bounds",
+            file!(),
+            line!()
+        );
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by_key(|i| data[*i]);
+        let mut row = *index;
+        let original: Vec<_> = (0..n)
+            .map(|_| {
+                row = order[row];
+                data[row]
+            })
+            .collect();
+        data.copy_from_slice(&original);
+        *index -= *index;
+    }};
+}
+
+/// ASCII case toggle.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Toggles the case of every ASCII letter in the byte slice `$bytes`
+/// by flipping the case bit, other bytes are left as they are. A letter
+/// is still a letter after the toggle, so the same bytes are toggled
+/// again in the other direction and this is its own reverse.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, toggle_case};
+/// rfn!(Toggle, (bytes: &mut [u8]), {
+///     toggle_case!(bytes);
+/// });
+///
+/// let mut bytes = *b"Hello, World!";
+///
+/// Toggle::forward(&mut bytes);
+///
+/// assert_eq!(&bytes, b"hELLO, wORLD!");
+///
+/// Toggle::backwards(&mut bytes);
+///
+/// assert_eq!(&bytes, b"Hello, World!");
+/// ```
+#[macro_export]
+macro_rules! toggle_case {
+    ($bytes:expr) => {{
+        let bytes: &mut [u8] = &mut $bytes[..];
+        ::rrust::forward! {{
+            let mut i = 0;
+            ::rrust::rloop!(
+                i == 0,
+                {
+                    ::rrust::rif!(
+                        bytes[i].is_ascii_alphabetic(),
+                        {
+                            bytes[i] ^= 0x20;
+                        },
+                        {},
+                        bytes[i].is_ascii_alphabetic()
+                    );
+                    i += 1;
+                },
+                i == bytes.len()
+            );
+            ::rrust::delocal!(i, bytes.len());
+        }}
+    }};
+}
+
+/// Lookup table remapping.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Maps every value in the slice `$pixels` through the lookup table
+/// `$lut`, like an intensity remapping of an image. The reverse maps
+/// the values through `$inv_lut`. For every value it is asserted that
+/// the two tables are inverses of each other at that value, so a table
+/// that is not a bijection makes it panic.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, apply_lut};
+/// rfn!(Remap, (pixels: &mut [u8], lut: &[u8; 4], inv_lut: &[u8; 4]), {
+///     apply_lut!(pixels, lut, inv_lut);
+/// });
+///
+/// let lut = [0, 2, 3, 1];
+/// let inv_lut = [0, 3, 1, 2];
+/// let mut pixels = [1, 1, 3, 0];
+///
+/// Remap::forward(&mut pixels, &lut, &inv_lut);
+///
+/// assert_eq!(pixels, [2, 2, 1, 0]);
+///
+/// Remap::backwards(&mut pixels, &lut, &inv_lut);
+///
+/// assert_eq!(pixels, [1, 1, 3, 0]);
+/// ```
+#[macro_export]
+macro_rules! apply_lut {
+    ($pixels:expr, $lut:expr, $inv_lut:expr) => {
+        ::rrust::_apply_lut!($pixels, $lut, $inv_lut)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_apply_lut {
+    ($pixels:expr, $lut:expr, $inv_lut:expr) => {
+        ::rrust::_apply_lut!($pixels, $inv_lut, $lut)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _apply_lut {
+    ($pixels:expr, $lut:expr, $inv_lut:expr) => {{
+        let pixels = &mut $pixels[..];
+        let lut = &$lut[..];
+        let inv_lut = &$inv_lut[..];
+        ::rrust::forward! {{
+            let mut i = 0;
+            ::rrust::rloop!(
+                i == 0,
+                {
+                    ::rrust::_lut_map!(pixels[i], lut, inv_lut);
+                    i += 1;
+                },
+                i == pixels.len()
+            );
+            ::rrust::delocal!(i, pixels.len());
+        }}
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _lut_map {
+    ($p:expr, $lut:expr, $inv_lut:expr) => {{
+        let mapped = $lut[$p as usize];
+        assert!(
+            $inv_lut[mapped as usize] == $p,
+            "{}:{}: Lookup tables are not inverses of each other",
+            file!(),
+            line!()
+        );
+        $p = mapped;
+    }};
+}
+
+/// Syndrome of a linear code.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// XORs the syndrome `H·x` over GF(2) of the word `$x` into the slice
+/// `$syndrome`, where `$h` is the parity-check matrix given as a slice
+/// of rows. The values are bits as `0` and `1` or `bool`s. If
+/// `$syndrome` is zero beforehand it holds the syndrome afterwards, and
+/// as `$x` is kept, running it again zeroes the syndrome, so this is
+/// its own reverse.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, syndrome};
+/// rfn!(Syndrome, (s: &mut [u8; 2], h: &[[u8; 3]; 2], x: &[u8; 3]), {
+///     syndrome!(*s, *h, *x);
+/// });
+///
+/// let h = [[1, 1, 0], [0, 1, 1]];
+/// let mut s = [0; 2];
+///
+/// Syndrome::forward(&mut s, &h, &[1, 0, 0]);
+///
+/// assert_eq!(s, [1, 0]);
+///
+/// Syndrome::backwards(&mut s, &h, &[1, 0, 0]);
+///
+/// assert_eq!(s, [0, 0]);
+/// ```
+#[macro_export]
+macro_rules! syndrome {
+    ($syndrome:expr, $h:expr, $x:expr) => {{
+        let syndrome = &mut $syndrome[..];
+        let h = &$h[..];
+        let x = &$x[..];
+        assert!(
+            h.len() == syndrome.len() && h.iter().all(|row| row.len() == x.len()),
+            "{}:{}: Size of the matrix does not match the word and the syndrome",
+            file!(),
+            line!()
+        );
+        ::rrust::forward! {{
+            ::rrust::rfor!(r in 0..syndrome.len(), {
+                ::rrust::rfor!(c in 0..x.len(), {
+                    syndrome[r] ^= h[r][c] & x[c];
+                });
+            });
+        }}
+    }};
+}
+
+/// Arithmetic coding interval update.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Narrows the interval starting at `$low` with the width `$range` to
+/// the part of it belonging to the symbol `$sym`, where `$freqs` holds
+/// the frequency of every symbol. The interval is split into equally
+/// wide steps, one per unit of the total frequency, and the symbol gets
+/// the steps after those of the symbols before it. To keep it exact
+/// `$range` has to be divisible by the total frequency and the symbol
+/// has to have a frequency which is not zero, this is asserted. The
+/// reverse of [`arith_encode_step`](crate::arith_encode_step) is
+/// [`arith_decode_step`](crate::arith_decode_step), which widens the
+/// interval again given the same symbol.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, arith_encode_step};
+/// rfn!(Encode, (low: &mut u64, range: &mut u64, sym: &usize), {
+///     arith_encode_step!(*low, *range, *sym, [1, 2, 1]);
+/// });
+///
+/// let mut low = 0;
+/// let mut range = 64;
+///
+/// Encode::forward(&mut low, &mut range, &1);
+///
+/// assert_eq!((low, range), (16, 32));
+///
+/// Encode::backwards(&mut low, &mut range, &1);
+///
+/// assert_eq!((low, range), (0, 64));
+/// ```
+#[macro_export]
+macro_rules! arith_encode_step {
+    ($low:expr, $range:expr, $sym:expr, $freqs:expr) => {{
+        ::rrust::_alias_check!($low, $range);
+        let (start, freq, total) = ::rrust::_arith_symbol!($freqs, $sym);
+        assert!(
+            $range % total == freq - freq,
+            "{}:{}: Range is not divisible by the total frequency",
+            file!(),
+            line!()
+        );
+        let step = $range / total;
+        $low += step * start;
+        $range = step * freq;
+    }};
+}
+
+/// Arithmetic decoding interval update.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Widens the interval starting at `$low` with the width `$range`,
+/// which belongs to the symbol `$sym`, back to the interval it was
+/// narrowed from with the frequencies `$freqs`. For this `$range` has
+/// to be divisible by the frequency of the symbol, this is asserted.
+/// The reverse of [`arith_decode_step`](crate::arith_decode_step) is
+/// [`arith_encode_step`](crate::arith_encode_step).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, arith_decode_step};
+/// rfn!(Decode, (low: &mut u64, range: &mut u64, sym: &usize), {
+///     arith_decode_step!(*low, *range, *sym, [1, 2, 1]);
+/// });
+///
+/// let mut low = 16;
+/// let mut range = 32;
+///
+/// Decode::forward(&mut low, &mut range, &1);
+///
+/// assert_eq!((low, range), (0, 64));
+/// ```
+#[macro_export]
+macro_rules! arith_decode_step {
+    ($low:expr, $range:expr, $sym:expr, $freqs:expr) => {{
+        ::rrust::_alias_check!($low, $range);
+        let (start, freq, total) = ::rrust::_arith_symbol!($freqs, $sym);
+        assert!(
+            $range % freq == freq - freq,
+            "{}:{}: Range is not divisible by the frequency of the symbol",
+            file!(),
+            line!()
+        );
+        let step = $range / freq;
+        $low -= step * start;
+        $range = step * total;
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _arith_symbol {
+    ($freqs:expr, $sym:expr) => {{
+        let freqs = &$freqs[..];
+        let sym: usize = $sym;
+        assert!(
+            sym < freqs.len() && freqs[sym] != freqs[sym] - freqs[sym],
+            "{}:{}: Symbol has no frequency",
+            file!(),
+            line!()
+        );
+        let zero = freqs[sym] - freqs[sym];
+        let start = freqs[..sym].iter().fold(zero, |sum, f| sum + *f);
+        let total = freqs.iter().fold(zero, |sum, f| sum + *f);
+        (start, freqs[sym], total)
+    }};
+}
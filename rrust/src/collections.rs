@@ -0,0 +1,802 @@
+//! Reversible operations on data structures stored in slices.
+
+/// Rotate the head of a ring buffer.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Advances the head index `$head` of the ring buffer `$buf` by `$k`
+/// modulo the length of the buffer, the data itself is not moved. In
+/// reverse the head is moved back by `$k`.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, rotate_ring};
+/// rfn!(Rotate, (buf: &mut [i32], head: &mut usize), {
+///     rotate_ring!(buf, *head, 3);
+/// });
+///
+/// let mut buf = [1, 2, 3, 4, 5];
+/// let mut head = 4;
+///
+/// Rotate::forward(&mut buf, &mut head);
+///
+/// assert_eq!(head, 2);
+///
+/// Rotate::backwards(&mut buf, &mut head);
+///
+/// assert_eq!(head, 4);
+/// ```
+#[macro_export]
+macro_rules! rotate_ring {
+    ($buf:expr, $head:expr, $k:expr) => {
+        ::rrust::add_mod!($head, $k, $buf.len())
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_rotate_ring {
+    ($buf:expr, $head:expr, $k:expr) => {
+        ::rrust::sub_mod!($head, $k, $buf.len())
+    };
+}
+
+/// Reverse a linked list.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Reverses the singly linked list starting at the index `$head` in
+/// place, `$next` is a slice holding the index of the next node for
+/// every node. The end of the list is marked by the length of
+/// `$next`. Only the links are changed, the nodes are not moved.
+/// Reversing a list is its own reverse.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, reverse_links};
+/// rfn!(Reverse, (next: &mut [usize], head: &mut usize), {
+///     reverse_links!(next, *head);
+/// });
+///
+/// let mut next = [1, 2, 3];
+/// let mut head = 0;
+///
+/// Reverse::forward(&mut next, &mut head);
+///
+/// assert_eq!((next, head), ([3, 0, 1], 2));
+///
+/// Reverse::backwards(&mut next, &mut head);
+///
+/// assert_eq!((next, head), ([1, 2, 3], 0));
+/// ```
+#[macro_export]
+macro_rules! reverse_links {
+    ($next:expr, $head:expr) => {{
+        let next = &mut $next[..];
+        let head = &mut $head;
+        let nil = next.len();
+        let mut prev = nil;
+        let mut cur = nil;
+        ::rrust::rswap!(cur, *head);
+        ::rrust::rloop!(
+            prev == nil,
+            {
+                ::rrust::rswap!(next[cur], prev);
+                ::rrust::rswap!(prev, cur);
+            },
+            cur == nil
+        );
+        ::rrust::rswap!(prev, *head);
+        ::rrust::delocal!(prev, nil);
+        ::rrust::delocal!(cur, nil);
+    }};
+}
+
+/// Push onto a stack.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Pushes a copy of `$x` onto the stack stored in the slice `$stack`,
+/// where `$top` is the number of elements on the stack. The slots
+/// above the top have to be zero. It will panic if the stack is full.
+/// The reverse of [`spush`](crate::spush) is [`spop`](crate::spop).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, spush};
+/// rfn!(Push, (stack: &mut [i32], top: &mut usize, x: &i32), {
+///     spush!(stack, *top, *x);
+/// });
+///
+/// let mut stack = [0; 4];
+/// let mut top = 0;
+///
+/// Push::forward(&mut stack, &mut top, &7);
+///
+/// assert_eq!((stack, top), ([7, 0, 0, 0], 1));
+///
+/// Push::backwards(&mut stack, &mut top, &7);
+///
+/// assert_eq!((stack, top), ([0; 4], 0));
+/// ```
+#[macro_export]
+macro_rules! spush {
+    ($stack:expr, $top:expr, $x:expr) => {{
+        let stack = &mut $stack[..];
+        let top = &mut $top;
+        assert!(
+            *top < stack.len(),
+            "{}:{}: Stack overflow",
+            file!(),
+            line!()
+        );
+        assert!(
+            stack[*top] == 0,
+            "{}:{}: Stack slot is not empty",
+            file!(),
+            line!()
+        );
+        stack[*top] += $x;
+        *top += 1;
+    }};
+}
+
+/// Pop from a stack.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Pops the top element of the stack stored in the slice `$stack`,
+/// where `$top` is the number of elements on the stack, and clears its
+/// slot. The element has to be equal to `$x`, this is asserted, so
+/// that it can be pushed back in reverse. It will panic if the stack
+/// is empty. The reverse of [`spop`](crate::spop) is
+/// [`spush`](crate::spush).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, spop};
+/// rfn!(Pop, (stack: &mut [i32], top: &mut usize, x: &i32), {
+///     spop!(stack, *top, *x);
+/// });
+///
+/// let mut stack = [7, 0, 0, 0];
+/// let mut top = 1;
+///
+/// Pop::forward(&mut stack, &mut top, &7);
+///
+/// assert_eq!((stack, top), ([0; 4], 0));
+///
+/// Pop::backwards(&mut stack, &mut top, &7);
+///
+/// assert_eq!((stack, top), ([7, 0, 0, 0], 1));
+/// ```
+#[macro_export]
+macro_rules! spop {
+    ($stack:expr, $top:expr, $x:expr) => {{
+        let stack = &mut $stack[..];
+        let top = &mut $top;
+        assert!(*top > 0, "{}:{}: Stack underflow", file!(), line!());
+        *top -= 1;
+        let x = $x;
+        assert!(
+            stack[*top] == x,
+            "{}:{}: Popped value does not match",
+            file!(),
+            line!()
+        );
+        stack[*top] -= x;
+    }};
+}
+
+/// Sift up in a binary max-heap.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Moves the element at the index `$idx` of the heap stored in the
+/// slice `$heap` up towards the root, swapping it with its parent as
+/// long as the parent is smaller. This is how an element pushed at the
+/// end of a heap is put in place. The elements have to implement
+/// [`Copy`] and [`PartialOrd`].
+///
+/// The index each swap moved the element away from is recorded in
+/// `$path`, which has to be zero beforehand and long enough to hold
+/// every swap. In reverse the swaps in `$path` are replayed backwards,
+/// moving the element down to `$idx` again and clearing `$path`.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, sift_up};
+/// rfn!(SiftUp, (heap: &mut [i32], idx: &usize, path: &mut [usize]), {
+///     sift_up!(*heap, *idx, *path);
+/// });
+///
+/// let mut heap = [9, 5, 8, 1, 3, 4, 10];
+/// let mut path = [0; 3];
+///
+/// SiftUp::forward(&mut heap, &6, &mut path);
+///
+/// assert_eq!(heap, [10, 5, 9, 1, 3, 4, 8]);
+/// assert_eq!(path, [6, 2, 0]);
+///
+/// SiftUp::backwards(&mut heap, &6, &mut path);
+///
+/// assert_eq!(heap, [9, 5, 8, 1, 3, 4, 10]);
+/// assert_eq!(path, [0; 3]);
+/// ```
+#[macro_export]
+macro_rules! sift_up {
+    ($heap:expr, $idx:expr, $path:expr) => {
+        ::rrust::_sift_up!(forward, $heap, $idx, $path)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_sift_up {
+    ($heap:expr, $idx:expr, $path:expr) => {
+        ::rrust::_sift_up!(reverse, $heap, $idx, $path)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _sift_up {
+    ($dir:ident, $heap:expr, $idx:expr, $path:expr) => {{
+        let heap = &mut $heap;
+        let path = &mut $path;
+        // The element is at the index `$idx` moved `n` levels up.
+        let start: usize = $idx + 1;
+        ::rrust::$dir! {{
+            let mut n = 0;
+            ::rrust::rloop!(
+                n == 0,
+                {
+                    let pos = (start >> n) - 1;
+                    ::rrust::rswap!(heap[pos], heap[(pos - 1) / 2]);
+                    path[n] += pos;
+                    ::rrust::delocal!(pos, path[n]);
+                    n += 1;
+                },
+                start >> n == 1 || heap[(start >> n) / 2 - 1] >= heap[(start >> n) - 1]
+            );
+            ::rrust::delocal!(n, path.iter().take_while(|p| **p != 0).count());
+        }}
+    }};
+}
+
+/// Insertion into a sorted array.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Moves `$x` into the sorted prefix of length `$len` of the slice
+/// `$arr`, after any elements equal to it, shifting the greater
+/// elements one slot up and incrementing `$len`. The slot just after
+/// the prefix is moved into `$x`, so usually both are zero. The
+/// position of the inserted element is added to `$pos`, which has to
+/// be zero beforehand. It will panic if the array is full.
+///
+/// In reverse the element at `$pos` is removed again, shifting the
+/// elements after it back down, and `$pos` is cleared. It is asserted
+/// that the element belongs at that position.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, sorted_insert};
+/// rfn!(Insert, (arr: &mut [i32], len: &mut usize, x: &mut i32, pos: &mut usize), {
+///     sorted_insert!(arr, *len, *x, *pos);
+/// });
+///
+/// let mut arr = [1, 4, 9, 0];
+/// let mut len = 3;
+/// let mut x = 5;
+/// let mut pos = 0;
+///
+/// Insert::forward(&mut arr, &mut len, &mut x, &mut pos);
+///
+/// assert_eq!((arr, len, x, pos), ([1, 4, 5, 9], 4, 0, 2));
+///
+/// Insert::backwards(&mut arr, &mut len, &mut x, &mut pos);
+///
+/// assert_eq!((arr, len, x, pos), ([1, 4, 9, 0], 3, 5, 0));
+/// ```
+#[macro_export]
+macro_rules! sorted_insert {
+    ($arr:expr, $len:expr, $x:expr, $pos:expr) => {{
+        let arr = &mut $arr[..];
+        let len = &mut $len;
+        let pos = &mut $pos;
+        assert!(*len < arr.len(), "{}:{}: Array is full", file!(), line!());
+        assert!(*pos == 0, "{}:{}: Position is not zero", file!(), line!());
+        let at = arr[..*len].partition_point(|e| *e <= $x);
+        for k in (at + 1..=*len).rev() {
+            arr.swap(k, k - 1);
+        }
+        core::mem::swap(&mut arr[at], &mut $x);
+        *pos += at;
+        *len += 1;
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_sorted_insert {
+    ($arr:expr, $len:expr, $x:expr, $pos:expr) => {{
+        let arr = &mut $arr[..];
+        let len = &mut $len;
+        let pos = &mut $pos;
+        assert!(*len > 0, "{}:{}: Array is empty", file!(), line!());
+        *len -= 1;
+        let at = *pos;
+        assert!(
+            at <= *len
+                && arr[..at].iter().all(|e| *e <= arr[at])
+                && arr[at + 1..=*len].iter().all(|e| *e > arr[at]),
+            "{}:{}: Element does not belong at the position",
+            file!(),
+            line!()
+        );
+        core::mem::swap(&mut arr[at], &mut $x);
+        for k in at + 1..=*len {
+            arr.swap(k - 1, k);
+        }
+        *pos -= at;
+    }};
+}
+
+/// Moving sum over a ring buffer.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Swaps `$sample` into the slot at `$head` of the ring buffer `$ring`
+/// holding the past samples, updates the running sum `$sum` of the
+/// buffer with the difference, and advances `$head` modulo the length
+/// of the buffer. The evicted oldest sample ends up in `$sample`, so
+/// no information is lost and the moving average is `$sum` divided by
+/// the length of the buffer. In reverse the head is moved back and the
+/// evicted sample is swapped back into the buffer.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, moving_avg_step};
+/// rfn!(Step, (sum: &mut i32, ring: &mut [i32], head: &mut usize, sample: &mut i32), {
+///     moving_avg_step!(*sum, ring, *head, *sample);
+/// });
+///
+/// let mut sum = 6;
+/// let mut ring = [1, 2, 3];
+/// let mut head = 1;
+/// let mut sample = 5;
+///
+/// Step::forward(&mut sum, &mut ring, &mut head, &mut sample);
+///
+/// assert_eq!((sum, ring, head, sample), (9, [1, 5, 3], 2, 2));
+///
+/// Step::backwards(&mut sum, &mut ring, &mut head, &mut sample);
+///
+/// assert_eq!((sum, ring, head, sample), (6, [1, 2, 3], 1, 5));
+/// ```
+#[macro_export]
+macro_rules! moving_avg_step {
+    ($sum:expr, $ring:expr, $head:expr, $sample:expr) => {
+        ::rrust::_moving_avg_step!(forward, $sum, $ring, $head, $sample)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_moving_avg_step {
+    ($sum:expr, $ring:expr, $head:expr, $sample:expr) => {
+        ::rrust::_moving_avg_step!(reverse, $sum, $ring, $head, $sample)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _moving_avg_step {
+    ($dir:ident, $sum:expr, $ring:expr, $head:expr, $sample:expr) => {{
+        let sum = &mut $sum;
+        let ring = &mut $ring[..];
+        let head = &mut $head;
+        let sample = &mut $sample;
+        let len = ring.len();
+        ::rrust::$dir! {{
+            ::rrust::rswap!(ring[*head], *sample);
+            *sum += ring[*head];
+            *sum -= *sample;
+            ::rrust::add_mod!(*head, 1, len);
+        }}
+    }};
+}
+
+/// Step forward in a XOR linked list.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// `$links` is a slice holding the XOR of the indices of the previous
+/// and the next node for every node, where the index `0` marks both
+/// ends of the list so the first slot is not used by a node. Moves
+/// `$cur` to the next node, and `$prev` to the node `$cur` was at.
+/// The reverse of [`xor_list_advance`](crate::xor_list_advance) is
+/// [`xor_list_retreat`](crate::xor_list_retreat).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, xor_list_advance};
+/// rfn!(Advance, (cur: &mut usize, prev: &mut usize, links: &[usize]), {
+///     xor_list_advance!(*cur, *prev, links);
+/// });
+///
+/// // The list 2, 1, 3.
+/// let links = [0, 2 ^ 3, 1, 1];
+/// let mut cur = 2;
+/// let mut prev = 0;
+///
+/// Advance::forward(&mut cur, &mut prev, &links);
+///
+/// assert_eq!((cur, prev), (1, 2));
+///
+/// Advance::backwards(&mut cur, &mut prev, &links);
+///
+/// assert_eq!((cur, prev), (2, 0));
+/// ```
+#[macro_export]
+macro_rules! xor_list_advance {
+    ($cur:expr, $prev:expr, $links:expr) => {{
+        let cur: &mut usize = &mut $cur;
+        let prev: &mut usize = &mut $prev;
+        let links = &$links[..];
+        *prev ^= links[*cur];
+        core::mem::swap(cur, prev);
+    }};
+}
+
+/// Step backwards in a XOR linked list.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// `$links` is a slice holding the XOR of the indices of the previous
+/// and the next node for every node, where the index `0` marks both
+/// ends of the list. Moves `$cur` to the previous node `$prev`, and
+/// `$prev` to the node before that. The reverse of
+/// [`xor_list_retreat`](crate::xor_list_retreat) is
+/// [`xor_list_advance`](crate::xor_list_advance).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, xor_list_retreat};
+/// rfn!(Retreat, (cur: &mut usize, prev: &mut usize, links: &[usize]), {
+///     xor_list_retreat!(*cur, *prev, links);
+/// });
+///
+/// // The list 2, 1, 3.
+/// let links = [0, 2 ^ 3, 1, 1];
+/// let mut cur = 3;
+/// let mut prev = 1;
+///
+/// Retreat::forward(&mut cur, &mut prev, &links);
+///
+/// assert_eq!((cur, prev), (1, 2));
+///
+/// Retreat::backwards(&mut cur, &mut prev, &links);
+///
+/// assert_eq!((cur, prev), (3, 1));
+/// ```
+#[macro_export]
+macro_rules! xor_list_retreat {
+    ($cur:expr, $prev:expr, $links:expr) => {{
+        let cur: &mut usize = &mut $cur;
+        let prev: &mut usize = &mut $prev;
+        let links = &$links[..];
+        core::mem::swap(cur, prev);
+        *prev ^= links[*cur];
+    }};
+}
+
+/// Step of a sliding window maximum.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Streams the value `$new` into a window of the last `$width` values,
+/// whose maximum is kept at the front of the monotonic deque `$deque`
+/// of positions and values, a `VecDeque<(usize, T)>`. The position of
+/// the value is the number of steps recorded in `$history` so far, a
+/// `Vec<Vec<(usize, T)>>`. First the front is removed if it has left
+/// the window, then the values at the back which are not greater than
+/// `$new` are removed, and `$new` is pushed at the back. Every step
+/// records the removed entries in `$history`, so nothing is lost.
+///
+/// In reverse `$new` is popped from the back, which is asserted, and
+/// the entries recorded by the last step are put back where they came
+/// from.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, window_max_step};
+/// # use std::collections::VecDeque;
+/// rfn!(Step, (deque: &mut VecDeque<(usize, i32)>, history: &mut Vec<Vec<(usize, i32)>>, new: &i32), {
+///     window_max_step!(*deque, *history, *new, 2);
+/// });
+///
+/// let mut deque = VecDeque::new();
+/// let mut history = Vec::new();
+///
+/// Step::forward(&mut deque, &mut history, &5);
+/// Step::forward(&mut deque, &mut history, &3);
+///
+/// assert_eq!(deque.front(), Some(&(0, 5)));
+///
+/// Step::forward(&mut deque, &mut history, &1);
+///
+/// assert_eq!(deque.front(), Some(&(1, 3)));
+///
+/// Step::backwards(&mut deque, &mut history, &1);
+///
+/// assert_eq!(deque, [(0, 5), (1, 3)]);
+/// ```
+#[macro_export]
+macro_rules! window_max_step {
+    ($deque:expr, $history:expr, $new:expr, $width:expr) => {{
+        let deque = &mut $deque;
+        let history = &mut $history;
+        let new = $new;
+        let width: usize = $width;
+        let at = history.len();
+        let mut removed = Vec::new();
+        if deque.front().is_some_and(|front| front.0 + width <= at) {
+            removed.extend(deque.pop_front());
+        }
+        while deque.back().is_some_and(|back| back.1 <= new) {
+            removed.extend(deque.pop_back());
+        }
+        deque.push_back((at, new));
+        history.push(removed);
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_window_max_step {
+    ($deque:expr, $history:expr, $new:expr, $width:expr) => {{
+        let deque = &mut $deque;
+        let history = &mut $history;
+        let new = $new;
+        let width: usize = $width;
+        let removed = match history.pop() {
+            Some(removed) => removed,
+            None => panic!("{}:{}: History of the window is empty", file!(), line!()),
+        };
+        let at = history.len();
+        assert!(
+            deque.pop_back() == Some((at, new)),
+            "{}:{}: Value is not the last one in the window",
+            file!(),
+            line!()
+        );
+        // Only the front can have left the window, every other removed
+        // entry came from the back.
+        for entry in removed.into_iter().rev() {
+            if entry.0 + width <= at {
+                deque.push_front(entry);
+            } else {
+                deque.push_back(entry);
+            }
+        }
+    }};
+}
+
+/// Edge relaxation of the Bellman-Ford algorithm.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Relaxes the edge from `$u` to `$v` with the weight `$w`. If the
+/// distance `$dist[$u] + $w` is shorter than `$dist[$v]`, the distance
+/// and the predecessor `$pred[$v]` are replaced by it and `$u`. The
+/// replaced values are pushed onto the `Vec` `$history`, or `None` if
+/// the edge did not relax anything, so that every step can be undone.
+/// An unreached node can have the largest value of the type as its
+/// distance, an edge from it whose distance overflows does not relax
+/// anything.
+///
+/// In reverse the entry of the step is popped from `$history` and the
+/// replaced values are restored. It is asserted that the distance and
+/// the predecessor are those set by the step.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, relax};
+/// const INF: i64 = i64::MAX / 2;
+///
+/// rfn!(Relax, (dist: &mut [i64], pred: &mut [usize], history: &mut Vec<Option<(i64, usize)>>), {
+///     relax!(dist, pred, 0, 1, 4, *history);
+///     relax!(dist, pred, 0, 2, 1, *history);
+///     relax!(dist, pred, 2, 1, 2, *history);
+///     relax!(dist, pred, 1, 2, 5, *history);
+/// });
+///
+/// let mut dist = [0, INF, INF];
+/// let mut pred = [0, usize::MAX, usize::MAX];
+/// let mut history = Vec::new();
+///
+/// Relax::forward(&mut dist, &mut pred, &mut history);
+///
+/// assert_eq!((dist, pred), ([0, 3, 1], [0, 2, 0]));
+///
+/// Relax::backwards(&mut dist, &mut pred, &mut history);
+///
+/// assert_eq!((dist, pred), ([0, INF, INF], [0, usize::MAX, usize::MAX]));
+/// assert!(history.is_empty());
+/// ```
+#[macro_export]
+macro_rules! relax {
+    ($dist:expr, $pred:expr, $u:expr, $v:expr, $w:expr, $history:expr) => {{
+        let dist = &mut $dist[..];
+        let pred = &mut $pred[..];
+        let (u, v) = ($u, $v);
+        match dist[u].checked_add($w) {
+            Some(candidate) if candidate < dist[v] => {
+                let old_dist = core::mem::replace(&mut dist[v], candidate);
+                let old_pred = core::mem::replace(&mut pred[v], u);
+                $history.push(Some((old_dist, old_pred)));
+            }
+            _ => $history.push(None),
+        }
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_relax {
+    ($dist:expr, $pred:expr, $u:expr, $v:expr, $w:expr, $history:expr) => {{
+        let dist = &mut $dist[..];
+        let pred = &mut $pred[..];
+        let (u, v) = ($u, $v);
+        match $history.pop() {
+            Some(Some((old_dist, old_pred))) => {
+                assert!(
+                    dist[u].checked_add($w) == Some(dist[v]) && pred[v] == u,
+                    "{}:{}: Edge was not the one relaxed",
+                    file!(),
+                    line!()
+                );
+                dist[v] = old_dist;
+                pred[v] = old_pred;
+            }
+            Some(None) => {}
+            None => panic!(
+                "{}:{}: History of the relaxations is empty",
+                file!(),
+                line!()
+            ),
+        }
+    }};
+}
+
+/// Point update of a segment tree.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Adds `$delta` to the leaf `$idx` of the sum segment tree stored in
+/// the slice `$tree`, and to every node on the path from it up to the
+/// root. The tree is stored bottom-up, for `n` leaves it has length
+/// `2 * n` with the leaves at `n..2 * n`, the root at `1` and the
+/// children of the node `i` at `2 * i` and `2 * i + 1`. It will panic
+/// if the length is odd or `$idx` is not a leaf. In reverse `$delta`
+/// is subtracted along the same path.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, segtree_update};
+/// rfn!(Update, (tree: &mut [i32; 8], idx: &usize, delta: &i32), {
+///     segtree_update!(*tree, *idx, *delta);
+/// });
+///
+/// // The leaves are [1, 2, 3, 4].
+/// let mut tree = [0, 10, 3, 7, 1, 2, 3, 4];
+///
+/// Update::forward(&mut tree, &2, &5);
+///
+/// assert_eq!(tree, [0, 15, 3, 12, 1, 2, 8, 4]);
+///
+/// Update::backwards(&mut tree, &2, &5);
+///
+/// assert_eq!(tree, [0, 10, 3, 7, 1, 2, 3, 4]);
+/// ```
+#[macro_export]
+macro_rules! segtree_update {
+    ($tree:expr, $idx:expr, $delta:expr) => {
+        ::rrust::_segtree_update!(forward, $tree, $idx, $delta)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_segtree_update {
+    ($tree:expr, $idx:expr, $delta:expr) => {
+        ::rrust::_segtree_update!(reverse, $tree, $idx, $delta)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _segtree_update {
+    ($dir:ident, $tree:expr, $idx:expr, $delta:expr) => {{
+        let tree = &mut $tree[..];
+        let delta = $delta;
+        let leaf = tree.len() / 2 + $idx;
+        assert!(
+            tree.len() % 2 == 0 && leaf < tree.len(),
+            "{}:{}: Index is not a leaf of the segment tree",
+            file!(),
+            line!()
+        );
+        // The node `n` levels above the leaf is `leaf >> n`, the last
+        // one is the root.
+        let levels = (usize::BITS - leaf.leading_zeros()) as usize;
+        ::rrust::$dir! {{
+            ::rrust::rfor!(n in 0..levels, {
+                tree[leaf >> n] += delta;
+            });
+        }}
+    }};
+}
+
+/// Insertion into a counting Bloom filter.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Increments the counters of the counting Bloom filter `$counts` at
+/// the slots given by the hashes `$hashes` of an element, each taken
+/// modulo the number of counters. Unlike setting bits in a plain Bloom
+/// filter this can be undone, an element is in the filter if none of
+/// its counters are zero.
+///
+/// In reverse the counters are decremented again. It will panic if a
+/// counter is already zero, as the element was then never added.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, counting_bloom_add};
+/// rfn!(Add, (counts: &mut [u8; 8], hashes: &[usize; 3]), {
+///     counting_bloom_add!(*counts, *hashes);
+/// });
+///
+/// let mut counts = [0; 8];
+///
+/// Add::forward(&mut counts, &[1, 4, 12]);
+///
+/// assert_eq!(counts, [0, 1, 0, 0, 2, 0, 0, 0]);
+///
+/// Add::backwards(&mut counts, &[1, 4, 12]);
+///
+/// assert_eq!(counts, [0; 8]);
+/// ```
+#[macro_export]
+macro_rules! counting_bloom_add {
+    ($counts:expr, $hashes:expr) => {{
+        let counts = &mut $counts[..];
+        let len = counts.len();
+        for hash in $hashes.iter() {
+            counts[*hash % len] += 1;
+        }
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_counting_bloom_add {
+    ($counts:expr, $hashes:expr) => {{
+        let counts = &mut $counts[..];
+        let len = counts.len();
+        for hash in $hashes.iter().rev() {
+            let slot = &mut counts[*hash % len];
+            assert!(
+                *slot != 0,
+                "{}:{}: Counter of the Bloom filter is already zero",
+                file!(),
+                line!()
+            );
+            *slot -= 1;
+        }
+    }};
+}
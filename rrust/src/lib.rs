@@ -58,6 +58,25 @@
 //! identifier or a dereference of a identifier, but e can be any
 //! expression that does not cause a mutation.
 //!
+//! The lefthand side can also be a range of a slice, for example
+//! `arr[2..5] += e`, which applies the operation to every element in
+//! the range.
+//!
+//! Two or more places can be updated at once with tuples on both sides,
+//! like `(*a, *b) += (dx, dy)`. The places are updated at the same time,
+//! so every righthand side sees the values from before the update, like
+//! in `(*a, *b) += (*b, 1)`. Righthand sides that read each other's
+//! places, like in `(*a, *b) += (*b, *a)`, can not be reversed and are
+//! rejected.
+//!
+//! A `bool` out-parameter can be set to a property with `^=`, like
+//! `*sorted ^= descents == 0`, as long as it starts out as `false`.
+//! Running the function backwards then clears it again.
+//!
+//! Other operators can be used once they are registered with their
+//! inverse by `inverse_ops!`, like `inverse_ops!(*=, /=)` for values
+//! where the division is exact.
+//!
 //! | Operator | Reverse |
 //! |----------|---------|
 //! |  `+=`    |  `-=`   |
@@ -72,6 +91,29 @@
 //! -= a` will always cause `a` to be nullified and thus causing a
 //! loss of information.
 //!
+//! The check is only done when the righthand side is a place, such as
+//! a variable, a dereference, an index or a field. Literals and other
+//! computed values like `MASK | 1` can never be aliases.
+//! Dereferences like `*ptr` are compared by their address without
+//! borrowing them, so this also works for raw pointers in `unsafe`
+//! blocks. Fields behind a smart pointer such as `Box<State>` can be
+//! changed both as `boxed.x` and `(**boxed).x`, the auto-deref resolves
+//! them to the same address so they are also caught as aliases.
+//!
+//! Calls to `mem::swap`, `std::mem::swap` and `core::mem::swap` are
+//! checked in the same way, though `rswap!` is the preferred way to swap
+//! two places.
+//!
+//! For a group of statements in `disjoint! { ... }` the places are
+//! instead checked to be pairwise disjoint once at the top of the group.
+//!
+//! The arguments of a reversible function can not be aliases either,
+//! this is checked by the borrow checker when the function is called.
+//! It also rejects two overlapping mutable slices, like `&mut arr[0..3]`
+//! and `&mut arr[2..5]`. Slices made by unsafe code are not seen by the
+//! borrow checker, so arguments of the type `&mut [T]` are also checked
+//! not to overlap when the function is entered.
+//!
 //! ## Function and method calls
 //!
 //! At the given time no non-reversible Rust functions or methods are
@@ -79,6 +121,44 @@
 //! something that can be changed since non-mutating functions and
 //! methods could be allowed here.
 //!
+//! A reversible function can call itself recursively, but a call of
+//! its own `forward` outside of any `rif!` or loop never terminates and
+//! is rejected at compile time.
+//!
+//! ## Macros
+//!
+//! Macros which are not part of this crate are passed through as they
+//! are in both directions. This is fine for pure macros like
+//! `assert!` and `assert_eq!`, which then check the same condition in
+//! both directions. Macros with side effects like `println!` or `vec!`
+//! are rejected.
+//!
+//! ## Skipping statements
+//!
+//! A statement marked with `#[rrust::skip]` is passed through as it is
+//! in both directions, this can be used for things like debug
+//! printing. It is up to the user to ensure that such a statement
+//! does not break the reversibility of the code.
+//!
+//! # Features
+//!
+//! - `inline`: Mark the `forward` and `backwards` functions generated
+//!   by `rfn!` with `#[inline(always)]`, this can help small reversible
+//!   functions called in tight loops at the cost of code size.
+//! - `unchecked`: Leave out the runtime alias checks of assignment
+//!   operations and `mem::swap` calls in reversible code. It is then up
+//!   to the user to ensure that the two sides are never aliases of each
+//!   other, if they are the code may silently lose information and no
+//!   longer be reversible. The checks done by the other macros, like
+//!   `rswap!`, are kept.
+//! - `budget`: Generate the `forward_with_budget` and
+//!   `backwards_with_budget` functions of `rfn!`, which limit the number
+//!   of calls and loop iterations. Without it the steps are not counted,
+//!   so there is no cost to reversible code that does not use a budget.
+//! - `debug-expand`: Print the code generated for every reversible
+//!   block to stderr while it is compiled, formatted with `prettyplease`
+//!   so it can be read when debugging reversible code.
+//!
 //! # Bibliography
 //! The language as it is now is mostly based upon the
 //! [Janus](https://en.wikipedia.org/wiki/Janus_(time-reversible_computing_programming_language))
@@ -88,6 +168,22 @@
 //! language and its invertible self-interpreter.
 //! [DOI](https://doi.org/10.1145/1244381.1244404)
 
+mod arith;
+mod codec;
+mod collections;
+mod runtime;
+mod slice;
+
+pub use runtime::{
+    backwards_all, forward_all, Deferred, RevRng, Reversible, Stats, TransactionLog, Undo,
+};
+
+#[cfg(feature = "budget")]
+#[doc(hidden)]
+pub use runtime::with_budget;
+#[doc(hidden)]
+pub use runtime::{budget_step, slices_overlap};
+
 /// Create a new reversible function.
 ///
 /// The first parameter will be the name of a unit struct created to
@@ -113,24 +209,453 @@
 ///
 ///assert_eq!(a, 1);
 ///```
+///
+/// The name can be followed by generic parameters, these are placed
+/// on the generated functions rather than on the struct. Type
+/// parameters can have trait bounds, so with bounds like
+/// `T: Copy + core::ops::AddAssign + core::ops::SubAssign` the same
+/// reversible code can work on values of different types. The bounds
+/// can not themselves contain generic arguments.
+///
+/// ```rust
+/// # use rrust::rfn;
+/// rfn!(AddTo<'a, 'b>, (a: &'a mut i64, b: &'b mut i64), { *a += *b; });
+///
+/// let mut a = 1;
+/// let mut b = 2;
+///
+/// AddTo::forward(&mut a, &mut b);
+///
+/// assert_eq!(a, 3);
+///
+/// AddTo::backwards(&mut a, &mut b);
+///
+/// assert_eq!(a, 1);
+/// ```
+///
+/// When the name is preceded by `stats` a `stats` function is
+/// generated as well, it returns the [`Stats`] of the operations found
+/// in the code when the macro was expanded. It can be combined with
+/// the direction described below, like `rfn!(stats forward Name, ...)`.
+///
+/// ```rust
+/// # use rrust::rfn;
+/// rfn!(stats Twice, (a: &mut i64), { *a += 1; *a += 1; });
+///
+/// assert_eq!(Twice::stats().adds, 2);
+/// ```
+///
+/// With the `budget` feature the functions `forward_with_budget` and
+/// `backwards_with_budget` are generated next to `forward` and
+/// `backwards`, they take a budget as their first argument. Every call
+/// of a reversible function and every loop iteration takes a step of
+/// the budget, and when it is used up the function panics instead of
+/// running forever.
+///
+/// ```rust
+/// # use rrust::{rfn, rif};
+/// rfn!(Down, (n: &mut i64), {
+///     rif!(*n == 0, {}, { *n -= 1; Down::forward(n); *n += 1; }, *n == 0);
+/// });
+///
+/// # #[cfg(feature = "budget")]
+/// # {
+/// let mut n = 10;
+/// Down::forward_with_budget(20, &mut n);
+/// assert!(std::panic::catch_unwind(move || Down::forward_with_budget(5, &mut n)).is_err());
+/// # }
+/// ```
+///
+/// The parameters can be followed by a returned value written as
+/// `-> out: T`, where `T` implements [`Default`] and [`PartialEq`].
+/// In the body `out` is a mutable local which starts out as
+/// `T::default()`, and `forward` returns it. `backwards` takes the
+/// returned value as its last argument and consumes it, the reversed
+/// body has to bring it back to `T::default()`, this is asserted.
+///
+/// ```rust
+/// # use rrust::rfn;
+/// rfn!(Take, (stock: &mut u32, n: &u32) -> taken: u32, {
+///     *stock -= *n;
+///     taken += *n;
+/// });
+///
+/// let mut stock = 10;
+///
+/// let taken = Take::forward(&mut stock, &3);
+///
+/// assert_eq!((stock, taken), (7, 3));
+///
+/// Take::backwards(&mut stock, &3, taken);
+///
+/// assert_eq!(stock, 10);
+/// ```
+///
+/// When only one direction is ever used the name can be preceded by
+/// `forward` or `backwards`, then only that function is generated.
+///
+/// ```rust
+/// # use rrust::rfn;
+/// rfn!(forward Inc, (a: &mut i64), { *a += 1; });
+/// rfn!(backwards Dec, (a: &mut i64), { *a += 1; });
+///
+/// let mut a = 1;
+///
+/// Inc::forward(&mut a);
+/// Dec::backwards(&mut a);
+///
+/// assert_eq!(a, 1);
+/// ```
 #[macro_export]
 macro_rules! rfn {
-    ($name:ident, ($($param:ident: $party:ty),* $(,)?), $code:block) => {
+    (@generics $name:ident, $dirs:tt, [$($generics:tt)*], > , ($($param:ident: $party:ty),* $(,)?), $code:block) => {
+        ::rrust::rfn!(@impl $name, $dirs, [$($generics)*], [($($param: $party),*)], $code);
+    };
+    (@generics $name:ident, $dirs:tt, [$($generics:tt)*], > , ($($param:ident: $party:ty),* $(,)?) -> $out:ident: $outty:ty, $code:block) => {
+        ::rrust::rfn!(@impl $name, $dirs, [$($generics)*], [($($param: $party),*) -> $out: $outty], $code);
+    };
+    (@generics $name:ident, $dirs:tt, [$($generics:tt)*], $next:tt $($rest:tt)*) => {
+        ::rrust::rfn!(@generics $name, $dirs, [$($generics)* $next], $($rest)*);
+    };
+    (@impl $name:ident, [$($dir:ident)*], $generics:tt, $signature:tt, $code:block) => {
         struct $name;
 
         impl $name {
-            fn forward($($param:$party),*) {
+            $(
+                ::rrust::rfn!(@fn $dir, $name, $generics, $signature, $code);
+            )*
+        }
+    };
+    (@fn stats, $name:ident, $generics:tt, $signature:tt, $code:block) => {
+        fn stats() -> ::rrust::Stats {
+            ::rrust::stats! {
+                $code
+            }
+        }
+    };
+    (@fn forward, $name:ident, [$($generics:tt)*], [($($param:ident: $party:ty),*)], $code:block) => {
+        ::rrust::_maybe_inline! {
+            fn forward<$($generics)*>($($param:$party),*) {
+                ::rrust::budget_step();
+                ::rrust::_slice_guards!($($param: $party),*);
                 ::rrust::forward! {
+                    $name, $code
+                };
+            }
+        }
+        ::rrust::_budget_fns! {
+            fn forward_with_budget<$($generics)*>(budget: usize, $($param:$party),*) {
+                ::rrust::with_budget(budget, || Self::forward($($param),*))
+            }
+        }
+    };
+    (@fn forward, $name:ident, [$($generics:tt)*], [($($param:ident: $party:ty),*) -> $out:ident: $outty:ty], $code:block) => {
+        ::rrust::_maybe_inline! {
+            fn forward<$($generics)*>($($param:$party),*) -> $outty {
+                ::rrust::budget_step();
+                ::rrust::_slice_guards!($($param: $party),*);
+                let mut $out = <$outty>::default();
+                ::rrust::forward! {
+                    $name, $code
+                };
+                $out
+            }
+        }
+        ::rrust::_budget_fns! {
+            fn forward_with_budget<$($generics)*>(budget: usize, $($param:$party),*) -> $outty {
+                ::rrust::with_budget(budget, || Self::forward($($param),*))
+            }
+        }
+    };
+    (@fn backwards, $name:ident, [$($generics:tt)*], [($($param:ident: $party:ty),*)], $code:block) => {
+        ::rrust::_maybe_inline! {
+            fn backwards<$($generics)*>($($param:$party),*) {
+                ::rrust::budget_step();
+                ::rrust::_slice_guards!($($param: $party),*);
+                ::rrust::reverse! {
                     $code
                 };
             }
-            fn backwards($($param:$party),*) {
+        }
+        ::rrust::_budget_fns! {
+            fn backwards_with_budget<$($generics)*>(budget: usize, $($param:$party),*) {
+                ::rrust::with_budget(budget, || Self::backwards($($param),*))
+            }
+        }
+    };
+    (@fn backwards, $name:ident, [$($generics:tt)*], [($($param:ident: $party:ty),*) -> $out:ident: $outty:ty], $code:block) => {
+        ::rrust::_maybe_inline! {
+            fn backwards<$($generics)*>($($param:$party,)* $out: $outty) {
+                ::rrust::budget_step();
+                ::rrust::_slice_guards!($($param: $party),*);
+                let mut $out = $out;
                 ::rrust::reverse! {
                     $code
                 };
+                assert!(
+                    $out == <$outty>::default(),
+                    "{}:{}: Returned value is not uncomputed",
+                    file!(),
+                    line!()
+                );
             }
         }
-    }
+        ::rrust::_budget_fns! {
+            fn backwards_with_budget<$($generics)*>(budget: usize, $($param:$party,)* $out: $outty) {
+                ::rrust::with_budget(budget, || Self::backwards($($param,)* $out))
+            }
+        }
+    };
+    (@dirs $dirs:tt, $name:ident < $($rest:tt)*) => {
+        ::rrust::rfn!(@generics $name, $dirs, [], $($rest)*);
+    };
+    (@dirs $dirs:tt, $name:ident, ($($param:ident: $party:ty),* $(,)?), $code:block) => {
+        ::rrust::rfn!(@impl $name, $dirs, [], [($($param: $party),*)], $code);
+    };
+    (@dirs $dirs:tt, $name:ident, ($($param:ident: $party:ty),* $(,)?) -> $out:ident: $outty:ty, $code:block) => {
+        ::rrust::rfn!(@impl $name, $dirs, [], [($($param: $party),*) -> $out: $outty], $code);
+    };
+    (stats forward $name:ident $($rest:tt)*) => {
+        ::rrust::rfn!(@dirs [forward stats], $name $($rest)*);
+    };
+    (stats backwards $name:ident $($rest:tt)*) => {
+        ::rrust::rfn!(@dirs [backwards stats], $name $($rest)*);
+    };
+    (stats $name:ident $($rest:tt)*) => {
+        ::rrust::rfn!(@dirs [forward backwards stats], $name $($rest)*);
+    };
+    (forward $name:ident $($rest:tt)*) => {
+        ::rrust::rfn!(@dirs [forward], $name $($rest)*);
+    };
+    (backwards $name:ident $($rest:tt)*) => {
+        ::rrust::rfn!(@dirs [backwards], $name $($rest)*);
+    };
+    ($name:ident $($rest:tt)*) => {
+        ::rrust::rfn!(@dirs [forward backwards], $name $($rest)*);
+    };
+}
+
+/// Async reversible function.
+///
+/// Like [`rfn`], but the generated `forward` and `backwards` functions
+/// are `async`, so the body can `.await` futures. Awaiting is done the
+/// same way in both directions, so the awaited futures have to be pure,
+/// only calls of other reversible functions like
+/// `Step::forward(x).await` are reversed.
+///
+/// # Example
+/// ```rust
+/// # use rrust::rfn_async;
+/// async fn fetch() -> i32 {
+///     2
+/// }
+///
+/// rfn_async!(AddFetched, (x: &mut i32), {
+///     *x += fetch().await;
+/// });
+///
+/// # fn block_on<F: core::future::Future>(future: F) -> F::Output {
+/// #     let mut future = core::pin::pin!(future);
+/// #     let mut cx = core::task::Context::from_waker(core::task::Waker::noop());
+/// #     loop {
+/// #         if let core::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+/// #             return output;
+/// #         }
+/// #     }
+/// # }
+/// let mut x = 1;
+///
+/// block_on(AddFetched::forward(&mut x));
+///
+/// assert_eq!(x, 3);
+///
+/// block_on(AddFetched::backwards(&mut x));
+///
+/// assert_eq!(x, 1);
+/// ```
+#[macro_export]
+macro_rules! rfn_async {
+    ($name:ident, ($($param:ident: $party:ty),* $(,)?), $code:block) => {
+        struct $name;
+
+        impl $name {
+            async fn forward($($param:$party),*) {
+                ::rrust::budget_step();
+                ::rrust::_slice_guards!($($param: $party),*);
+                ::rrust::forward! {
+                    $name, $code
+                };
+            }
+
+            async fn backwards($($param:$party),*) {
+                ::rrust::budget_step();
+                ::rrust::_slice_guards!($($param: $party),*);
+                ::rrust::reverse! {
+                    $code
+                };
+            }
+        }
+    };
+}
+
+#[cfg(feature = "budget")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _budget_fns {
+    ($($fn:tt)*) => {
+        $($fn)*
+    };
+}
+
+#[cfg(not(feature = "budget"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _budget_fns {
+    ($($fn:tt)*) => {};
+}
+
+/// Run a block of reversible code.
+///
+/// The block is run forwards immediately and an [`Undo`] handle is
+/// returned, calling [`Undo::undo`] runs the block in reverse. This
+/// is useful for one-off reversible computations where defining a
+/// function with [`rfn`] is too heavyweight.
+///
+/// The same limitations as in [`rfn`] apply to the block.
+///
+/// # Example
+/// ```rust
+/// # use rrust::reversible;
+/// let mut a = 1;
+/// let mut b = 2;
+///
+/// let undo = reversible! {
+///     a += 1;
+///     b += a;
+/// };
+/// undo.undo();
+///
+/// assert_eq!(a, 1);
+/// assert_eq!(b, 2);
+/// ```
+#[macro_export]
+macro_rules! reversible {
+    ($($code:tt)*) => {{
+        ::rrust::forward! {{
+            $($code)*
+        }};
+        ::rrust::Undo::new(|| {
+            ::rrust::reverse! {{
+                $($code)*
+            }};
+        })
+    }};
+}
+
+/// Capture a block of reversible code for later.
+///
+/// Takes a closure without arguments whose body is reversible code and
+/// returns a [`Deferred`], which runs the body forwards with
+/// [`Deferred::forward`] and in reverse with [`Deferred::backwards`].
+/// Unlike [`reversible`] nothing is run right away, so this can be
+/// used to build up a list of actions to undo at runtime. Both
+/// directions share the captured environment, and the closure can be
+/// marked with `move`.
+///
+/// The same limitations as in [`rfn`] apply to the body.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{backwards_all, forward_all, reversible_closure, Reversible};
+/// let mut a = 1;
+/// let mut b = 2;
+///
+/// let mut actions: Vec<Box<dyn Reversible<()>>> = vec![
+///     Box::new(reversible_closure!(|| { a += 1; })),
+///     Box::new(reversible_closure!(|| { b ^= 7; })),
+/// ];
+///
+/// forward_all(&mut actions, &mut ());
+/// backwards_all(&mut actions, &mut ());
+/// drop(actions);
+///
+/// assert_eq!((a, b), (1, 2));
+/// ```
+#[macro_export]
+macro_rules! reversible_closure {
+    ($($move:ident)? || { $($code:tt)* }) => {
+        ::rrust::Deferred::new($($move)? |forwards: bool| {
+            if forwards {
+                ::rrust::forward! {{
+                    $($code)*
+                }};
+            } else {
+                ::rrust::reverse! {{
+                    $($code)*
+                }};
+            }
+        })
+    };
+}
+
+/// Run a sequence of reversible steps.
+///
+/// This should only be used inside of functions defined with [`rfn`].
+///
+/// Runs the steps in the slice `$steps` forwards on the place `$state`
+/// from the first to the last, every step implements [`Reversible`].
+/// In reverse the steps are run backwards from the last to the first.
+/// This is useful when the steps are only known at runtime.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, reverse_order};
+/// fn inc(x: &mut i32) {
+///     *x += 1;
+/// }
+///
+/// fn dec(x: &mut i32) {
+///     *x -= 1;
+/// }
+///
+/// fn double(x: &mut i32) {
+///     *x *= 2;
+/// }
+///
+/// fn halve(x: &mut i32) {
+///     *x /= 2;
+/// }
+///
+/// type Step = (fn(&mut i32), fn(&mut i32));
+///
+/// rfn!(Pipeline, (steps: &mut [Step], x: &mut i32), {
+///     reverse_order!(steps, *x);
+/// });
+///
+/// let mut steps: [Step; 2] = [(inc, dec), (double, halve)];
+/// let mut x = 3;
+///
+/// Pipeline::forward(&mut steps, &mut x);
+///
+/// assert_eq!(x, 8);
+///
+/// Pipeline::backwards(&mut steps, &mut x);
+///
+/// assert_eq!(x, 3);
+/// ```
+#[macro_export]
+macro_rules! reverse_order {
+    ($steps:expr, $state:expr) => {
+        ::rrust::forward_all(&mut $steps[..], &mut $state)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_reverse_order {
+    ($steps:expr, $state:expr) => {
+        ::rrust::backwards_all(&mut $steps[..], &mut $state)
+    };
 }
 
 /// A reversible if construct.
@@ -269,6 +794,138 @@ macro_rules! _reverse_rif {
     };
 }
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _outer_locals {
+    ($($local:ident),*) => {};
+}
+
+/// Reversible match.
+///
+/// This should only be used inside of functions defined with [`rfn`].
+///
+/// Every arm is written as `$pat => $body => $post`. The arm whose
+/// pattern `$pat` matches `$value` is run, and afterwards `$value` has
+/// to match its postcondition `$post`. Like [`rif`] it is reversed by
+/// swapping the patterns and the postconditions, so in reverse the
+/// arm is chosen by the postcondition and the reversed body is run.
+///
+/// The patterns can bind the inner values of `$value`, such as the
+/// payload of an enum variant, which are then borrowed by the body. In
+/// reverse the same bindings are rebuilt by the postcondition, so it
+/// has to bind the names used by the body as well.
+///
+/// As the arm has to be found in both directions, exactly one pattern
+/// has to match before and exactly one postcondition afterwards, this
+/// is asserted.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, rmatch};
+/// enum Shape {
+///     Square(i64),
+///     Rect(i64, i64),
+/// }
+///
+/// rfn!(Grow, (shape: &mut Shape, area: &mut i64), {
+///     rmatch!(*shape,
+///         Shape::Square(s) => { *s += 1; *area += *s * *s; } => Shape::Square(s),
+///         Shape::Rect(w, h) => { *w += 1; *area += *w * *h; } => Shape::Rect(w, h),
+///     );
+/// });
+///
+/// let mut shape = Shape::Rect(2, 3);
+/// let mut area = 0;
+///
+/// Grow::forward(&mut shape, &mut area);
+///
+/// assert_eq!(area, 9);
+///
+/// Grow::backwards(&mut shape, &mut area);
+///
+/// assert!(matches!(shape, Shape::Rect(2, 3)));
+/// assert_eq!(area, 0);
+/// ```
+#[macro_export]
+macro_rules! rmatch {
+    ($value:expr, $($pat:pat => $body:block => $post:pat),+ $(,)?) => {
+        assert!(
+            ::rrust::_rmatch_count!($value, $($pat),+) == 1,
+            "{}:{}: Patterns of rmatch! are not exclusive",
+            file!(),
+            line!()
+        );
+        match &mut $value {
+            $($pat => {
+                ::rrust::forward! {
+                    $body
+                };
+                assert!(
+                    ::rrust::_rmatch_count!($value, $post) == 1,
+                    "{}:{}: Postcondition of the arm does not hold",
+                    file!(),
+                    line!()
+                );
+            })+
+        }
+        assert!(
+            ::rrust::_rmatch_count!($value, $($post),+) == 1,
+            "{}:{}: Postconditions of rmatch! are not exclusive",
+            file!(),
+            line!()
+        );
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_rmatch {
+    ($value:expr, $($pat:pat => $body:block => $post:pat),+ $(,)?) => {
+        assert!(
+            ::rrust::_rmatch_count!($value, $($post),+) == 1,
+            "{}:{}: Postconditions of rmatch! are not exclusive",
+            file!(),
+            line!()
+        );
+        #[allow(unreachable_patterns)]
+        match &mut $value {
+            $($post => {
+                ::rrust::reverse! {
+                    $body
+                };
+                assert!(
+                    ::rrust::_rmatch_count!($value, $pat) == 1,
+                    "{}:{}: Pattern of the arm does not hold",
+                    file!(),
+                    line!()
+                );
+            })+
+            _ => unreachable!(),
+        }
+        assert!(
+            ::rrust::_rmatch_count!($value, $($pat),+) == 1,
+            "{}:{}: Patterns of rmatch! are not exclusive",
+            file!(),
+            line!()
+        );
+    };
+}
+
+/// The number of the patterns matching the value.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _rmatch_count {
+    ($value:expr, $($pat:pat),+) => {
+        0usize $(+ {
+            #[allow(unused_variables, unreachable_patterns)]
+            match &$value {
+                $pat => 1,
+                _ => 0,
+            }
+        })+
+    };
+}
+
 /// Reversible loop construct.
 ///
 /// This should only be used inside of functions defined with [`rfn`].
@@ -311,6 +968,34 @@ macro_rules! _reverse_rif {
 /// So we can see it as `$from` may only be true when entering, and
 /// then the loop will run until `$until` evaluates to true.
 ///
+/// An invariant of the loop, like the sum of squares of a vector that
+/// is rotated, can be given last as `preserves: $norm`. It is then
+/// evaluated before and after every iteration in both directions, and
+/// the loop panics if it changed.
+///
+/// ```rust
+/// # use rrust::{rfn, rloop, rswap};
+/// rfn!(Rotate, (v: &mut [i64; 3], n: &mut i64), {
+///     rloop!(
+///         *n == 0,
+///         {
+///             rswap!(v[0], v[1]);
+///             rswap!(v[1], v[2]);
+///             *n += 1;
+///         },
+///         *n == 4,
+///         preserves: v.iter().map(|x| x * x).sum::<i64>()
+///     );
+/// });
+///
+/// let mut v = [1, -2, 3];
+/// let mut n = 0;
+///
+/// Rotate::forward(&mut v, &mut n);
+///
+/// assert_eq!((v, n), ([-2, 3, 1], 4));
+/// ```
+///
 /// # Example
 /// ```rust
 /// # use rrust::{rfn, rloop, delocal};
@@ -345,12 +1030,43 @@ macro_rules! _reverse_rif {
 /// [DOI](https://doi.org/10.1145/1244381.1244404)
 #[macro_export]
 macro_rules! rloop {
+    ($from:expr, $do:block, $loop:block, $until:expr, preserves: $norm:expr) => {
+        assert!($from);
+        ::rrust::forward! {
+            $do
+        };
+        while !$until {
+            ::rrust::budget_step();
+            let norm = $norm;
+            ::rrust::forward! {
+                $loop
+            };
+            assert!(!$from);
+            ::rrust::forward! {
+                $do
+            };
+            ::rrust::_assert_preserved!(norm, $norm);
+        }
+    };
+    ($from:expr, $loop:block, $until:expr, preserves: $norm:expr) => {
+        assert!($from);
+        while !$until {
+            ::rrust::budget_step();
+            let norm = $norm;
+            ::rrust::forward! {
+                $loop
+            };
+            assert!(!$from);
+            ::rrust::_assert_preserved!(norm, $norm);
+        }
+    };
     ($from:expr, $do:block, $loop:block, $until:expr) => {
         assert!($from);
         ::rrust::forward! {
             $do
         };
         while !$until {
+            ::rrust::budget_step();
             ::rrust::forward! {
                 $loop
             };
@@ -363,6 +1079,7 @@ macro_rules! rloop {
     ($from:expr, $loop:block, $until:expr) => {
         assert!($from);
         while !$until {
+            ::rrust::budget_step();
             ::rrust::forward! {
                 $loop
             };
@@ -374,12 +1091,43 @@ macro_rules! rloop {
 #[doc(hidden)]
 #[macro_export]
 macro_rules! _reverse_rloop {
+    ($from:expr, $do:block, $loop:block, $until:expr, preserves: $norm:expr) => {
+        assert!($until);
+        ::rrust::reverse! {
+            $do;
+        };
+        while !$from {
+            ::rrust::budget_step();
+            let norm = $norm;
+            ::rrust::reverse! {
+                $loop;
+            };
+            assert!(!$until);
+            ::rrust::reverse! {
+                $do;
+            };
+            ::rrust::_assert_preserved!(norm, $norm);
+        }
+    };
+    ($from:expr, $loop:block, $until:expr, preserves: $norm:expr) => {
+        assert!($until);
+        while !$from {
+            ::rrust::budget_step();
+            let norm = $norm;
+            ::rrust::reverse! {
+                $loop
+            };
+            assert!(!$until);
+            ::rrust::_assert_preserved!(norm, $norm);
+        }
+    };
     ($from:expr, $do:block, $loop:block, $until:expr) => {
         assert!($until);
         ::rrust::reverse! {
             $do;
         };
         while !$from {
+            ::rrust::budget_step();
             ::rrust::reverse! {
                 $loop;
             };
@@ -392,6 +1140,7 @@ macro_rules! _reverse_rloop {
     ($from:expr, $loop:block, $until:expr) => {
         assert!($until);
         while !$from {
+            ::rrust::budget_step();
             ::rrust::reverse! {
                 $loop
             };
@@ -401,7 +1150,180 @@ macro_rules! _reverse_rloop {
 }
 
 #[doc(hidden)]
-pub use rrust_macro::{forward, reverse};
+#[macro_export]
+macro_rules! _assert_preserved {
+    ($before:expr, $after:expr) => {
+        assert!(
+            $before == $after,
+            "{}:{}: Loop iteration does not preserve the norm",
+            file!(),
+            line!()
+        );
+    };
+}
+
+/// Reversible counted loop.
+///
+/// This should only be used inside of functions defined with [`rfn`].
+///
+/// Runs the body once for every value of `$i` in the range `$range`,
+/// in reverse the reversed body is run for the values in the opposite
+/// order. The counter is introduced and delocaled by the loop itself,
+/// so no `delocal!` is needed for it. The body may read the counter
+/// but it must not change it.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, rfor};
+/// rfn!(Copy, (arr: &mut [i32], payload: &[i32]), {
+///     rfor!(i in 0..arr.len(), {
+///         arr[i] += payload[i];
+///     });
+/// });
+///
+/// let mut arr = [0; 16];
+/// let payload = [42; 16];
+///
+/// Copy::forward(&mut arr, &payload);
+///
+/// assert_eq!(arr, payload);
+///
+/// Copy::backwards(&mut arr, &payload);
+///
+/// assert_eq!(arr, [0; 16]);
+/// ```
+#[macro_export]
+macro_rules! rfor {
+    ($i:ident in $range:expr, $body:block) => {
+        ::rrust::_rfor!(forward, $i, $range, $body)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_rfor {
+    ($i:ident in $range:expr, $body:block) => {
+        ::rrust::_rfor!(reverse, $i, $range, $body)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _rfor {
+    ($dir:ident, $i:ident, $range:expr, $body:block) => {{
+        let range: core::ops::Range<_> = $range;
+        let (start, end) = (range.start, range.end);
+        assert!(
+            start <= end,
+            "{}:{}: Range of rfor! is decreasing",
+            file!(),
+            line!()
+        );
+        ::rrust::$dir! {{
+            let mut $i = start;
+            ::rrust::rloop!(
+                $i == start,
+                {
+                    $body
+                    $i += 1;
+                },
+                $i == end
+            );
+            ::rrust::delocal!($i, end);
+        }}
+    }};
+}
+
+/// Scratch local.
+///
+/// This should only be used inside of functions defined with [`rfn`].
+///
+/// Introduces the mutable local `$s` with the value `$init` for the
+/// body and delocals it afterwards, panicking if the body did not
+/// restore it to `$init`. The value is evaluated once and has to be
+/// [`Copy`].
+///
+/// This is useful when the righthand side of an operation is computed
+/// by another reversible function, the result is computed into the
+/// scratch, used and then uncomputed again by running the function
+/// backwards.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, with_scratch};
+/// rfn!(Square, (s: &mut i32, x: &i32), {
+///     *s += *x * *x;
+/// });
+///
+/// rfn!(AddSquare, (a: &mut i32, x: &i32), {
+///     with_scratch!(s = 0, {
+///         Square::forward(&mut s, x);
+///         *a += s;
+///         Square::backwards(&mut s, x);
+///     });
+/// });
+///
+/// let mut a = 1;
+///
+/// AddSquare::forward(&mut a, &3);
+///
+/// assert_eq!(a, 10);
+///
+/// AddSquare::backwards(&mut a, &3);
+///
+/// assert_eq!(a, 1);
+/// ```
+#[macro_export]
+macro_rules! with_scratch {
+    ($s:ident = $init:expr, $body:block) => {
+        ::rrust::_with_scratch!(forward, $s, $init, $body)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_with_scratch {
+    ($s:ident = $init:expr, $body:block) => {
+        ::rrust::_with_scratch!(reverse, $s, $init, $body)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _with_scratch {
+    ($dir:ident, $s:ident, $init:expr, $body:block) => {{
+        let init = $init;
+        ::rrust::$dir! {{
+            let mut $s = init;
+            $body
+            ::rrust::delocal!($s, init);
+        }}
+    }};
+}
+
+#[doc(hidden)]
+pub use rrust_macro::maybe_inline as _maybe_inline;
+#[doc(hidden)]
+pub use rrust_macro::slice_guards as _slice_guards;
+#[doc(hidden)]
+pub use rrust_macro::{forward, reverse, stats};
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _alias_check {
+    ($left:expr, $right:expr) => {
+        if core::ptr::eq(
+            &($left) as *const _ as *const (),
+            &($right) as *const _ as *const (),
+        ) {
+            panic!(
+                "{}:{}: Lefthand and righthand are aliases of each other",
+                file!(),
+                line!()
+            );
+        }
+    };
+}
 
 /// De-localization
 ///
@@ -410,15 +1332,32 @@ pub use rrust_macro::{forward, reverse};
 /// When ever any local variables are used in reversible code you will
 /// also have to ensure to clean it up. This is done with the
 /// [`delocal`] macro which takes the local identifier and the
-/// expected value at that point and will ensure that they match.
+/// expected value at that point and will ensure that they match. In
+/// reverse the local is initialized with that value, so it can not
+/// refer to the local itself, `delocal!(z, z + 1)` does not compile.
+///
+/// Immutable locals initialized with a literal, like `let i = 42;`,
+/// can never change so they are delocaled automatically where they go
+/// out of scope if it is not done by hand.
+///
+/// A slot of an array used as scratch, like `arr[k]` or `s.buf[k]`, can
+/// be delocaled as well. As it is not bound by a local the value is only
+/// checked, and in reverse the same check is done where the scratch is
+/// taken into use. The slot has to be used in the same block before it
+/// is delocaled, so delocaling it twice without using it in between
+/// does not compile.
 ///
 /// # Example
 /// ```rust
 /// # use rrust::{rfn, delocal};
-/// rfn!(Local, (), {
+/// rfn!(Local, (scratch: &mut [i32]), {
 ///     let mut a = 41;
-///     a += 1;
+///     let step = 1;
+///     a += step;
+///     scratch[0] += a;
 ///     delocal!(a, 42);
+///     scratch[0] -= 42;
+///     delocal!(scratch[0], 0);
 /// });
 /// ```
 #[macro_export]
@@ -429,4 +1368,235 @@ macro_rules! delocal {
         }
         drop($name);
     };
+    ($slot:expr, $e:expr) => {
+        if $slot != $e {
+            panic!("Delocal failed {} != {}", $slot, $e);
+        }
+    };
+}
+
+/// Reversible swap.
+///
+/// This should only be used inside of functions defined with [`rfn`].
+///
+/// Swaps the values of two places, which makes it its own reverse. It
+/// will panic if the two places are aliases of each other.
+///
+/// Indexed places on the form `arr[i]` are swapped by copying the
+/// values, so here the elements have to implement [`Copy`]. This
+/// allows two elements of the same slice to be swapped. When both
+/// indices are integer literals the alias check is done at compile
+/// time instead, so `rswap!(arr[1], arr[1])` does not compile.
+///
+/// Other places are swapped with [`core::mem::swap`] on two separate
+/// mutable borrows, so two disjoint fields of the same tuple or struct,
+/// like `t.0` and `t.1`, can be swapped as well.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, rswap};
+/// rfn!(Swap, (arr: &mut [i32], x: &mut i32, y: &mut i32), {
+///     rswap!(arr[0], arr[2]);
+///     rswap!(*x, *y);
+/// });
+///
+/// let mut arr = [1, 2, 3];
+/// let mut x = 4;
+/// let mut y = 5;
+///
+/// Swap::forward(&mut arr, &mut x, &mut y);
+///
+/// assert_eq!(arr, [3, 2, 1]);
+/// assert_eq!((x, y), (5, 4));
+///
+/// Swap::backwards(&mut arr, &mut x, &mut y);
+///
+/// assert_eq!(arr, [1, 2, 3]);
+/// assert_eq!((x, y), (4, 5));
+/// ```
+#[macro_export]
+macro_rules! rswap {
+    ($a:ident [$i:literal], $b:ident [$j:literal]) => {{
+        const _: () = {
+            #[allow(unused_variables)]
+            let $a = false;
+            #[allow(unused_variables)]
+            let $b = true;
+            assert!(
+                !$a || $i != $j,
+                "Lefthand and righthand are aliases of each other"
+            );
+        };
+        let t = $a[$i];
+        $a[$i] = core::mem::replace(&mut $b[$j], t);
+    }};
+    ($a:ident [$i:expr], $b:ident [$j:expr]) => {{
+        let (i, j) = ($i, $j);
+        ::rrust::_alias_check!($a[i], $b[j]);
+        let t = $a[i];
+        $a[i] = core::mem::replace(&mut $b[j], t);
+    }};
+    ($a:expr, $b:expr) => {{
+        ::rrust::_alias_check!($a, $b);
+        core::mem::swap(&mut $a, &mut $b);
+    }};
+}
+
+/// Place returned by a method.
+///
+/// This should only be used inside of functions defined with [`rfn`].
+///
+/// Marks an expression returning a mutable reference, like a call of
+/// an accessor method, as the place it refers to. This way
+/// `place!(container.at_mut(i)) += e` updates the element, and the
+/// alias check of the assignment is done on the returned reference.
+/// The expression is evaluated again for the check, so it must not
+/// have other side effects.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, place};
+/// struct Grid {
+///     cells: [i32; 4],
+/// }
+///
+/// impl Grid {
+///     fn at_mut(&mut self, x: usize, y: usize) -> &mut i32 {
+///         &mut self.cells[y * 2 + x]
+///     }
+/// }
+///
+/// rfn!(Bump, (grid: &mut Grid, e: &i32), {
+///     place!(grid.at_mut(1, 0)) += *e;
+/// });
+///
+/// let mut grid = Grid { cells: [0; 4] };
+///
+/// Bump::forward(&mut grid, &5);
+///
+/// assert_eq!(grid.cells, [0, 5, 0, 0]);
+///
+/// Bump::backwards(&mut grid, &5);
+///
+/// assert_eq!(grid.cells, [0; 4]);
+/// ```
+#[macro_export]
+macro_rules! place {
+    ($e:expr) => {
+        *$e
+    };
+}
+
+/// Group of disjoint places.
+///
+/// This should only be used inside of functions defined with [`rfn`].
+///
+/// Groups statements whose assignment operations only use places that
+/// are pairwise disjoint. Instead of an alias check for every
+/// operation, it is checked once at the top of the group that no two
+/// of the places are aliases of each other. As this is done before any
+/// of the statements, an index of a place can not depend on a value
+/// changed in the group, this is rejected at compile time. Macros in
+/// the group keep their own checks.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, disjoint};
+/// rfn!(Mix, (arr: &mut [i32], i: &usize, j: &usize), {
+///     disjoint! {
+///         arr[*i] += arr[*j];
+///         arr[*j] ^= arr[*i];
+///         arr[*i] -= 1;
+///     }
+/// });
+///
+/// let mut arr = [1, 2, 3];
+///
+/// Mix::forward(&mut arr, &0, &2);
+///
+/// assert_eq!(arr, [3, 2, 7]);
+///
+/// Mix::backwards(&mut arr, &0, &2);
+///
+/// assert_eq!(arr, [1, 2, 3]);
+/// ```
+#[macro_export]
+macro_rules! disjoint {
+    (@group $body:block) => {
+        $body
+    };
+    ($($body:tt)*) => {
+        compile_error!("disjoint! can only be used inside of functions defined with rfn!")
+    };
+}
+
+/// Registration of inverse operators.
+///
+/// This should only be used inside of functions defined with [`rfn`].
+///
+/// Registers the assignment operators `$op` and `$inverse` as the
+/// inverses of each other, so they can be used in the rest of the
+/// block and the blocks nested in it. This way `*=` can be used for a
+/// type where `/=` undoes it exactly, or the operators of a custom
+/// numeric type can be given their own inverses. A registered operator
+/// takes precedence over the built in inverse.
+///
+/// The pair is not checked, it is up to the user that the operators
+/// are really inverses. Even a pair like `inverse_ops!(+=, +=)` is
+/// accepted, and the reversed code then silently computes the wrong
+/// values. Applying `$inverse` after `$op` with the same righthand
+/// side has to give back the value from before for every value used.
+///
+/// The bodies of macros like [`rif`] and [`rloop`] are reversed on
+/// their own, so the operators have to be registered in them again.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, inverse_ops};
+/// rfn!(Triple, (x: &mut i64), {
+///     inverse_ops!(*=, /=);
+///     *x *= 3;
+///     *x += 1;
+/// });
+///
+/// let mut x = 5;
+///
+/// Triple::forward(&mut x);
+///
+/// assert_eq!(x, 16);
+///
+/// Triple::backwards(&mut x);
+///
+/// assert_eq!(x, 5);
+/// ```
+#[macro_export]
+macro_rules! inverse_ops {
+    ($op:tt, $inverse:tt) => {};
+}
+
+/// Reversible assertion.
+///
+/// This should only be used inside of functions defined with [`rfn`].
+///
+/// Asserts that the condition holds, it takes the same arguments as
+/// [`assert`]. An assertion does not change any state so it is its
+/// own reverse and it is checked in both directions. Functions which
+/// only contain assertions can be used to check invariants and do not
+/// need any mutable parameters.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, rassert};
+/// rfn!(Sorted, (arr: &[i32]), {
+///     rassert!(arr.windows(2).all(|w| w[0] <= w[1]), "not sorted");
+/// });
+///
+/// Sorted::forward(&[1, 2, 3]);
+/// Sorted::backwards(&[1, 2, 3]);
+/// ```
+#[macro_export]
+macro_rules! rassert {
+    ($($arg:tt)*) => {
+        assert!($($arg)*)
+    };
 }
@@ -0,0 +1,377 @@
+//! Runtime support for running reversible code outside of [`rfn`](crate::rfn).
+
+/// Handle to undo a block of reversible code.
+///
+/// This is returned by [`reversible`](crate::reversible) after the
+/// block has been run forwards, calling [`Undo::undo`] runs the block
+/// in reverse.
+#[must_use = "the reversible block can only be undone through this handle"]
+pub struct Undo<F: FnOnce()> {
+    reverse: F,
+}
+
+impl<F: FnOnce()> Undo<F> {
+    #[doc(hidden)]
+    pub fn new(reverse: F) -> Self {
+        Undo { reverse }
+    }
+
+    /// Run the block in reverse, undoing what it did.
+    pub fn undo(self) {
+        (self.reverse)()
+    }
+}
+
+/// A block of reversible code captured by a closure.
+///
+/// This is returned by [`reversible_closure`](crate::reversible_closure),
+/// the block is not run until [`Deferred::forward`] or
+/// [`Deferred::backwards`] is called. It is also a [`Reversible`] step
+/// on the state `()`, so a list of them can be run with
+/// [`forward_all`] and undone with [`backwards_all`].
+pub struct Deferred<F: FnMut(bool)> {
+    run: F,
+}
+
+impl<F: FnMut(bool)> Deferred<F> {
+    #[doc(hidden)]
+    pub fn new(run: F) -> Self {
+        Deferred { run }
+    }
+
+    /// Run the block forwards.
+    pub fn forward(&mut self) {
+        (self.run)(true)
+    }
+
+    /// Run the block in reverse, undoing [`Deferred::forward`].
+    pub fn backwards(&mut self) {
+        (self.run)(false)
+    }
+}
+
+impl<F: FnMut(bool)> Reversible<()> for Deferred<F> {
+    fn forward(&mut self, _: &mut ()) {
+        Deferred::forward(self)
+    }
+
+    fn backwards(&mut self, _: &mut ()) {
+        Deferred::backwards(self)
+    }
+}
+
+/// Counts of the operations in a function defined with
+/// [`rfn`](crate::rfn), they are returned by the `stats` function which
+/// is generated when the name is preceded by `stats`.
+///
+/// The operations are counted in the source when the macro is
+/// expanded, so operations in loops are only counted once and the
+/// operations of called functions are not included. Loops are the
+/// uses of [`rloop`](crate::rloop) and [`rfor`](crate::rfor).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of `+=` operations.
+    pub adds: usize,
+    /// The number of `-=` operations.
+    pub subs: usize,
+    /// The number of `^=` operations.
+    pub xors: usize,
+    /// The number of function and method calls.
+    pub calls: usize,
+    /// The number of loops.
+    pub loops: usize,
+}
+
+/// A step which can be run in both directions on some state.
+///
+/// Running [`Reversible::backwards`] after [`Reversible::forward`]
+/// has to leave the state as it was. A pair of functions or closures
+/// taking the state is a step, where the first element runs forwards
+/// and the second backwards, so the functions generated by
+/// [`rfn`](crate::rfn) can be used as `(Step::forward, Step::backwards)`.
+pub trait Reversible<S: ?Sized> {
+    /// Run the step forwards.
+    fn forward(&mut self, state: &mut S);
+
+    /// Run the step backwards, undoing [`Reversible::forward`].
+    fn backwards(&mut self, state: &mut S);
+}
+
+impl<S: ?Sized, F: FnMut(&mut S), B: FnMut(&mut S)> Reversible<S> for (F, B) {
+    fn forward(&mut self, state: &mut S) {
+        (self.0)(state)
+    }
+
+    fn backwards(&mut self, state: &mut S) {
+        (self.1)(state)
+    }
+}
+
+impl<S: ?Sized, R: Reversible<S> + ?Sized> Reversible<S> for Box<R> {
+    fn forward(&mut self, state: &mut S) {
+        (**self).forward(state)
+    }
+
+    fn backwards(&mut self, state: &mut S) {
+        (**self).backwards(state)
+    }
+}
+
+/// Run the steps forwards, first to last.
+pub fn forward_all<S: ?Sized, R: Reversible<S>>(steps: &mut [R], state: &mut S) {
+    for step in steps.iter_mut() {
+        step.forward(state);
+    }
+}
+
+/// Run the steps backwards, last to first, undoing [`forward_all`].
+pub fn backwards_all<S: ?Sized, R: Reversible<S>>(steps: &mut [R], state: &mut S) {
+    for step in steps.iter_mut().rev() {
+        step.backwards(state);
+    }
+}
+
+/// Log of the reversible steps run on some state.
+///
+/// Every step is run forwards by [`TransactionLog::apply`] and recorded,
+/// so [`TransactionLog::rollback`] can undo them by running them
+/// backwards in the reverse order.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, TransactionLog};
+/// rfn!(Deposit, (balance: &mut i64), { *balance += 100; });
+///
+/// let mut balance = 50;
+/// let mut log = TransactionLog::new();
+///
+/// log.apply((Deposit::forward, Deposit::backwards), &mut balance);
+/// log.apply((Deposit::forward, Deposit::backwards), &mut balance);
+///
+/// assert_eq!(balance, 250);
+///
+/// log.rollback(&mut balance);
+///
+/// assert_eq!(balance, 50);
+/// ```
+pub struct TransactionLog<'a, S: ?Sized> {
+    steps: Vec<Box<dyn Reversible<S> + 'a>>,
+}
+
+impl<'a, S: ?Sized> TransactionLog<'a, S> {
+    /// Create an empty log.
+    pub fn new() -> Self {
+        TransactionLog { steps: Vec::new() }
+    }
+
+    /// Run the step forwards on the state and record it.
+    pub fn apply<R: Reversible<S> + 'a>(&mut self, mut step: R, state: &mut S) {
+        step.forward(state);
+        self.steps.push(Box::new(step));
+    }
+
+    /// Undo all of the recorded steps, last to first, and clear the log.
+    pub fn rollback(&mut self, state: &mut S) {
+        while let Some(mut step) = self.steps.pop() {
+            step.backwards(state);
+        }
+    }
+
+    /// Keep the changes made by the recorded steps and clear the log.
+    pub fn commit(&mut self) {
+        self.steps.clear();
+    }
+
+    /// The number of recorded steps.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Whether no steps are recorded.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+impl<S: ?Sized> Default for TransactionLog<'_, S> {
+    fn default() -> Self {
+        TransactionLog::new()
+    }
+}
+
+/// Random number generator which can be stepped in both directions.
+///
+/// The state is a counter which [`RevRng::next`] advances and
+/// [`RevRng::prev`] moves back, and [`RevRng::value`] is a mix of the
+/// counter in the same way as SplitMix64. In reversible code the
+/// generator is stepped with [`rng_next`](crate::rng_next) and
+/// [`rng_prev`](crate::rng_prev), which are the reverse of each other,
+/// so a value is drawn by stepping the generator and then reading it
+/// with `rng.value()`.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, rng_next, RevRng};
+/// rfn!(Roll, (rng: &mut RevRng, sum: &mut u64), {
+///     rng_next!(*rng);
+///     *sum += rng.value() % 6 + 1;
+/// });
+///
+/// let mut rng = RevRng::new(7);
+/// let mut sum = 0;
+///
+/// Roll::forward(&mut rng, &mut sum);
+/// Roll::forward(&mut rng, &mut sum);
+///
+/// assert!((2..=12).contains(&sum));
+///
+/// Roll::backwards(&mut rng, &mut sum);
+/// Roll::backwards(&mut rng, &mut sum);
+///
+/// assert_eq!((rng, sum), (RevRng::new(7), 0));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RevRng {
+    state: u64,
+}
+
+impl RevRng {
+    const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+    /// Create a generator starting at `seed`.
+    pub fn new(seed: u64) -> Self {
+        RevRng { state: seed }
+    }
+
+    /// Step the generator forwards.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) {
+        self.state = self.state.wrapping_add(Self::GAMMA);
+    }
+
+    /// Step the generator backwards, undoing [`RevRng::next`].
+    pub fn prev(&mut self) {
+        self.state = self.state.wrapping_sub(Self::GAMMA);
+    }
+
+    /// The random value at the current step.
+    pub fn value(&self) -> u64 {
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Step a [`RevRng`] forwards.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Calls [`RevRng::next`] on the place `$rng`, which has to be a
+/// [`RevRng`]. The reverse of [`rng_next`](crate::rng_next) is
+/// [`rng_prev`](crate::rng_prev).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, rng_next, RevRng};
+/// rfn!(Step, (rng: &mut RevRng), {
+///     rng_next!(*rng);
+/// });
+///
+/// let mut rng = RevRng::new(3);
+///
+/// Step::forward(&mut rng);
+///
+/// assert_ne!(rng, RevRng::new(3));
+///
+/// Step::backwards(&mut rng);
+///
+/// assert_eq!(rng, RevRng::new(3));
+/// ```
+#[macro_export]
+macro_rules! rng_next {
+    ($rng:expr) => {
+        ::rrust::RevRng::next(&mut $rng)
+    };
+}
+
+/// Step a [`RevRng`] backwards.
+///
+/// This should only be used inside of functions defined with
+/// [`rfn`](crate::rfn).
+///
+/// Calls [`RevRng::prev`] on the place `$rng`, which has to be a
+/// [`RevRng`]. The reverse of [`rng_prev`](crate::rng_prev) is
+/// [`rng_next`](crate::rng_next).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, rng_prev, RevRng};
+/// rfn!(Step, (rng: &mut RevRng), {
+///     rng_prev!(*rng);
+/// });
+///
+/// let mut rng = RevRng::new(3);
+///
+/// Step::forward(&mut rng);
+/// Step::backwards(&mut rng);
+///
+/// assert_eq!(rng, RevRng::new(3));
+/// ```
+#[macro_export]
+macro_rules! rng_prev {
+    ($rng:expr) => {
+        ::rrust::RevRng::prev(&mut $rng)
+    };
+}
+
+#[cfg(feature = "budget")]
+std::thread_local! {
+    static BUDGET: core::cell::Cell<Option<usize>> = const { core::cell::Cell::new(None) };
+}
+
+/// Restores the previous budget when dropped, also when the budget
+/// runs out and the thread panics.
+#[cfg(feature = "budget")]
+struct BudgetGuard(Option<usize>);
+
+#[cfg(feature = "budget")]
+impl Drop for BudgetGuard {
+    fn drop(&mut self) {
+        BUDGET.with(|budget| budget.set(self.0));
+    }
+}
+
+/// Run `f` with a budget of `budget` steps, where every call of a
+/// function defined with [`rfn`](crate::rfn) and every loop iteration
+/// is a step. Used by the `forward_with_budget` and
+/// `backwards_with_budget` functions generated by [`rfn`](crate::rfn).
+#[cfg(feature = "budget")]
+#[doc(hidden)]
+pub fn with_budget<R>(budget: usize, f: impl FnOnce() -> R) -> R {
+    let _guard = BudgetGuard(BUDGET.with(|b| b.replace(Some(budget))));
+    f()
+}
+
+/// Take a step from the budget if there is one, panics if the budget
+/// is used up. Without the `budget` feature this does nothing.
+#[doc(hidden)]
+#[inline(always)]
+pub fn budget_step() {
+    #[cfg(feature = "budget")]
+    BUDGET.with(|budget| {
+        if let Some(steps) = budget.get() {
+            assert!(steps > 0, "Budget of reversible steps exhausted");
+            budget.set(Some(steps - 1));
+        }
+    })
+}
+
+/// Whether two non-empty regions of memory, given by their address and
+/// size in bytes, overlap. Used by the entry checks of the functions
+/// generated by [`rfn`](crate::rfn).
+#[doc(hidden)]
+pub fn slices_overlap((a, a_len): (usize, usize), (b, b_len): (usize, usize)) -> bool {
+    a_len > 0 && b_len > 0 && a < b + b_len && b < a + a_len
+}
@@ -0,0 +1,535 @@
+//! Reversible permutations of slices.
+
+/// Transpose a square matrix.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Transposes the `$n` times `$n` matrix `$m` stored in row-major
+/// order in a slice, it will panic if the length of the slice is not
+/// `$n * $n`. A transpose is its own reverse.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, transpose};
+/// rfn!(Transpose, (m: &mut [i32]), {
+///     transpose!(m, 2);
+/// });
+///
+/// let mut m = [1, 2, 3, 4];
+///
+/// Transpose::forward(&mut m);
+///
+/// assert_eq!(m, [1, 3, 2, 4]);
+///
+/// Transpose::backwards(&mut m);
+///
+/// assert_eq!(m, [1, 2, 3, 4]);
+/// ```
+#[macro_export]
+macro_rules! transpose {
+    ($m:expr, $n:expr) => {{
+        let m = &mut $m[..];
+        let n: usize = $n;
+        assert_eq!(
+            m.len(),
+            n * n,
+            "{}:{}: Matrix is not square",
+            file!(),
+            line!()
+        );
+        let mut i = 0;
+        ::rrust::rloop!(
+            i == 0,
+            {
+                let mut j = i + 1;
+                ::rrust::rloop!(
+                    j == i + 1,
+                    {
+                        ::rrust::rswap!(m[i * n + j], m[j * n + i]);
+                        j += 1;
+                    },
+                    j == n
+                );
+                ::rrust::delocal!(j, n);
+                i += 1;
+            },
+            i == n
+        );
+        ::rrust::delocal!(i, n);
+    }};
+}
+
+/// Distribution pass of a counting sort.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Moves every element `$in[i]` to the position `$offsets[$keys[i]]`
+/// of `$out` and increments the offset, so elements with the same key
+/// keep their order. `$offsets` has to hold the start of the bucket of
+/// every key beforehand, afterwards it holds the end of every bucket.
+/// The elements are moved by swapping them with the elements of
+/// `$out`, which end up in `$in`. It will panic if `$out`, `$in` and
+/// `$keys` are not of the same length.
+///
+/// In reverse the elements are moved back using the offsets, which
+/// are restored to the start of every bucket.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, counting_distribute};
+/// rfn!(Distribute, (out: &mut [char], input: &mut [char], offsets: &mut [usize], keys: &[usize]), {
+///     counting_distribute!(out, input, offsets, keys);
+/// });
+///
+/// let mut out = [' '; 4];
+/// let mut input = ['a', 'b', 'c', 'd'];
+/// let mut offsets = [0, 2];
+/// let keys = [1, 0, 1, 0];
+///
+/// Distribute::forward(&mut out, &mut input, &mut offsets, &keys);
+///
+/// assert_eq!(out, ['b', 'd', 'a', 'c']);
+/// assert_eq!(offsets, [2, 4]);
+///
+/// Distribute::backwards(&mut out, &mut input, &mut offsets, &keys);
+///
+/// assert_eq!(input, ['a', 'b', 'c', 'd']);
+/// assert_eq!(offsets, [0, 2]);
+/// ```
+#[macro_export]
+macro_rules! counting_distribute {
+    ($out:expr, $in:expr, $offsets:expr, $keys:expr) => {
+        ::rrust::_counting_distribute!(forward, $out, $in, $offsets, $keys)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_counting_distribute {
+    ($out:expr, $in:expr, $offsets:expr, $keys:expr) => {
+        ::rrust::_counting_distribute!(reverse, $out, $in, $offsets, $keys)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _counting_distribute {
+    ($dir:ident, $out:expr, $in:expr, $offsets:expr, $keys:expr) => {{
+        let out = &mut $out[..];
+        let input = &mut $in[..];
+        let offsets = &mut $offsets[..];
+        let keys = &$keys[..];
+        let len = keys.len();
+        assert!(
+            out.len() == len && input.len() == len,
+            "{}:{}: Slices are not of the same length",
+            file!(),
+            line!()
+        );
+        ::rrust::$dir! {{
+            let mut i = 0;
+            ::rrust::rloop!(
+                i == 0,
+                {
+                    let k = keys[i];
+                    ::rrust::rswap!(out[offsets[k]], input[i]);
+                    offsets[k] += 1;
+                    ::rrust::delocal!(k, keys[i]);
+                    i += 1;
+                },
+                i == len
+            );
+            ::rrust::delocal!(i, len);
+        }}
+    }};
+}
+
+/// Bit-reversal permutation.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Swaps every element of `$arr` with the element at the index with
+/// the lowest `$log2n` bits reversed, as is done for an in-place FFT.
+/// It will panic if the length of the slice is not `2^$log2n`. The
+/// permutation is its own reverse.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, bit_reverse_permute};
+/// rfn!(Permute, (arr: &mut [i32]), {
+///     bit_reverse_permute!(arr, 3);
+/// });
+///
+/// let mut arr = [0, 1, 2, 3, 4, 5, 6, 7];
+///
+/// Permute::forward(&mut arr);
+///
+/// assert_eq!(arr, [0, 4, 2, 6, 1, 5, 3, 7]);
+///
+/// Permute::backwards(&mut arr);
+///
+/// assert_eq!(arr, [0, 1, 2, 3, 4, 5, 6, 7]);
+/// ```
+#[macro_export]
+macro_rules! bit_reverse_permute {
+    ($arr:expr, $log2n:expr) => {{
+        let arr = &mut $arr[..];
+        let bits: u32 = $log2n;
+        let n = arr.len();
+        assert!(
+            1usize.checked_shl(bits) == Some(n),
+            "{}:{}: Length is not two to the power of log2n",
+            file!(),
+            line!()
+        );
+        let rev = |i: usize| {
+            i.reverse_bits()
+                .checked_shr(usize::BITS - bits)
+                .unwrap_or(0)
+        };
+        let mut i = 0;
+        ::rrust::rloop!(
+            i == 0,
+            {
+                let j = rev(i);
+                ::rrust::rif!(
+                    i < j,
+                    {
+                        ::rrust::rswap!(arr[i], arr[j]);
+                    },
+                    i < j
+                );
+                ::rrust::delocal!(j, rev(i));
+                i += 1;
+            },
+            i == n
+        );
+        ::rrust::delocal!(i, n);
+    }};
+}
+
+/// Interleaving of two slices.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Moves the elements of the slices `$a` and `$b` into the slice
+/// `$out`, alternating between them, so `$out` holds `a[0], b[0],
+/// a[1], b[1], ...` and `$a` and `$b` are left with default values.
+/// The elements have to implement [`Copy`], [`Default`] and
+/// [`PartialEq`]. `$a` and `$b` have to have the same length and
+/// `$out` twice that, and `$out` has to hold only default values, this
+/// is asserted. The reverse of [`interleave`](crate::interleave) is
+/// [`deinterleave`](crate::deinterleave).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, interleave};
+/// rfn!(Zip, (out: &mut [i32], a: &mut [i32], b: &mut [i32]), {
+///     interleave!(out, a, b);
+/// });
+///
+/// let mut out = [0; 4];
+/// let mut a = [1, 2];
+/// let mut b = [3, 4];
+///
+/// Zip::forward(&mut out, &mut a, &mut b);
+///
+/// assert_eq!((out, a, b), ([1, 3, 2, 4], [0; 2], [0; 2]));
+///
+/// Zip::backwards(&mut out, &mut a, &mut b);
+///
+/// assert_eq!((out, a, b), ([0; 4], [1, 2], [3, 4]));
+/// ```
+#[macro_export]
+macro_rules! interleave {
+    ($out:expr, $a:expr, $b:expr) => {
+        ::rrust::_interleave!($out, $a, $b)
+    };
+}
+
+/// Deinterleaving of a slice.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Moves the elements at the even indices of the slice `$out` into the
+/// slice `$a` and the elements at the odd indices into the slice `$b`,
+/// `$out` is left with default values. The elements have to implement
+/// [`Copy`], [`Default`] and [`PartialEq`]. `$a` and `$b` have to have
+/// the same length and `$out` twice that, and `$a` and `$b` have to
+/// hold only default values, this is asserted. The reverse of
+/// [`deinterleave`](crate::deinterleave) is
+/// [`interleave`](crate::interleave).
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, deinterleave};
+/// rfn!(Unzip, (out: &mut [i32], a: &mut [i32], b: &mut [i32]), {
+///     deinterleave!(out, a, b);
+/// });
+///
+/// let mut out = [1, 3, 2, 4];
+/// let mut a = [0; 2];
+/// let mut b = [0; 2];
+///
+/// Unzip::forward(&mut out, &mut a, &mut b);
+///
+/// assert_eq!((out, a, b), ([0; 4], [1, 2], [3, 4]));
+///
+/// Unzip::backwards(&mut out, &mut a, &mut b);
+///
+/// assert_eq!((out, a, b), ([1, 3, 2, 4], [0; 2], [0; 2]));
+/// ```
+#[macro_export]
+macro_rules! deinterleave {
+    ($out:expr, $a:expr, $b:expr) => {
+        ::rrust::_deinterleave!($out, $a, $b)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _interleave {
+    ($out:expr, $a:expr, $b:expr) => {{
+        let out = &mut $out[..];
+        let a = &mut $a[..];
+        let b = &mut $b[..];
+        ::rrust::_interleave_check!(out, a, b, [out]);
+        ::rrust::_interleave_swaps!(out, a, b);
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _deinterleave {
+    ($out:expr, $a:expr, $b:expr) => {{
+        let out = &mut $out[..];
+        let a = &mut $a[..];
+        let b = &mut $b[..];
+        ::rrust::_interleave_check!(out, a, b, [a, b]);
+        ::rrust::_interleave_swaps!(out, a, b);
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _interleave_check {
+    ($out:ident, $a:ident, $b:ident, [$($into:ident),*]) => {{
+        fn is_default<T: Default + PartialEq>(x: &T) -> bool {
+            *x == T::default()
+        }
+        assert!(
+            $a.len() == $b.len() && $out.len() == 2 * $a.len(),
+            "{}:{}: Lengths of the slices do not match",
+            file!(),
+            line!()
+        );
+        assert!(
+            $($into.iter().all(is_default))&&*,
+            "{}:{}: Slice to move into does not hold default values",
+            file!(),
+            line!()
+        );
+    }};
+}
+
+// The elements are moved by swapping them with the default values on
+// the other side.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _interleave_swaps {
+    ($out:ident, $a:ident, $b:ident) => {
+        ::rrust::rfor!(i in 0..$a.len(), {
+            ::rrust::rswap!($out[2 * i], $a[i]);
+            ::rrust::rswap!($out[2 * i + 1], $b[i]);
+        });
+    };
+}
+
+/// Predict step of a lifting scheme.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// The integer slice `$x` holds the signal with the even samples at
+/// the even indices and the odd samples at the odd indices. Every odd
+/// sample is predicted by the mean of the even samples next to it,
+/// rounded down, and the prediction is subtracted. What is left is the
+/// detail of the signal. At the end of the signal the one even sample
+/// is used twice.
+///
+/// As the step only reads the even samples and changes the odd samples
+/// it is undone exactly, the same prediction is added back in reverse.
+/// Together with [`lifting_update`](crate::lifting_update) this is the
+/// integer LeGall 5/3 wavelet transform.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, lifting_predict};
+/// rfn!(Predict, (x: &mut [i32]), {
+///     lifting_predict!(x);
+/// });
+///
+/// let mut x = [2, 3, 4, 9, 6];
+///
+/// Predict::forward(&mut x);
+///
+/// assert_eq!(x, [2, 0, 4, 4, 6]);
+///
+/// Predict::backwards(&mut x);
+///
+/// assert_eq!(x, [2, 3, 4, 9, 6]);
+/// ```
+#[macro_export]
+macro_rules! lifting_predict {
+    ($x:expr) => {
+        ::rrust::_lifting_predict!(forward, $x)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_lifting_predict {
+    ($x:expr) => {
+        ::rrust::_lifting_predict!(reverse, $x)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _lifting_predict {
+    ($dir:ident, $x:expr) => {{
+        let x = &mut $x[..];
+        let len = x.len();
+        let right = |i: usize| if i + 1 < len { i + 1 } else { i - 1 };
+        ::rrust::$dir! {{
+            ::rrust::rfor!(k in 0..len / 2, {
+                x[2 * k + 1] -= (x[2 * k] + x[right(2 * k + 1)]) >> 1;
+            });
+        }}
+    }};
+}
+
+/// Update step of a lifting scheme.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// The integer slice `$x` holds the signal as for
+/// [`lifting_predict`](crate::lifting_predict), after the odd samples
+/// have been replaced by the detail. Every even sample is updated by
+/// adding a quarter of the details next to it, rounded to the nearest,
+/// which keeps the mean of the even samples close to that of the whole
+/// signal. At the ends of the signal the one detail is used twice.
+///
+/// As the step only reads the odd samples and changes the even samples
+/// it is undone exactly, the same update is subtracted in reverse.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, lifting_predict, lifting_update};
+/// rfn!(Wavelet, (x: &mut [i32]), {
+///     lifting_predict!(x);
+///     lifting_update!(x);
+/// });
+///
+/// let mut x = [2, 3, 4, 9, 6];
+///
+/// Wavelet::forward(&mut x);
+///
+/// assert_eq!(x, [2, 0, 5, 4, 8]);
+///
+/// Wavelet::backwards(&mut x);
+///
+/// assert_eq!(x, [2, 3, 4, 9, 6]);
+/// ```
+#[macro_export]
+macro_rules! lifting_update {
+    ($x:expr) => {
+        ::rrust::_lifting_update!(forward, $x)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_lifting_update {
+    ($x:expr) => {
+        ::rrust::_lifting_update!(reverse, $x)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _lifting_update {
+    ($dir:ident, $x:expr) => {{
+        let x = &mut $x[..];
+        let len = x.len();
+        // A single sample has no detail to update it with.
+        let evens = if len > 1 { len.div_ceil(2) } else { 0 };
+        let left = |i: usize| if i > 0 { i - 1 } else { i + 1 };
+        let right = |i: usize| if i + 1 < len { i + 1 } else { i - 1 };
+        ::rrust::$dir! {{
+            ::rrust::rfor!(k in 0..evens, {
+                x[2 * k] += (x[left(2 * k)] + x[right(2 * k)] + 2) >> 2;
+            });
+        }}
+    }};
+}
+
+/// Permutation of a slice given as cycles.
+///
+/// This should only be used inside of functions defined with [`rfn`](crate::rfn).
+///
+/// Applies the permutation given by the disjoint cycles `$cycles` to
+/// the slice `$arr`, where a cycle `[a, b, c]` moves the element at `a`
+/// to `b`, the element at `b` to `c` and the element at `c` to `a`. The
+/// elements have to implement [`Copy`]. Every cycle is applied by
+/// swapping its first element with the others in turn, so the reverse
+/// does the same swaps in the opposite order, which applies the
+/// inverse cycles.
+///
+/// # Example
+/// ```rust
+/// # use rrust::{rfn, apply_perm};
+/// rfn!(Permute, (arr: &mut [char], cycles: &[&[usize]]), {
+///     apply_perm!(arr, cycles);
+/// });
+///
+/// let cycles: [&[usize]; 2] = [&[0, 2, 3], &[1, 4]];
+/// let mut arr = ['a', 'b', 'c', 'd', 'e'];
+///
+/// Permute::forward(&mut arr, &cycles);
+///
+/// assert_eq!(arr, ['d', 'e', 'a', 'c', 'b']);
+///
+/// Permute::backwards(&mut arr, &cycles);
+///
+/// assert_eq!(arr, ['a', 'b', 'c', 'd', 'e']);
+/// ```
+#[macro_export]
+macro_rules! apply_perm {
+    ($arr:expr, $cycles:expr) => {
+        ::rrust::_apply_perm!(forward, $arr, $cycles)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _reverse_apply_perm {
+    ($arr:expr, $cycles:expr) => {
+        ::rrust::_apply_perm!(reverse, $arr, $cycles)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _apply_perm {
+    ($dir:ident, $arr:expr, $cycles:expr) => {{
+        let arr = &mut $arr[..];
+        let cycles = &$cycles[..];
+        ::rrust::$dir! {{
+            ::rrust::rfor!(c in 0..cycles.len(), {
+                ::rrust::rfor!(j in 1..cycles[c].len().max(1), {
+                    ::rrust::rswap!(arr[cycles[c][0]], arr[cycles[c][j]]);
+                });
+            });
+        }}
+    }};
+}